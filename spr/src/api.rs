@@ -0,0 +1,367 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Sending requests to the GitHub API. Requests that GitHub rejects because
+//! of its rate limits are retried once the limit allows, and idempotent
+//! requests are also retried after transient failures.
+
+use std::{
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{Method, StatusCode, header::HeaderMap};
+
+use crate::{
+    error::{Error, Result},
+    output::output,
+};
+
+/// Number of attempts at sending a request before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry after a transient failure. It doubles with
+/// every further retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest delay between two attempts after a transient failure
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Longest time to wait for a rate limit to reset. If the limit resets later,
+/// the request fails.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+
+#[derive(Clone)]
+pub struct ApiClient {
+    client: reqwest::Client,
+    verbose: bool,
+}
+
+/// Why a request is tried again
+#[derive(Debug, PartialEq, Eq)]
+enum Retry {
+    /// GitHub rejected the request because of a rate limit, so it is safe to
+    /// send it again, whether it is idempotent or not.
+    RateLimited(Duration),
+    /// The request failed in a way that may go away by itself.
+    Transient,
+}
+
+impl ApiClient {
+    /// Create a client sending requests with `client`, which carries the
+    /// authentication headers. In `verbose` mode, the remaining rate limit
+    /// budget is reported after every request.
+    pub fn new(client: reqwest::Client, verbose: bool) -> Self {
+        Self { client, verbose }
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn patch(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.patch(url)
+    }
+
+    pub fn put(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.put(url)
+    }
+
+    /// Send a request that may change something on GitHub. It is only sent
+    /// again if it was rejected because of a rate limit.
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.send_with_retries(request, false).await
+    }
+
+    /// Send a request that can safely be repeated, like any GET request or a
+    /// GraphQL query. It is also sent again after server errors and
+    /// connection failures.
+    pub async fn send_idempotent(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        self.send_with_retries(request, true).await
+    }
+
+    async fn send_with_retries(
+        &self,
+        request: reqwest::RequestBuilder,
+        idempotent: bool,
+    ) -> Result<reqwest::Response> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let idempotent = idempotent || is_idempotent_method(request.method());
+
+        let mut attempt = 1;
+        loop {
+            let this_request = request
+                .try_clone()
+                .ok_or_else(|| Error::new("request body cannot be sent more than once"))?;
+
+            let (retry, error) = match client.execute(this_request).await {
+                Ok(response) => {
+                    self.report_rate_limit(response.headers())?;
+
+                    let status = response.status();
+                    if status.is_success() {
+                        return Ok(response);
+                    }
+
+                    let headers = response.headers().clone();
+                    let text = response.text().await.unwrap_or_default();
+                    (
+                        retry_after(status, &headers, &text, SystemTime::now()),
+                        api_error(status, &text),
+                    )
+                }
+                Err(error) => (
+                    (error.is_connect() || error.is_timeout()).then_some(Retry::Transient),
+                    Error::from(error),
+                ),
+            };
+
+            let delay = match retry {
+                _ if attempt >= MAX_ATTEMPTS => return Err(error),
+                Some(Retry::RateLimited(delay)) if delay > MAX_RATE_LIMIT_WAIT => {
+                    let mut error = error;
+                    error.push(format!(
+                        "GitHub API rate limit exceeded, try again in {} minutes",
+                        delay.as_secs().div_ceil(60)
+                    ));
+                    return Err(error);
+                }
+                Some(Retry::RateLimited(delay)) => {
+                    output(
+                        "⏳",
+                        &format!(
+                            "GitHub API rate limit exceeded, waiting {} seconds",
+                            delay.as_secs_f64().ceil()
+                        ),
+                    )?;
+                    delay
+                }
+                Some(Retry::Transient) if idempotent => {
+                    let delay = backoff(attempt, random_fraction());
+                    if self.verbose {
+                        output(
+                            "🔁",
+                            &format!("{} - retrying in {:.1} seconds", error, delay.as_secs_f64()),
+                        )?;
+                    }
+                    delay
+                }
+                _ => return Err(error),
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn report_rate_limit(&self, headers: &HeaderMap) -> Result<()> {
+        if !self.verbose {
+            return Ok(());
+        }
+
+        if let (Some(remaining), Some(limit)) = (
+            header_value::<u64>(headers, "x-ratelimit-remaining"),
+            header_value::<u64>(headers, "x-ratelimit-limit"),
+        ) {
+            let resource = headers
+                .get("x-ratelimit-resource")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("core");
+            output(
+                "📊",
+                &format!("GitHub API rate limit ({resource}): {remaining} of {limit} remaining"),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Methods that are safe to repeat on GitHub. Unlike what HTTP promises, a
+/// PUT can do something different the second time: merging a Pull Request
+/// fails once it has been merged.
+fn is_idempotent_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn header_value<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Decide whether a failed request should be sent again, following GitHub's
+/// advice on handling rate limits: wait as long as `Retry-After` says, or
+/// until `X-RateLimit-Reset` if no requests remain. A secondary rate limit
+/// without either header calls for waiting at least a minute.
+fn retry_after(
+    status: StatusCode,
+    headers: &HeaderMap,
+    text: &str,
+    now: SystemTime,
+) -> Option<Retry> {
+    if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+        if let Some(seconds) = header_value::<u64>(headers, "retry-after") {
+            return Some(Retry::RateLimited(Duration::from_secs(seconds)));
+        }
+
+        if header_value::<u64>(headers, "x-ratelimit-remaining") == Some(0)
+            && let Some(reset) = header_value::<u64>(headers, "x-ratelimit-reset")
+        {
+            let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            // One second more, as the reset time is rounded down.
+            return Some(Retry::RateLimited(Duration::from_secs(
+                reset.saturating_sub(now) + 1,
+            )));
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS || text.to_lowercase().contains("rate limit") {
+            return Some(Retry::RateLimited(Duration::from_secs(60)));
+        }
+
+        return None;
+    }
+
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+    .then_some(Retry::Transient)
+}
+
+/// Exponential backoff before the next attempt, scaled by `jitter` (in
+/// `0.0..1.0`) to between half and all of the full delay, so that clients
+/// failing at the same time do not retry at the same time.
+fn backoff(attempt: u32, jitter: f64) -> Duration {
+    let delay = INITIAL_BACKOFF
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(MAX_BACKOFF);
+    delay.mul_f64(0.5 + jitter / 2.0)
+}
+
+/// A random number in `0.0..1.0`, good enough for jitter
+fn random_fraction() -> f64 {
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The error for an unsuccessful response, with the message GitHub gave
+fn api_error(status: StatusCode, text: &str) -> Error {
+    #[derive(serde::Deserialize)]
+    struct ErrorResponse {
+        message: String,
+    }
+
+    let mut error = Error::new(format!("GitHub API request failed ({status})"));
+    match serde_json::from_str::<ErrorResponse>(text) {
+        Ok(response) => error.push(response.message),
+        Err(_) if !text.trim().is_empty() => error.push(text.trim().to_string()),
+        Err(_) => (),
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        assert_eq!(
+            retry_after(
+                StatusCode::FORBIDDEN,
+                &headers(&[("retry-after", "30")]),
+                "",
+                now
+            ),
+            Some(Retry::RateLimited(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            retry_after(
+                StatusCode::FORBIDDEN,
+                &headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1060")
+                ]),
+                "",
+                now
+            ),
+            Some(Retry::RateLimited(Duration::from_secs(61)))
+        );
+        assert_eq!(
+            retry_after(
+                StatusCode::FORBIDDEN,
+                &headers(&[]),
+                r#"{"message": "You have exceeded a secondary rate limit."}"#,
+                now
+            ),
+            Some(Retry::RateLimited(Duration::from_secs(60)))
+        );
+        assert_eq!(
+            retry_after(
+                StatusCode::FORBIDDEN,
+                &headers(&[
+                    ("x-ratelimit-remaining", "4000"),
+                    ("x-ratelimit-reset", "1060")
+                ]),
+                r#"{"message": "Resource not accessible by integration"}"#,
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            retry_after(StatusCode::BAD_GATEWAY, &headers(&[]), "", now),
+            Some(Retry::Transient)
+        );
+        assert_eq!(
+            retry_after(StatusCode::UNPROCESSABLE_ENTITY, &headers(&[]), "", now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1, 1.0), INITIAL_BACKOFF);
+        assert_eq!(backoff(1, 0.0), INITIAL_BACKOFF / 2);
+        assert_eq!(backoff(3, 1.0), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff(30, 1.0), MAX_BACKOFF);
+        assert!(backoff(2, random_fraction()) <= INITIAL_BACKOFF * 2);
+    }
+
+    #[test]
+    fn test_api_error() {
+        let error = api_error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            r#"{"message": "Pull Request is not mergeable"}"#,
+        );
+        assert_eq!(
+            error.messages(),
+            &vec![
+                "GitHub API request failed (405 Method Not Allowed)".to_string(),
+                "Pull Request is not mergeable".to_string()
+            ]
+        );
+    }
+}
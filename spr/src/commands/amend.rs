@@ -7,7 +7,6 @@
 
 use crate::{
     error::{Error, Result},
-    jj::PreparedCommit,
     message::validate_commit_message,
     output::{output, write_commit_title},
 };
@@ -22,8 +21,9 @@ pub struct AmendOptions {
     #[clap(long)]
     base: Option<String>,
 
-    /// Jujutsu revision(s) to operate on. Can be a single revision like '@' or a range like 'main..@' or 'a::c'.
-    /// If a range is provided, behaves like --all mode. If not specified, uses '@-'.
+    /// Jujutsu revset to operate on, e.g. a single revision like '@', a range
+    /// like 'main..@', or 'trunk()..@ & mine()'. With --all, the commits from
+    /// base up to it. If not specified, uses '@-'.
     #[clap(short = 'r', long)]
     revision: Option<String>,
 }
@@ -31,49 +31,41 @@ pub struct AmendOptions {
 pub async fn amend(
     opts: AmendOptions,
     jj: &crate::jj::Jujutsu,
-    gh: &mut crate::github::GitHub,
+    gh: &mut dyn crate::forge::Forge,
     config: &crate::config::Config,
 ) -> Result<()> {
-    // Determine revision and whether to use range mode
-    let (use_range_mode, base_rev, target_rev, is_inclusive) =
-        crate::revision_utils::parse_revision_and_range(
-            opts.revision.as_deref(),
-            opts.all,
-            opts.base.as_deref(),
-        )?;
-
-    let mut pc = if use_range_mode {
-        jj.get_prepared_commits_from_to(config, &base_rev, &target_rev, is_inclusive)?
-    } else {
-        vec![jj.get_prepared_commit_for_revision(config, &target_rev)?]
-    };
+    let revset = crate::revision_utils::revset_for_options(
+        opts.revision.as_deref(),
+        opts.all,
+        opts.base.as_deref(),
+    );
+    let mut pc = jj.get_prepared_commits_for_revset(config, &revset)?;
 
     if pc.is_empty() {
         output("👋", "No commits found - nothing to do. Good bye!")?;
         return Ok(());
     }
 
-    // Request the Pull Request information for each commit (well, those that
-    // declare to have Pull Requests).
-    let pull_requests: Vec<_> = pc
+    // Request the Pull Request information for all commits (well, those that
+    // declare to have Pull Requests) at once.
+    let pull_request_numbers: Vec<u64> = pc
         .iter()
-        .map(|commit: &PreparedCommit| {
-            commit
-                .pull_request_number
-                .map(|number| tokio::spawn(gh.clone().get_pull_request(number)))
-        })
+        .filter_map(|commit| commit.pull_request_number)
         .collect();
+    let pull_requests = gh.get_pull_requests(&pull_request_numbers).await?;
 
     let mut failure = false;
 
-    for (commit, pull_request) in pc.iter_mut().zip(pull_requests.into_iter()) {
+    for commit in pc.iter_mut() {
         write_commit_title(commit)?;
-        if let Some(pull_request) = pull_request {
-            let pull_request = pull_request.await??;
-            commit.message = pull_request.sections;
+        if let Some(pull_request) = commit
+            .pull_request_number
+            .and_then(|number| pull_requests.get(&number))
+        {
+            commit.message = pull_request.sections.clone();
             commit.message_changed = true;
         }
-        failure = validate_commit_message(&commit.message).is_err() || failure;
+        failure = validate_commit_message(&commit.message, config).is_err() || failure;
     }
     jj.rewrite_commit_messages(&mut pc)?;
 
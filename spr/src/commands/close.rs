@@ -5,13 +5,13 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use std::process::Stdio;
+use std::{collections::HashMap, process::Stdio};
 
 use indoc::formatdoc;
 
 use crate::{
     error::{Error, Result, add_error},
-    github::{PullRequestState, PullRequestUpdate},
+    forge::{PullRequest, PullRequestState},
     jj::PreparedCommit,
     message::MessageSection,
     output::{output, write_commit_title},
@@ -27,8 +27,9 @@ pub struct CloseOptions {
     #[clap(long)]
     base: Option<String>,
 
-    /// Jujutsu revision(s) to operate on. Can be a single revision like '@' or a range like 'main..@' or 'a::c'.
-    /// If a range is provided, behaves like --all mode. If not specified, uses '@-'.
+    /// Jujutsu revset to operate on, e.g. a single revision like '@', a range
+    /// like 'main..@', or 'trunk()..@ & mine()'. With --all, the commits from
+    /// base up to it. If not specified, uses '@-'.
     #[clap(short = 'r', long)]
     revision: Option<String>,
 }
@@ -36,30 +37,30 @@ pub struct CloseOptions {
 pub async fn close(
     opts: CloseOptions,
     jj: &crate::jj::Jujutsu,
-    gh: &mut crate::github::GitHub,
+    gh: &mut dyn crate::forge::Forge,
     config: &crate::config::Config,
 ) -> Result<()> {
     let mut result = Ok(());
 
-    // Determine revision and whether to use range mode
-    let (use_range_mode, base_rev, target_rev, is_inclusive) =
-        crate::revision_utils::parse_revision_and_range(
-            opts.revision.as_deref(),
-            opts.all,
-            opts.base.as_deref(),
-        )?;
-
-    let mut prepared_commits = if use_range_mode {
-        jj.get_prepared_commits_from_to(config, &base_rev, &target_rev, is_inclusive)?
-    } else {
-        vec![jj.get_prepared_commit_for_revision(config, &target_rev)?]
-    };
+    let revset = crate::revision_utils::revset_for_options(
+        opts.revision.as_deref(),
+        opts.all,
+        opts.base.as_deref(),
+    );
+    let mut prepared_commits = jj.get_prepared_commits_for_revset(config, &revset)?;
 
     if prepared_commits.is_empty() {
         output("👋", "No commits found - nothing to do. Good bye!")?;
         return result;
     }
 
+    // Load all the Pull Requests to close at once.
+    let pull_request_numbers: Vec<u64> = prepared_commits
+        .iter()
+        .filter_map(|pc| pc.pull_request_number)
+        .collect();
+    let mut pull_requests = gh.get_pull_requests(&pull_request_numbers).await?;
+
     for prepared_commit in prepared_commits.iter_mut() {
         if result.is_err() {
             break;
@@ -71,7 +72,7 @@ pub async fn close(
         // This makes it easier to run the code to update the local commit message
         // with all the changes that the implementation makes at the end, even if
         // the implementation encounters an error or exits early.
-        result = close_impl(gh, config, prepared_commit).await;
+        result = close_impl(gh, config, prepared_commit, &mut pull_requests).await;
     }
 
     // This updates the commit message in the local Jujutsu repository (if it was
@@ -85,9 +86,10 @@ pub async fn close(
 }
 
 async fn close_impl(
-    gh: &mut crate::github::GitHub,
+    gh: &mut dyn crate::forge::Forge,
     config: &crate::config::Config,
     prepared_commit: &mut PreparedCommit,
+    pull_requests: &mut HashMap<u64, PullRequest>,
 ) -> Result<()> {
     let pull_request_number = if let Some(number) = prepared_commit.pull_request_number {
         output("#️⃣ ", &format!("Pull Request #{}", number))?;
@@ -96,8 +98,10 @@ async fn close_impl(
         return Err(Error::new("This commit does not refer to a Pull Request."));
     };
 
-    // Load Pull Request information
-    let pull_request = gh.clone().get_pull_request(pull_request_number).await?;
+    // A Pull Request is missing if an earlier commit referred to it, too.
+    let pull_request = pull_requests
+        .remove(&pull_request_number)
+        .ok_or_else(|| Error::new("This Pull Request is already closed!"))?;
 
     if pull_request.state != PullRequestState::Open {
         return Err(Error::new(formatdoc!(
@@ -109,15 +113,7 @@ async fn close_impl(
 
     let base_is_master = pull_request.base.is_master_branch();
 
-    let result = gh
-        .update_pull_request(
-            pull_request_number,
-            PullRequestUpdate {
-                state: Some(PullRequestState::Closed),
-                ..Default::default()
-            },
-        )
-        .await;
+    let result = gh.close_pull_request(pull_request_number).await;
 
     match result {
         Ok(()) => (),
@@ -135,12 +131,13 @@ async fn close_impl(
     prepared_commit.message.remove(&MessageSection::ReviewedBy);
     prepared_commit.message_changed = true;
 
-    let mut remove_old_branch_child_process = tokio::process::Command::new("git")
+    let mut remove_old_branch_child_process = config
+        .git_command()
         .arg("push")
         .arg("--no-verify")
         .arg("--delete")
         .arg("--")
-        .arg(&config.remote_name)
+        .arg(&config.push_remote_name)
         .arg(pull_request.head.on_github())
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -150,12 +147,13 @@ async fn close_impl(
         None
     } else {
         Some(
-            tokio::process::Command::new("git")
+            config
+                .git_command()
                 .arg("push")
                 .arg("--no-verify")
                 .arg("--delete")
                 .arg("--")
-                .arg(&config.remote_name)
+                .arg(&config.push_remote_name)
                 .arg(pull_request.base.on_github())
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::{
+    config::{KEYS, REPOSITORY_CONFIG_FILE, Settings, describe_auth_token_source},
+    error::Result,
+    output::output,
+};
+
+pub fn config(settings: &Settings, git_repo: &git2::Repository) -> Result<()> {
+    let term = console::Term::stdout();
+
+    for key in KEYS {
+        match settings.display_value(key) {
+            Some((value, source)) => term.write_line(&format!(
+                "spr.{key} = {value} {}",
+                console::style(format!("({source})")).dim()
+            ))?,
+            None => term.write_line(&format!("spr.{key} {}", console::style("(not set)").dim()))?,
+        }
+    }
+
+    for key in settings.unknown_keys() {
+        let Some((value, source)) = settings.display_value(key) else {
+            continue;
+        };
+        term.write_line(&format!(
+            "spr.{key} = {value} {}",
+            console::style(format!("({source}, unknown setting)")).yellow()
+        ))?;
+    }
+
+    for key in settings.ignored_repository_keys() {
+        term.write_line(&format!(
+            "spr.{key} {}",
+            console::style(format!(
+                "(ignored in {REPOSITORY_CONFIG_FILE}, set it in your own config)"
+            ))
+            .yellow()
+        ))?;
+    }
+
+    term.write_line("")?;
+    let config = settings.to_config(git_repo)?;
+    output("✅", "The configuration is valid")?;
+
+    // A GitHub App is only checked locally, without requesting a token.
+    match describe_auth_token_source(settings, Some(git_repo.path()), &config.host)? {
+        Some(source) => output(
+            "🔑",
+            &format!("Authenticating to {} with {source}", config.host),
+        )?,
+        None => output(
+            "❗",
+            &format!("No auth token for {} was found", config.host),
+        )?,
+    }
+
+    Ok(())
+}
@@ -5,19 +5,20 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use std::iter::zip;
-
 use crate::{
     error::{Error, Result, ResultExt, add_error},
-    github::{
-        GitHub, PullRequest, PullRequestRequestReviewers, PullRequestState, PullRequestUpdate,
+    forge::{PullRequest, PullRequestRequestReviewers, PullRequestState, PullRequestUpdate},
+    github::GitHubBranch,
+    message::{
+        MessageSection, MessageSectionsMap, build_stack_block, split_stack_block,
+        validate_commit_message, with_stack_block,
     },
-    message::{MessageSection, validate_commit_message},
     output::{output, write_commit_title},
     utils::{parse_name_list, remove_all_parens, run_command},
 };
 use git2::Oid;
 use indoc::{formatdoc, indoc};
+use std::collections::HashMap;
 
 #[derive(Debug, clap::Parser)]
 pub struct DiffOptions {
@@ -30,7 +31,8 @@ pub struct DiffOptions {
     #[clap(long)]
     update_message: bool,
 
-    /// Submit any new Pull Request as a draft
+    /// Submit any new Pull Request as a draft. This is the default if
+    /// spr.draft is set.
     #[clap(long)]
     draft: bool,
 
@@ -48,8 +50,9 @@ pub struct DiffOptions {
     #[clap(long)]
     base: Option<String>,
 
-    /// Jujutsu revision(s) to operate on. Can be a single revision like '@' or a range like 'main..@' or 'a::c'.
-    /// If a range is provided, behaves like --all mode. If not specified, uses '@-'.
+    /// Jujutsu revset to operate on, e.g. a single revision like '@', a range
+    /// like 'main..@', or 'trunk()..@ & mine()'. With --all, the commits from
+    /// base up to it. If not specified, uses '@-'.
     #[clap(short = 'r', long)]
     revision: Option<String>,
 }
@@ -57,68 +60,84 @@ pub struct DiffOptions {
 pub async fn diff(
     opts: DiffOptions,
     jj: &crate::jj::Jujutsu,
-    gh: &mut crate::github::GitHub,
+    gh: &mut dyn crate::forge::Forge,
     config: &crate::config::Config,
 ) -> Result<()> {
     let mut result = Ok(());
 
-    // Determine revision and whether to use range mode
-    let (use_range_mode, base_rev, target_rev, is_inclusive) =
-        crate::revision_utils::parse_revision_and_range(
-            opts.revision.as_deref(),
-            opts.all,
-            opts.base.as_deref(),
-        )?;
-
-    // Get commits to process
-    let mut prepared_commits = if use_range_mode {
-        // Get range of commits from base to target
-        jj.get_prepared_commits_from_to(config, &base_rev, &target_rev, is_inclusive)?
-    } else {
-        // Just get the single specified revision
-        vec![jj.get_prepared_commit_for_revision(config, &target_rev)?]
-    };
+    let revset = crate::revision_utils::revset_for_options(
+        opts.revision.as_deref(),
+        opts.all,
+        opts.base.as_deref(),
+    );
+    let prepared_commits = jj.get_prepared_commits_for_revset(config, &revset)?;
 
-    // Determine the master base OID - this is the commit on master that the stack is based on
-    let master_base_oid = if let Some(first_commit) = prepared_commits.first() {
-        if use_range_mode {
-            // For range mode, the parent of the first commit is the master base
-            first_commit.parent_oid
-        } else {
-            // For single commit mode, find the actual merge base with master
-            jj.get_master_base_for_commit(config, first_commit.oid)?
-        }
-    } else {
+    if prepared_commits.is_empty() {
         output("👋", "No commits found - nothing to do. Good bye!")?;
         return result;
-    };
+    }
+
+    // The revset may select several stacks. Each of them is based on its
+    // own commit on master.
+    let mut stacks = jj.get_stacks(config, prepared_commits)?;
 
-    #[allow(clippy::needless_collect)]
-    let pull_request_tasks: Vec<_> = prepared_commits
+    // Load all the Pull Requests of the stacks at once.
+    let pull_request_numbers: Vec<u64> = stacks
         .iter()
-        .map(|pc: &crate::jj::PreparedCommit| {
-            pc.pull_request_number
-                .map(|number| tokio::spawn(gh.clone().get_pull_request(number)))
-        })
+        .flat_map(|stack| &stack.commits)
+        .filter_map(|pc| pc.pull_request_number)
         .collect();
+    let pull_requests = gh.get_pull_requests(&pull_request_numbers).await?;
 
     let mut message_on_prompt = "".to_string();
 
-    for (prepared_commit, pull_request_task) in
-        zip(prepared_commits.iter_mut(), pull_request_tasks.into_iter())
-    {
+    // The Pull Request branches of the commits processed so far. A commit
+    // whose parent is among them is stacked on the parent's Pull Request.
+    let mut pull_request_heads: HashMap<Oid, PullRequestHead> = HashMap::new();
+
+    let stack_commits = stacks.iter_mut().flat_map(|stack| {
+        let master_base_oid = stack.master_base_oid;
+        stack
+            .commits
+            .iter_mut()
+            .map(move |prepared_commit| (master_base_oid, prepared_commit))
+    });
+    for (master_base_oid, prepared_commit) in stack_commits {
         if result.is_err() {
             break;
         }
 
-        let pull_request = if let Some(task) = pull_request_task {
-            Some(task.await??)
-        } else {
+        let pull_request = prepared_commit
+            .pull_request_number
+            .and_then(|number| pull_requests.get(&number).cloned());
+
+        // With --cherry-pick, every Pull Request is based on master. So is
+        // every Pull Request from a fork, as GitHub only accepts base branches
+        // in the repository itself.
+        let parent_head = if opts.cherry_pick || config.fork_owner.is_some() {
             None
+        } else {
+            pull_request_heads.get(&prepared_commit.parent_oid).cloned()
         };
+        let commit_oid = prepared_commit.oid;
 
         write_commit_title(prepared_commit)?;
 
+        if config.fork_owner.is_some()
+            && !opts.cherry_pick
+            && prepared_commit.parent_oid != master_base_oid
+        {
+            output(
+                "⚠️",
+                &formatdoc!(
+                    "Pull Requests from a fork cannot be stacked. This one is \
+                     against {master}, so it also shows the changes of the \
+                     commits below it until they have landed.",
+                    master = config.master_ref.branch_name(),
+                ),
+            )?;
+        }
+
         // The further implementation of the diff command is in a separate function.
         // This makes it easier to run the code to update the local commit message
         // with all the changes that the implementation makes at the end, even if
@@ -131,32 +150,150 @@ pub async fn diff(
             config,
             prepared_commit,
             master_base_oid,
+            parent_head,
             pull_request,
         )
-        .await;
+        .await
+        .map(|head| {
+            pull_request_heads.insert(commit_oid, head);
+        });
+    }
+
+    // Now that all Pull Requests of a stack exist, let each of them link to
+    // the others. The stack reaches beyond the commits in the revset, so that
+    // updating a single commit also refreshes the Pull Requests around it.
+    let mut updated_stacks: Vec<Vec<u64>> = Vec::new();
+    for stack in &stacks {
+        if result.is_err() {
+            break;
+        }
+        result = match stack_pull_request_numbers(jj, config, stack) {
+            Ok(numbers) if updated_stacks.contains(&numbers) => Ok(()),
+            Ok(numbers) => {
+                let result = update_stack_blocks(gh, &numbers).await;
+                updated_stacks.push(numbers);
+                result
+            }
+            Err(error) => Err(error),
+        };
     }
 
     // This updates the commit message in the local Jujutsu repository (if it was
     // changed by the implementation)
     add_error(
         &mut result,
-        jj.rewrite_commit_messages(prepared_commits.as_mut_slice()),
+        jj.rewrite_commit_messages(stacks.iter_mut().flat_map(|stack| &mut stack.commits)),
     );
 
     result
 }
 
+/// The branch of a Pull Request, and the commit it was last updated to
+#[derive(Clone)]
+struct PullRequestHead {
+    branch: GitHubBranch,
+    oid: Oid,
+}
+
+/// The Pull Requests of the whole stack the commits of `stack` are part of,
+/// ordered bottom to top: the commits between master and the stack, the
+/// stack itself, and the line of descendants above it, as long as it does
+/// not branch
+fn stack_pull_request_numbers(
+    jj: &crate::jj::Jujutsu,
+    config: &crate::config::Config,
+    stack: &crate::jj::Stack,
+) -> Result<Vec<u64>> {
+    let (Some(bottom), Some(top)) = (stack.commits.first(), stack.commits.last()) else {
+        return Ok(Vec::new());
+    };
+
+    let ancestors = jj.get_prepared_commits_for_revset(
+        config,
+        &format!("{}..{}", stack.master_base_oid, bottom.parent_oid),
+    )?;
+    let descendants = jj.get_prepared_commits_for_revset(config, &format!("{}+::", top.oid))?;
+
+    let mut numbers: Vec<u64> = ancestors
+        .iter()
+        .chain(&stack.commits)
+        .filter_map(|pc| pc.pull_request_number)
+        .collect();
+
+    let mut current = top.oid;
+    loop {
+        let mut children = descendants.iter().filter(|pc| pc.parent_oid == current);
+        match (children.next(), children.next()) {
+            (Some(child), None) => {
+                numbers.extend(child.pull_request_number);
+                current = child.oid;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(numbers)
+}
+
+/// Refresh the stack navigation block in the descriptions of all Pull
+/// Requests of a stack (ordered bottom to top). A Pull Request that is not
+/// part of a stack anymore loses its block.
+async fn update_stack_blocks(gh: &dyn crate::forge::Forge, stack: &[u64]) -> Result<()> {
+    let pull_requests = gh.get_pull_request_statuses(stack).await?;
+    let mut updated = false;
+
+    for number in stack {
+        let Some(pull_request) = pull_requests.get(number) else {
+            continue;
+        };
+
+        let (text, _) = split_stack_block(&pull_request.body);
+        let stack_block = (stack.len() > 1).then(|| build_stack_block(stack, *number));
+        let body = with_stack_block(&text, stack_block.as_deref());
+
+        if body.trim() != pull_request.body.trim() {
+            gh.update_pull_request(
+                *number,
+                PullRequestUpdate {
+                    body: Some(body),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            updated = true;
+        }
+    }
+
+    if updated {
+        output(
+            "📚",
+            "Updated stack navigation in Pull Request descriptions",
+        )?;
+    }
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn diff_impl(
     opts: &DiffOptions,
     message_on_prompt: &mut String,
     jj: &crate::jj::Jujutsu,
-    gh: &mut crate::github::GitHub,
+    gh: &mut dyn crate::forge::Forge,
     config: &crate::config::Config,
     local_commit: &mut crate::jj::PreparedCommit,
     master_base_oid: Oid,
+    parent_head: Option<PullRequestHead>,
     pull_request: Option<PullRequest>,
-) -> Result<()> {
+) -> Result<PullRequestHead> {
+    // The tree of a commit with conflicts contains jj's conflict
+    // representation, which must not end up in a Pull Request.
+    if local_commit.conflict {
+        return Err(Error::new(formatdoc!(
+            "This commit has conflicts. Resolve them before submitting it."
+        )));
+    }
+
     // Parsed commit message of the local commit
     let message = &mut local_commit.message;
 
@@ -175,7 +312,13 @@ async fn diff_impl(
         // current commit has, and the master base is the same as this commit's
         // parent.
         let head_tree = jj.get_tree_oid_for_commit(local_commit.oid)?;
-        let base_tree = jj.get_tree_oid_for_commit(local_commit.parent_oid)?;
+        // A Pull Request from a fork cannot have a base branch, so it is
+        // against master and also shows the changes this commit is based on.
+        let base_tree = if config.fork_owner.is_some() {
+            jj.get_tree_oid_for_commit(master_base_oid)?
+        } else {
+            jj.get_tree_oid_for_commit(local_commit.parent_oid)?
+        };
 
         (head_tree, base_tree)
     } else {
@@ -209,7 +352,7 @@ async fn diff_impl(
     }
 
     if local_commit.pull_request_number.is_none() || opts.update_message {
-        validate_commit_message(message)?;
+        validate_commit_message(message, config)?;
     }
 
     if let Some(ref pull_request) = pull_request {
@@ -252,11 +395,8 @@ async fn diff_impl(
         for reviewer in reviewers {
             // Teams are indicated with a leading #
             if let Some(slug) = reviewer.strip_prefix('#') {
-                if let Ok(team) = GitHub::get_github_team((&config.owner).into(), slug.into()).await
-                {
-                    requested_reviewers
-                        .team_reviewers
-                        .push(team.slug.to_string());
+                if let Ok(team) = gh.get_team(slug).await {
+                    requested_reviewers.team_reviewers.push(team);
 
                     checked_reviewers.push(reviewer);
                 } else {
@@ -265,7 +405,7 @@ async fn diff_impl(
                         reviewer
                     )));
                 }
-            } else if let Ok(user) = GitHub::get_github_user(reviewer.clone()).await {
+            } else if let Ok(user) = gh.get_user(&reviewer).await {
                 requested_reviewers.reviewers.push(user.login);
                 if let Some(name) = user.name {
                     checked_reviewers.push(format!(
@@ -303,172 +443,226 @@ async fn diff_impl(
         }
     };
 
-    // Get the tree ids of the current head of the Pull Request, as well as the
-    // base, and the commit id of the master commit this PR is currently based
-    // on.
-    // If there is no pre-existing Pull Request, we fill in the equivalent
-    // values.
-    let (pr_head_oid, pr_head_tree, pr_base_oid, pr_base_tree, pr_master_base) =
-        if let Some(pr) = &pull_request {
-            let pr_head_tree = jj.get_tree_oid_for_commit(pr.head_oid)?;
-
-            let current_master_oid = jj.resolve_reference(config.master_ref.local())?;
-            // Use git for merge base calculation since jj doesn't expose this directly
-            let pr_base_oid = jj.git_repo.merge_base(pr.head_oid, pr.base_oid)?;
-            let pr_base_tree = jj.get_tree_oid_for_commit(pr_base_oid)?;
-
-            let pr_master_base = jj.git_repo.merge_base(pr.head_oid, current_master_oid)?;
+    // We are going to construct the parents of the new Pull Request commit: the
+    // current head of the Pull Request branch, and `pr_base_parent`, a commit
+    // to merge into it to reflect changes of the parent of the local commit.
+    // Also the branch the Pull Request is based on (`None` for master), and
+    // the commit to push onto that branch, if it's updated.
+    let (pr_head_oid, pr_base_parent, base_branch, base_branch_commit, rebased) =
+        if let Some(parent_head) = parent_head {
+            // The parent of the local commit has a Pull Request, which was
+            // updated just now. Its branch has the tree of the parent commit,
+            // so this Pull Request is based on it directly.
+            let pr_head_oid = pull_request
+                .as_ref()
+                .map_or(parent_head.oid, |pr| pr.head_oid);
+            let contains_parent_head = pr_head_oid == parent_head.oid
+                || jj
+                    .git_repo
+                    .graph_descendant_of(pr_head_oid, parent_head.oid)?;
+
+            if let Some(ref pull_request) = pull_request
+                && contains_parent_head
+                && pull_request.base.branch_name() == parent_head.branch.branch_name()
+                && jj.get_tree_oid_for_commit(pr_head_oid)? == new_head_tree
+            {
+                return no_update_necessary(opts, gh, pull_request, message).await;
+            }
 
             (
-                pr.head_oid,
-                pr_head_tree,
-                pr_base_oid,
-                pr_base_tree,
-                pr_master_base,
+                pr_head_oid,
+                (!contains_parent_head).then_some(parent_head.oid),
+                Some(parent_head.branch),
+                None,
+                !contains_parent_head,
             )
         } else {
-            let master_base_tree = jj.get_tree_oid_for_commit(master_base_oid)?;
-            (
-                master_base_oid,
-                master_base_tree,
-                master_base_oid,
-                master_base_tree,
-                master_base_oid,
-            )
-        };
-    let needs_merging_master = pr_master_base != master_base_oid;
-
-    // At this point we can check if we can exit early because no update to the
-    // existing Pull Request is necessary
-    if let Some(ref pull_request) = pull_request {
-        // So there is an existing Pull Request...
-        if !needs_merging_master && pr_head_tree == new_head_tree && pr_base_tree == new_base_tree {
-            // ...and it does not need a rebase, and the trees of both Pull
-            // Request branch and base are all the right ones.
-            output("✅", "No update necessary")?;
-
-            if opts.update_message {
-                // However, the user requested to update the commit message on
-                // GitHub
-
-                let mut pull_request_updates: PullRequestUpdate = Default::default();
-                pull_request_updates.update_message(pull_request, message);
-
-                if !pull_request_updates.is_empty() {
-                    // ...and there are actual changes to the message
-                    gh.update_pull_request(pull_request.number, pull_request_updates)
-                        .await?;
-                    output("✍", "Updated commit message on GitHub")?;
+            // Get the tree ids of the current head of the Pull Request, as well as the
+            // base, and the commit id of the master commit this PR is currently based
+            // on.
+            // If there is no pre-existing Pull Request, we fill in the equivalent
+            // values.
+            let (pr_head_oid, pr_head_tree, pr_base_oid, pr_base_tree, pr_master_base) =
+                if let Some(pr) = &pull_request {
+                    let pr_head_tree = jj.get_tree_oid_for_commit(pr.head_oid)?;
+
+                    let current_master_oid = jj.resolve_reference(config.master_ref.local())?;
+                    // Use git for merge base calculation since jj doesn't expose this directly
+                    let pr_base_oid = jj.git_repo.merge_base(pr.head_oid, pr.base_oid)?;
+                    let pr_base_tree = jj.get_tree_oid_for_commit(pr_base_oid)?;
+
+                    let pr_master_base = jj.git_repo.merge_base(pr.head_oid, current_master_oid)?;
+
+                    (
+                        pr.head_oid,
+                        pr_head_tree,
+                        pr_base_oid,
+                        pr_base_tree,
+                        pr_master_base,
+                    )
+                } else {
+                    let master_base_tree = jj.get_tree_oid_for_commit(master_base_oid)?;
+                    (
+                        master_base_oid,
+                        master_base_tree,
+                        master_base_oid,
+                        master_base_tree,
+                        master_base_oid,
+                    )
+                };
+            let needs_merging_master = pr_master_base != master_base_oid;
+
+            // At this point we can check if we can exit early because no update to the
+            // existing Pull Request is necessary
+            if let Some(ref pull_request) = pull_request {
+                // So there is an existing Pull Request...
+                if !needs_merging_master
+                    && pr_head_tree == new_head_tree
+                    && pr_base_tree == new_base_tree
+                {
+                    // ...and it does not need a rebase, and the trees of both Pull
+                    // Request branch and base are all the right ones.
+                    return no_update_necessary(opts, gh, pull_request, message).await;
                 }
             }
 
-            return Ok(());
-        }
-    }
-
-    // Check if there is a base branch on GitHub already. That's the case when
-    // there is an existing Pull Request, and its base is not the master branch.
-    let base_branch = if let Some(ref pr) = pull_request {
-        if pr.base.is_master_branch() {
-            None
-        } else {
-            Some(pr.base.clone())
-        }
-    } else {
-        None
-    };
-
-    // We are going to construct `pr_base_parent: Option<Oid>`.
-    // The value will be the commit we have to merge into the new Pull Request
-    // commit to reflect changes in the parent of the local commit (by rebasing
-    // or changing commits between master and this one, although technically
-    // that's also rebasing).
-    // If it's `None`, then we will not merge anything into the new Pull Request
-    // commit.
-    // If we are updating an existing PR, then there are three cases here:
-    // (1) the parent tree of this commit is unchanged and we do not need to
-    //     merge in master, which means that the local commit was amended, but
-    //     not rebased. We don't need to merge anything into the Pull Request
-    //     branch.
-    // (2) the parent tree has changed, but the parent of the local commit is on
-    //     master (or we are cherry-picking) and we are not already using a base
-    //     branch: in this case we can merge the master commit we are based on
-    //     into the PR branch, without going via a base branch. Thus, we don't
-    //     introduce a base branch here and the PR continues to target the
-    //     master branch.
-    // (3) the parent tree has changed, and we need to use a base branch (either
-    //     because one was already created earlier, or we find that we are not
-    //     directly based on master now): we need to construct a new commit for
-    //     the base branch. That new commit's tree is always that of that local
-    //     commit's parent (thus making sure that the difference between base
-    //     branch and pull request branch are exactly the changes made by the
-    //     local commit, thus the changes we want to have reviewed). The new
-    //     commit may have one or two parents. The previous base is always a
-    //     parent (that's either the current commit on an existing base branch,
-    //     or the previous master commit the PR was based on if there isn't a
-    //     base branch already). In addition, if the master commit this commit
-    //     is based on has changed, (i.e. the local commit got rebased on newer
-    //     master in the meantime) then we have to merge in that master commit,
-    //     which will be the second parent.
-    // If we are creating a new pull request then `pr_base_tree` (the current
-    // base of the PR) was set above to be the tree of the master commit the
-    // local commit is based one, whereas `new_base_tree` is the tree of the
-    // parent of the local commit. So if the local commit for this new PR is on
-    // master, those two are the same (and we want to apply case 1). If the
-    // commit is not directly based on master, we have to create this new PR
-    // with a base branch, so that is case 3.
-
-    let (pr_base_parent, base_branch) = if pr_base_tree == new_base_tree && !needs_merging_master {
-        // Case 1
-        (None, base_branch)
-    } else if base_branch.is_none() && (directly_based_on_master || opts.cherry_pick) {
-        // Case 2
-        (Some(master_base_oid), None)
-    } else {
-        // Case 3
-
-        // We are constructing a base branch commit.
-        // One parent of the new base branch commit will be the current base
-        // commit, that could be either the top commit of an existing base
-        // branch, or a commit on master.
-        let mut parents = vec![pr_base_oid];
-
-        // If we need to rebase on master, make the master commit also a
-        // parent (except if the first parent is that same commit, we don't
-        // want duplicates in `parents`).
-        if needs_merging_master && pr_base_oid != master_base_oid {
-            parents.push(master_base_oid);
-        }
-
-        let new_base_branch_commit = jj.create_derived_commit(
-            local_commit.parent_oid,
-            &format!(
-                "[spr] {}\n\nCreated using jj-spr {}\n\n[skip ci]",
-                if pull_request.is_some() {
-                    "changes introduced through rebase".to_string()
+            // Check if there is a base branch on GitHub already. That's the case when
+            // there is an existing Pull Request, and its base is not the master branch.
+            let base_branch = if let Some(ref pr) = pull_request {
+                if pr.base.is_master_branch() {
+                    None
                 } else {
-                    format!(
-                        "changes to {} this commit is based on",
-                        config.master_ref.branch_name()
-                    )
-                },
-                env!("CARGO_PKG_VERSION"),
-            ),
-            new_base_tree,
-            &parents[..],
-        )?;
-
-        // If `base_branch` is `None` (which means a base branch does not exist
-        // yet), then make a `GitHubBranch` with a new name for a base branch
-        let base_branch = if let Some(base_branch) = base_branch {
-            base_branch
-        } else {
-            config.new_github_branch(&config.get_base_branch_name(&jj.get_all_ref_names()?, title))
+                    Some(pr.base.clone())
+                }
+            } else {
+                None
+            };
+
+            // We are going to construct `pr_base_parent: Option<Oid>`.
+            // The value will be the commit we have to merge into the new Pull Request
+            // commit to reflect changes in the parent of the local commit (by rebasing
+            // or changing commits between master and this one, although technically
+            // that's also rebasing).
+            // If it's `None`, then we will not merge anything into the new Pull Request
+            // commit.
+            // If we are updating an existing PR, then there are three cases here:
+            // (1) the parent tree of this commit is unchanged and we do not need to
+            //     merge in master, which means that the local commit was amended, but
+            //     not rebased. We don't need to merge anything into the Pull Request
+            //     branch.
+            // (2) the parent tree has changed, but the parent of the local commit is on
+            //     master (or we are cherry-picking) and we are not already using a base
+            //     branch: in this case we can merge the master commit we are based on
+            //     into the PR branch, without going via a base branch. Thus, we don't
+            //     introduce a base branch here and the PR continues to target the
+            //     master branch.
+            // (3) the parent tree has changed, and we need to use a base branch (either
+            //     because one was already created earlier, or we find that we are not
+            //     directly based on master now): we need to construct a new commit for
+            //     the base branch. That new commit's tree is always that of that local
+            //     commit's parent (thus making sure that the difference between base
+            //     branch and pull request branch are exactly the changes made by the
+            //     local commit, thus the changes we want to have reviewed). The new
+            //     commit may have one or two parents. The previous base is always a
+            //     parent (that's either the current commit on an existing base branch,
+            //     or the previous master commit the PR was based on if there isn't a
+            //     base branch already). In addition, if the master commit this commit
+            //     is based on has changed, (i.e. the local commit got rebased on newer
+            //     master in the meantime) then we have to merge in that master commit,
+            //     which will be the second parent.
+            // If we are creating a new pull request then `pr_base_tree` (the current
+            // base of the PR) was set above to be the tree of the master commit the
+            // local commit is based one, whereas `new_base_tree` is the tree of the
+            // parent of the local commit. So if the local commit for this new PR is on
+            // master, those two are the same (and we want to apply case 1). If the
+            // commit is not directly based on master, we have to create this new PR
+            // with a base branch, so that is case 3.
+
+            let (pr_base_parent, base_branch) =
+                if pr_base_tree == new_base_tree && !needs_merging_master {
+                    // Case 1
+                    (None, base_branch)
+                } else if base_branch.is_none()
+                    && (directly_based_on_master || opts.cherry_pick || config.fork_owner.is_some())
+                {
+                    // Case 2
+                    (Some(master_base_oid), None)
+                } else {
+                    // Case 3
+
+                    // A Pull Request stacked on another one is based on that
+                    // Pull Request's branch. That branch must not get the
+                    // changes to the parent, as they belong to the other Pull
+                    // Request, which has to be updated along with this one.
+                    if let Some(ref base_branch) = base_branch
+                        && !config.is_base_branch_name(base_branch.branch_name())
+                    {
+                        return Err(Error::new(formatdoc!(
+                            "This Pull Request is based on '{branch}', the branch \
+                             of another Pull Request, which does not match the \
+                             parent of this commit anymore. Submit this commit \
+                             together with its parent, e.g. with \
+                             `jj spr diff --all -r {change}`.",
+                            branch = base_branch.branch_name(),
+                            change = local_commit.short_change_id(),
+                        )));
+                    }
+
+                    // We are constructing a base branch commit.
+                    // One parent of the new base branch commit will be the current base
+                    // commit, that could be either the top commit of an existing base
+                    // branch, or a commit on master.
+                    let mut parents = vec![pr_base_oid];
+
+                    // If we need to rebase on master, make the master commit also a
+                    // parent (except if the first parent is that same commit, we don't
+                    // want duplicates in `parents`).
+                    if needs_merging_master && pr_base_oid != master_base_oid {
+                        parents.push(master_base_oid);
+                    }
+
+                    let new_base_branch_commit = jj.create_derived_commit(
+                        local_commit.parent_oid,
+                        &format!(
+                            "[spr] {}\n\nCreated using jj-spr {}\n\n[skip ci]",
+                            if pull_request.is_some() {
+                                "changes introduced through rebase".to_string()
+                            } else {
+                                format!(
+                                    "changes to {} this commit is based on",
+                                    config.master_ref.branch_name()
+                                )
+                            },
+                            env!("CARGO_PKG_VERSION"),
+                        ),
+                        new_base_tree,
+                        &parents[..],
+                    )?;
+
+                    // If `base_branch` is `None` (which means a base branch does not exist
+                    // yet), then make a `GitHubBranch` with a new name for a base branch
+                    let base_branch = if let Some(base_branch) = base_branch {
+                        base_branch
+                    } else {
+                        config.new_github_branch(
+                            &config.get_base_branch_name(&jj.get_all_ref_names()?, title),
+                        )
+                    };
+
+                    (Some(new_base_branch_commit), Some(base_branch))
+                };
+
+            // The commit merged into the Pull Request branch is also the new head of
+            // the base branch.
+            (
+                pr_head_oid,
+                pr_base_parent,
+                base_branch,
+                pr_base_parent,
+                needs_merging_master,
+            )
         };
 
-        (Some(new_base_branch_commit), Some(base_branch))
-    };
-
     let mut github_commit_message = opts.message.clone();
     if pull_request.is_some() && github_commit_message.is_none() {
         let input = {
@@ -522,18 +716,23 @@ async fn diff_impl(
         &pr_commit_parents[..],
     )?;
 
-    let mut cmd = tokio::process::Command::new("git");
+    let mut cmd = config.git_command();
     cmd.arg("push")
         .arg("--atomic")
         .arg("--no-verify")
         .arg("--")
-        .arg(&config.remote_name)
+        .arg(&config.push_remote_name)
         .arg(format!("{}:{}", pr_commit, pull_request_branch.on_github()));
 
+    let head = PullRequestHead {
+        branch: pull_request_branch,
+        oid: pr_commit,
+    };
+
     if let Some(pull_request) = pull_request {
         // We are updating an existing Pull Request
 
-        if needs_merging_master {
+        if rebased {
             output(
                 "⚾",
                 &format!(
@@ -561,7 +760,7 @@ async fn diff_impl(
         if let Some(base_branch) = base_branch {
             // We are using a base branch.
 
-            if let Some(base_branch_commit) = pr_base_parent {
+            if let Some(base_branch_commit) = base_branch_commit {
                 // ...and we prepared a new commit for it, so we need to push an
                 // update of the base branch.
                 cmd.arg(format!(
@@ -598,7 +797,7 @@ async fn diff_impl(
         // We are creating a new Pull Request.
 
         // If there's a base branch, add it to the push
-        if let (Some(base_branch), Some(base_branch_commit)) = (&base_branch, pr_base_parent) {
+        if let (Some(base_branch), Some(base_branch_commit)) = (&base_branch, base_branch_commit) {
             cmd.arg(format!(
                 "{}:{}",
                 base_branch_commit,
@@ -619,8 +818,8 @@ async fn diff_impl(
                     .unwrap_or(&config.master_ref)
                     .branch_name()
                     .to_string(),
-                pull_request_branch.branch_name().to_string(),
-                opts.draft,
+                head.branch.branch_name().to_string(),
+                opts.draft || config.draft,
             )
             .await?;
 
@@ -636,6 +835,7 @@ async fn diff_impl(
 
         message.insert(MessageSection::PullRequest, pull_request_url);
         local_commit.message_changed = true;
+        local_commit.pull_request_number = Some(pull_request_number);
 
         let result = gh
             .request_reviewers(pull_request_number, requested_reviewers)
@@ -651,7 +851,38 @@ async fn diff_impl(
         }
     }
 
-    Ok(())
+    Ok(head)
+}
+
+/// Report that the Pull Request is up to date, updating its title and
+/// description if the user asked for it
+async fn no_update_necessary(
+    opts: &DiffOptions,
+    gh: &mut dyn crate::forge::Forge,
+    pull_request: &PullRequest,
+    message: &MessageSectionsMap,
+) -> Result<PullRequestHead> {
+    output("✅", "No update necessary")?;
+
+    if opts.update_message {
+        // However, the user requested to update the commit message on
+        // GitHub
+
+        let mut pull_request_updates: PullRequestUpdate = Default::default();
+        pull_request_updates.update_message(pull_request, message);
+
+        if !pull_request_updates.is_empty() {
+            // ...and there are actual changes to the message
+            gh.update_pull_request(pull_request.number, pull_request_updates)
+                .await?;
+            output("✍", "Updated commit message on GitHub")?;
+        }
+    }
+
+    Ok(PullRequestHead {
+        branch: pull_request.head.clone(),
+        oid: pull_request.head_oid,
+    })
 }
 
 #[cfg(test)]
@@ -668,6 +899,12 @@ mod tests {
             "main".into(),
             "spr/test/".into(),
             false,
+            crate::forge::MergeMethod::Squash,
+            false,
+            Vec::new(),
+            crate::config::DEFAULT_GITHUB_HOST.into(),
+            crate::forge::ForgeKind::GitHub,
+            None,
         )
     }
 
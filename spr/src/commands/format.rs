@@ -21,8 +21,9 @@ pub struct FormatOptions {
     #[clap(long)]
     base: Option<String>,
 
-    /// Jujutsu revision(s) to operate on. Can be a single revision like '@' or a range like 'main..@' or 'a::c'.
-    /// If a range is provided, behaves like --all mode. If not specified, uses '@-'.
+    /// Jujutsu revset to operate on, e.g. a single revision like '@', a range
+    /// like 'main..@', or 'trunk()..@ & mine()'. With --all, the commits from
+    /// base up to it. If not specified, uses '@-'.
     #[clap(short = 'r', long)]
     revision: Option<String>,
 }
@@ -32,19 +33,12 @@ pub async fn format(
     jj: &crate::jj::Jujutsu,
     config: &crate::config::Config,
 ) -> Result<()> {
-    // Determine revision and whether to use range mode
-    let (use_range_mode, base_rev, target_rev, is_inclusive) =
-        crate::revision_utils::parse_revision_and_range(
-            opts.revision.as_deref(),
-            opts.all,
-            opts.base.as_deref(),
-        )?;
-
-    let mut pc = if use_range_mode {
-        jj.get_prepared_commits_from_to(config, &base_rev, &target_rev, is_inclusive)?
-    } else {
-        vec![jj.get_prepared_commit_for_revision(config, &target_rev)?]
-    };
+    let revset = crate::revision_utils::revset_for_options(
+        opts.revision.as_deref(),
+        opts.all,
+        opts.base.as_deref(),
+    );
+    let mut pc = jj.get_prepared_commits_for_revset(config, &revset)?;
 
     if pc.is_empty() {
         output("👋", "No commits found - nothing to do. Good bye!")?;
@@ -55,7 +49,7 @@ pub async fn format(
 
     for commit in pc.iter() {
         write_commit_title(commit)?;
-        failure = validate_commit_message(&commit.message).is_err() || failure;
+        failure = validate_commit_message(&commit.message, config).is_err() || failure;
     }
     jj.rewrite_commit_messages(&mut pc)?;
 
@@ -9,8 +9,12 @@ use indoc::formatdoc;
 use lazy_regex::regex;
 
 use crate::{
-    config::{AuthTokenSource, get_auth_token_with_source, set_jj_config},
+    config::{
+        AuthTokenSource, DEFAULT_GITHUB_HOST, Settings, get_auth_token_with_source, github_api_url,
+        local_auth_token, parse_remote_url, set_jj_config,
+    },
     error::{Error, Result, ResultExt},
+    jj::Jujutsu,
     output::output,
 };
 
@@ -18,14 +22,13 @@ pub async fn init() -> Result<()> {
     output("👋", "Welcome to spr!")?;
 
     let path = std::env::current_dir()?;
-    let repo = git2::Repository::discover(path.clone()).reword(formatdoc!(
-        "Could not open a Git repository in {:?}. Please run 'spr' from within \
-         a Git repository.",
+    let jj = Jujutsu::discover(&path).reword(formatdoc!(
+        "Could not open a Jujutsu repository in {:?}. Please run 'spr' from \
+         within a Jujutsu workspace.",
         path
     ))?;
-    let config = repo.config()?;
-
-    // GitHub Personal Access Token
+    let config = jj.git_repo.config()?;
+    let repo = &jj.git_repo;
 
     console::Term::stdout().write_line("")?;
 
@@ -34,15 +37,62 @@ pub async fn init() -> Result<()> {
         "Okay, let's get started. First we need to authenticate to GitHub.",
     )?;
 
-    let github_auth_token = get_auth_token_with_source(&config).and_then(|value| {
-        if value.token().is_empty() {
-            None
-        } else {
-            Some(value)
+    // GitHub host
+
+    output(
+        "❓",
+        &formatdoc!(
+            "Which GitHub instance hosts your repository? This is \
+             '{DEFAULT_GITHUB_HOST}', unless you are using GitHub Enterprise \
+             Server, in which case please enter its host name."
+        ),
+    )?;
+
+    // `spr.githubHost` is the old name of `spr.host`.
+    let github_host = ["spr.host", "spr.githubHost"]
+        .into_iter()
+        .filter_map(|key| config.get_string(key).ok())
+        .find(|value| !value.is_empty())
+        .or_else(|| {
+            let remote = config
+                .get_string("spr.githubRemoteName")
+                .unwrap_or_else(|_| "origin".to_string());
+            let remote = repo.find_remote(&remote).ok()?;
+            parse_remote_url(remote.url()?).map(|(host, _)| host)
+        })
+        .unwrap_or_else(|| DEFAULT_GITHUB_HOST.to_string());
+
+    let github_host = dialoguer::Input::<String>::new()
+        .with_prompt("GitHub host")
+        .with_initial_text(github_host)
+        .interact_text()?;
+    set_jj_config("spr.host", &github_host, &path)?;
+
+    // GitHub Personal Access Token
+
+    let settings = Settings::load(&jj, &config)?;
+    // The repository is not known yet, so a GitHub App can only be used if
+    // its installation is configured. Otherwise, look for another token.
+    let github_auth_token = match get_auth_token_with_source(
+        &settings,
+        Some(repo.path()),
+        &github_host,
+        &github_api_url(&github_host),
+        None,
+    )
+    .await
+    {
+        Ok(source) => source,
+        Err(error) => {
+            output("⚠️", "The configured GitHub App cannot be used:")?;
+            for message in error.messages() {
+                output("  ", message)?;
+            }
+            local_auth_token(Some(repo.path()), &github_host)
         }
-    });
+    };
 
-    let reuse_token = match github_auth_token {
+    let reuse_token = match &github_auth_token {
         None => false,
         Some(AuthTokenSource::GitHubCLI(_)) => dialoguer::Confirm::new()
             .with_prompt("Use the GitHub CLI to authenticate?")
@@ -52,10 +102,14 @@ pub async fn init() -> Result<()> {
             .with_prompt("A personal access token is already configured. Use it?")
             .default(true)
             .interact()?,
+        Some(source) => dialoguer::Confirm::new()
+            .with_prompt(format!("Authenticate with {source}?"))
+            .default(true)
+            .interact()?,
     };
 
     let pat = if reuse_token {
-        github_auth_token.unwrap().token().to_owned()
+        github_auth_token.as_ref().unwrap().token().to_owned()
     } else {
         output(
             "  ",
@@ -63,7 +117,7 @@ pub async fn init() -> Result<()> {
                 "We need a 'Personal Access Token' from GitHub. This will \
              authorise spr to open/update/merge Pull Requests etc. on behalf of \
              your GitHub user.
-             You can get one by going to https://github.com/settings/tokens \
+             You can get one by going to https://{github_host}/settings/tokens \
              and clicking on 'Generate new token'. The token needs the 'repo', \
              'user' and 'read:org' permissions, so please tick those three boxes \
              in the 'Select scopes' section.
@@ -86,11 +140,22 @@ pub async fn init() -> Result<()> {
     };
 
     let octocrab = octocrab::OctocrabBuilder::default()
+        .base_uri(github_api_url(&github_host))?
         .personal_token(pat.clone())
         .build()?;
-    let github_user = octocrab.current().user().await?;
 
-    output("👋", &formatdoc!("Hello {}!", github_user.login))?;
+    // GitHub App installations are not users, and cannot look themselves up.
+    let github_login = match &github_auth_token {
+        Some(AuthTokenSource::GitHubApp { app_id, .. }) if reuse_token => {
+            output("👋", &formatdoc!("Hello GitHub App {app_id}!"))?;
+            None
+        }
+        _ => {
+            let github_user = octocrab.current().user().await?;
+            output("👋", &formatdoc!("Hello {}!", github_user.login))?;
+            Some(github_user.login)
+        }
+    };
 
     if !reuse_token {
         set_jj_config("spr.githubAuthToken", pat.as_str(), &path)?;
@@ -128,21 +193,20 @@ pub async fn init() -> Result<()> {
         &formatdoc!(
             "What's the name of the GitHub repository. Please enter \
              'OWNER/REPOSITORY' (basically the bit that follow \
-             'github.com/' in the address.)"
+             '{github_host}/' in the address.)"
         ),
     )?;
 
     let url = repo.find_remote(&remote)?.url().map(String::from);
-    let regex = lazy_regex::regex!(r#"github\.com[/:]([\w\-\.]+/[\w\-\.]+?)(.git)?$"#);
     let github_repo = config
         .get_string("spr.githubRepository")
         .ok()
         .and_then(|value| if value.is_empty() { None } else { Some(value) })
         .or_else(|| {
-            url.as_ref()
-                .and_then(|url| regex.captures(url))
-                .and_then(|caps| caps.get(1))
-                .map(|m| m.as_str().to_string())
+            url.as_deref()
+                .and_then(parse_remote_url)
+                .filter(|(host, _)| host.eq_ignore_ascii_case(&github_host))
+                .map(|(_, repository)| repository)
         })
         .unwrap_or_default();
 
@@ -176,7 +240,10 @@ pub async fn init() -> Result<()> {
         .get_string("spr.branchPrefix")
         .ok()
         .and_then(|value| if value.is_empty() { None } else { Some(value) })
-        .unwrap_or_else(|| format!("spr/{}/", &github_user.login));
+        .unwrap_or_else(|| match &github_login {
+            Some(login) => format!("spr/{login}/"),
+            None => "spr/".to_string(),
+        });
 
     output(
         "❓",
@@ -202,7 +269,7 @@ pub async fn init() -> Result<()> {
     Ok(())
 }
 
-fn validate_branch_prefix(branch_prefix: &str) -> Result<()> {
+pub fn validate_branch_prefix(branch_prefix: &str) -> Result<()> {
     // They can include slash / for hierarchical (directory) grouping, but no slash-separated component can begin with a dot . or end with the sequence .lock.
     if branch_prefix.contains("/.")
         || branch_prefix.contains(".lock/")
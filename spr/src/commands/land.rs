@@ -6,11 +6,19 @@
  */
 
 use indoc::formatdoc;
-use std::{io::Write, process::Stdio, time::Duration};
+use std::{collections::HashMap, io::Write, iter::zip, process::Stdio, time::Duration};
+
+/// Longest time to sleep between two polls of the status checks in --wait mode
+const MAX_CHECK_POLL_INTERVAL: Duration = Duration::from_secs(60);
 
 use crate::{
     error::{Error, Result, ResultExt},
-    github::{PullRequestState, PullRequestUpdate, ReviewStatus},
+    forge::{
+        CheckState, MergeMethod, PullRequest, PullRequestState, PullRequestUpdate, ReviewStatus,
+        StatusCheck,
+    },
+    github::GitHubBranch,
+    jj::PreparedCommit,
     message::build_github_body_for_merging,
     output::{output, write_commit_title},
     utils::run_command,
@@ -23,7 +31,38 @@ pub struct LandOptions {
     #[clap(long)]
     cherry_pick: bool,
 
-    /// Jujutsu revision to operate on (if not specified, uses '@')
+    /// How to merge the Pull Request on GitHub (if not given taken from jj
+    /// config spr.mergeMethod, defaulting to 'squash')
+    #[clap(long, value_enum)]
+    merge_method: Option<MergeMethod>,
+
+    /// Wait for the status checks of the Pull Request to pass before merging
+    /// it, instead of failing when they have not completed yet
+    #[clap(long)]
+    wait: bool,
+
+    /// Let GitHub merge the Pull Request once it is ready, using auto-merge or
+    /// the merge queue of the base branch. Run `jj spr land --resume` after it
+    /// has been merged to clean up
+    #[clap(long, conflicts_with = "wait")]
+    auto: bool,
+
+    /// Finish landing Pull Requests that GitHub has merged after `land --auto`
+    #[clap(long, conflicts_with_all = ["auto", "wait", "merge_method"])]
+    resume: bool,
+
+    /// Land Pull Requests for commits in range from base to revision, starting
+    /// with the bottom-most one
+    #[clap(long, short = 'a')]
+    all: bool,
+
+    /// Base revision for --all mode (if not specified, uses trunk)
+    #[clap(long)]
+    base: Option<String>,
+
+    /// Jujutsu revset to operate on, e.g. a single revision like '@' or a
+    /// range like 'main..@-'. With --all, the commits from base up to it. If
+    /// not specified, uses '@'.
     #[clap(short = 'r', long)]
     revision: Option<String>,
 }
@@ -31,17 +70,141 @@ pub struct LandOptions {
 pub async fn land(
     opts: LandOptions,
     jj: &crate::jj::Jujutsu,
-    gh: &mut crate::github::GitHub,
+    gh: &mut dyn crate::forge::Forge,
     config: &crate::config::Config,
 ) -> Result<()> {
-    let revision = opts.revision.as_deref().unwrap_or("@");
-    let prepared_commit = jj.get_prepared_commit_for_revision(config, revision)?;
+    let revset = crate::revision_utils::revset_for_options(
+        Some(opts.revision.as_deref().unwrap_or("@")),
+        opts.all,
+        opts.base.as_deref(),
+    );
+    let prepared_commits = jj.get_prepared_commits_for_revset(config, &revset)?;
+
+    if prepared_commits.is_empty() {
+        output("👋", "No commits found - nothing to do. Good bye!")?;
+        return Ok(());
+    }
 
-    // For Jujutsu, we'll determine if this is cherry-pick based on the revision's ancestry
-    // For now, we'll trust the user's --cherry-pick flag
+    // Each landed Pull Request hands over to the next one, so the commits
+    // need to form a single line.
+    if prepared_commits
+        .windows(2)
+        .any(|pair| pair[1].parent_oid != pair[0].oid)
+    {
+        return Err(Error::new(formatdoc!(
+            "The revisions to land do not form a single stack. Land them \
+             one stack at a time."
+        )));
+    }
 
-    write_commit_title(&prepared_commit)?;
+    // Make sure GitHub will accept the merge method before touching any Pull
+    // Request.
+    let merge_method = opts.merge_method.unwrap_or(config.merge_method);
+    let allowed_merge_methods = if opts.resume {
+        // Nothing gets merged when resuming.
+        vec![merge_method]
+    } else {
+        gh.get_allowed_merge_methods().await?
+    };
+    if !allowed_merge_methods.contains(&merge_method) {
+        return Err(Error::new(formatdoc!(
+            "This repository does not allow the '{merge_method}' merge method. \
+             Allowed merge methods: {allowed}",
+            allowed = allowed_merge_methods
+                .iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )));
+    }
 
+    // Land the Pull Requests bottom-up. Each one needs to know about the next
+    // Pull Request in the stack, because that one has to be retargeted to the
+    // master branch before the branches of the landed one get deleted. We stop
+    // at the first Pull Request that cannot be landed.
+    let next_pull_request_numbers = prepared_commits
+        .iter()
+        .skip(1)
+        .map(|pc| pc.pull_request_number)
+        .chain(std::iter::once(None));
+
+    // Load all the Pull Requests at once. This also makes sure that they all
+    // exist before anything gets landed.
+    let pull_request_numbers: Vec<u64> = prepared_commits
+        .iter()
+        .filter_map(|pc| pc.pull_request_number)
+        .collect();
+    let mut pull_requests = gh.get_pull_requests(&pull_request_numbers).await?;
+
+    for (prepared_commit, next_pull_request_number) in
+        zip(prepared_commits.iter(), next_pull_request_numbers)
+    {
+        // Landing the Pull Requests below this one rebased the commit, so
+        // pick up its current version.
+        let prepared_commit = &jj.reload_prepared_commit(config, prepared_commit.oid)?;
+
+        write_commit_title(prepared_commit)?;
+
+        if opts.resume {
+            let merged = resume_impl(
+                jj,
+                gh,
+                config,
+                prepared_commit,
+                &mut pull_requests,
+                next_pull_request_number,
+            )
+            .await?;
+            if !merged {
+                break;
+            }
+
+            // Landing retargets the next Pull Request, so what we loaded
+            // about the ones above is out of date.
+            pull_requests.clear();
+            continue;
+        }
+
+        land_impl(
+            &opts,
+            jj,
+            gh,
+            config,
+            prepared_commit,
+            &mut pull_requests,
+            next_pull_request_number,
+            merge_method,
+        )
+        .await?;
+        pull_requests.clear();
+
+        if opts.auto {
+            // The next Pull Request can only target the master branch once
+            // this one has been merged.
+            if next_pull_request_number.is_some() {
+                output(
+                    "⏸️ ",
+                    "The Pull Requests above this one can be landed once it has been merged",
+                )?;
+            }
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn land_impl(
+    opts: &LandOptions,
+    jj: &crate::jj::Jujutsu,
+    gh: &mut dyn crate::forge::Forge,
+    config: &crate::config::Config,
+    prepared_commit: &PreparedCommit,
+    pull_requests: &mut HashMap<u64, PullRequest>,
+    next_pull_request_number: Option<u64>,
+    merge_method: MergeMethod,
+) -> Result<()> {
     let pull_request_number = if let Some(number) = prepared_commit.pull_request_number {
         output("#️⃣ ", &format!("Pull Request #{}", number))?;
         number
@@ -49,8 +212,11 @@ pub async fn land(
         return Err(Error::new("This commit does not refer to a Pull Request."));
     };
 
-    // Load Pull Request information
-    let pull_request = gh.clone().get_pull_request(pull_request_number).await?;
+    // Load Pull Request information, unless it is loaded already
+    let pull_request = match pull_requests.remove(&pull_request_number) {
+        Some(pull_request) => pull_request,
+        None => gh.get_pull_request(pull_request_number).await?,
+    };
 
     if pull_request.state != PullRequestState::Open {
         return Err(Error::new(formatdoc!(
@@ -68,7 +234,8 @@ pub async fn land(
 
     // Fetch current master from GitHub.
     run_command(
-        tokio::process::Command::new("git")
+        config
+            .git_command()
             .arg("fetch")
             .arg("--no-write-fetch-head")
             .arg("--")
@@ -78,13 +245,55 @@ pub async fn land(
     .await
     .reword("git fetch failed".to_string())?;
 
-    // TODO: Implement Jujutsu-native cherry-pick and merge validation
-    // For now, we'll trust GitHub's merge validation and skip local validation
+    let current_master_oid = jj.resolve_reference(config.master_ref.local())?;
     let base_is_master = pull_request.base.is_master_branch();
 
-    // Skip local cherry-pick validation for Jujutsu workflow
-    // GitHub will validate mergeability during the merge process
-    let merge_matches_cherrypick = true;
+    // Unless we are told to --cherry-pick, the commit must be based on master,
+    // i.e. the commits it is stacked on must have been landed already.
+    if !opts.cherry_pick
+        && jj
+            .git_repo
+            .merge_base(prepared_commit.parent_oid, current_master_oid)?
+            != prepared_commit.parent_oid
+    {
+        return Err(Error::new(formatdoc!(
+            "This commit is not based on the '{master}' branch. Please land \
+             the commits it is based on first, or use --cherry-pick if this \
+             commit is independent of them.",
+            master = config.master_ref.branch_name(),
+        )));
+    }
+
+    // Cherry-pick the local commit onto current master. This is the tree we
+    // want master to have after landing.
+    let index = jj.cherrypick(prepared_commit.oid, current_master_oid)?;
+
+    if index.has_conflicts() {
+        return Err(Error::new(formatdoc!(
+            "This commit cannot be applied on top of the '{master}' branch.
+             Please rebase this commit on top of current \
+             '{remote}/{master}'.{unstacked_hint}",
+            master = config.master_ref.branch_name(),
+            remote = &config.remote_name,
+            unstacked_hint = if !opts.cherry_pick {
+                "\nIf this commit is independent, consider trying --cherry-pick."
+            } else {
+                ""
+            },
+        )));
+    }
+
+    let our_tree_oid = jj.write_index(index)?;
+
+    // Now let's predict what merging the Pull Request into the master branch
+    // would produce.
+    let merge_index = jj.merge_commits(current_master_oid, pull_request.head_oid)?;
+
+    let merge_matches_cherrypick = if merge_index.has_conflicts() {
+        false
+    } else {
+        jj.write_index(merge_index)? == our_tree_oid
+    };
 
     if !merge_matches_cherrypick {
         return Err(Error::new(formatdoc!(
@@ -97,7 +306,7 @@ pub async fn land(
     // Okay, we are confident now that the PR can be merged and the result of
     // that merge would be a master commit with the same tree as if we
     // cherry-picked the commit onto master.
-    let pr_head_oid = pull_request.head_oid;
+    let mut pr_head_oid = pull_request.head_oid;
 
     if !base_is_master {
         // The base of the Pull Request on GitHub is not set to master. This
@@ -129,12 +338,36 @@ pub async fn land(
         // This final merge never introduces any changes to the Pull Request. In
         // fact, the tree that we use for the merge commit is the one we got
         // above from the cherry-picking of this commit on master.
-
-        // TODO: Implement Jujutsu-native merge base and tree comparison
-        // For now, skip the complex merge-in-master logic
-        // This logic would need to be rewritten using jj commands
-
-        // Skip the merge-in-master commit creation for Jujutsu workflow
+        if jj.git_repo.merge_base(pr_head_oid, current_master_oid)? != current_master_oid {
+            let merge_commit_oid = jj.create_derived_commit(
+                pr_head_oid,
+                &format!(
+                    "[spr] landed version\n\nCreated using jj-spr {}",
+                    env!("CARGO_PKG_VERSION"),
+                ),
+                our_tree_oid,
+                &[pr_head_oid, current_master_oid],
+            )?;
+
+            run_command(
+                config
+                    .git_command()
+                    .arg("push")
+                    .arg("--atomic")
+                    .arg("--no-verify")
+                    .arg("--")
+                    .arg(&config.push_remote_name)
+                    .arg(format!(
+                        "{}:{}",
+                        merge_commit_oid,
+                        pull_request.head.on_github()
+                    )),
+            )
+            .await
+            .reword("git push failed".to_string())?;
+
+            pr_head_oid = merge_commit_oid;
+        }
 
         gh.update_pull_request(
             pull_request_number,
@@ -172,9 +405,30 @@ pub async fn land(
                 )));
             }
 
-            // TODO: Implement Jujutsu-native commit fetching and tree comparison
-            // For now, skip the merge commit validation
-            // This would need to be rewritten using jj commands
+            // If GitHub tells us the commit it would create when merging,
+            // make sure it has the tree we expect.
+            if let Some(merge_commit) = mergeability.merge_commit {
+                run_command(
+                    config
+                        .git_command()
+                        .arg("fetch")
+                        .arg("--no-write-fetch-head")
+                        .arg("--")
+                        .arg(&config.remote_name)
+                        .arg(format!("{}", merge_commit)),
+                )
+                .await
+                .reword("git fetch failed".to_string())?;
+
+                if jj.get_tree_oid_for_commit(merge_commit)? != our_tree_oid {
+                    break Err(Error::new(formatdoc!(
+                        "This commit has been updated and/or rebased since the \
+                         pull request was last updated. Please run `spr diff` \
+                         to update the pull request and then try `spr land` \
+                         again!"
+                    )));
+                }
+            }
 
             break Ok(());
         }
@@ -191,38 +445,67 @@ pub async fn land(
     };
 
     let result = match result {
+        Ok(()) if opts.wait => wait_for_checks(gh, pull_request_number, pr_head_oid).await,
+        result => result,
+    };
+
+    let result = match result {
+        Ok(()) if opts.auto => {
+            // Same checks as below, but GitHub merges the Pull Request once
+            // all its requirements are met.
+            hand_off_to_github(
+                gh,
+                pull_request_number,
+                pr_head_oid,
+                merge_method,
+                &pull_request.title,
+                &build_github_body_for_merging(&pull_request.sections),
+            )
+            .await
+            .map(|()| None)
+        }
         Ok(()) => {
             // We have checked that merging the Pull Request branch into the master
             // branch produces the intended result, and that's independent of whether we
             // used a base branch with this Pull Request or not. We have made sure the
             // target of the Pull Request is set to the master branch. So let GitHub do
             // the merge now!
-            octocrab::instance()
-                .pulls(&config.owner, &config.repo)
-                .merge(pull_request_number)
-                .method(octocrab::params::pulls::MergeMethod::Squash)
-                .title(pull_request.title)
-                .message(build_github_body_for_merging(&pull_request.sections))
-                .sha(format!("{}", pr_head_oid))
-                .send()
-                .await
-                .convert()
-                .and_then(|merge| {
-                    if merge.merged {
-                        Ok(merge)
-                    } else {
-                        Err(Error::new(formatdoc!(
-                            "GitHub Pull Request merge failed: {}",
-                            merge.message.unwrap_or_default()
-                        )))
-                    }
-                })
+            gh.merge_pull_request(
+                pull_request_number,
+                merge_method,
+                &pull_request.title,
+                &build_github_body_for_merging(&pull_request.sections),
+                pr_head_oid,
+            )
+            .await
+            .map(Some)
         }
         Err(err) => Err(err),
     };
 
-    let merge = match result {
-        Ok(merge) => merge,
+    let merge_oid = match result {
+        Ok(Some(merge_oid)) => merge_oid,
+        Ok(None) => {
+            // The Pull Request targets the master branch now, so its old base
+            // branch is not needed anymore. Everything else has to wait until
+            // GitHub has merged the Pull Request.
+            // Like below, the result of deleting the branch is ignored.
+            if !base_is_master {
+                config
+                    .git_command()
+                    .arg("push")
+                    .arg("--no-verify")
+                    .arg("--delete")
+                    .arg("--")
+                    .arg(&config.push_remote_name)
+                    .arg(pull_request.base.on_github())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .await?;
+            }
+            return Ok(());
+        }
         Err(mut error) => {
             output("❌", "GitHub Pull Request merge failed")?;
 
@@ -249,47 +532,205 @@ pub async fn land(
 
     output("🛬", "Landed!")?;
 
-    let mut remove_old_branch_child_process = tokio::process::Command::new("git")
-        .arg("push")
-        .arg("--no-verify")
-        .arg("--delete")
-        .arg("--")
-        .arg(&config.remote_name)
-        .arg(pull_request.head.on_github())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    let remove_old_base_branch_child_process = if base_is_master {
-        None
+    let mut old_branches = vec![pull_request.head.clone()];
+    if !base_is_master {
+        old_branches.push(pull_request.base.clone());
+    }
+
+    finish_landing(
+        jj,
+        gh,
+        config,
+        prepared_commit,
+        old_branches,
+        next_pull_request_number,
+        merge_oid,
+    )
+    .await
+}
+
+/// Finish landing a Pull Request that was handed to GitHub with `land --auto`.
+/// Returns whether it has been merged.
+async fn resume_impl(
+    jj: &crate::jj::Jujutsu,
+    gh: &mut dyn crate::forge::Forge,
+    config: &crate::config::Config,
+    prepared_commit: &PreparedCommit,
+    pull_requests: &mut HashMap<u64, PullRequest>,
+    next_pull_request_number: Option<u64>,
+) -> Result<bool> {
+    let pull_request_number = if let Some(number) = prepared_commit.pull_request_number {
+        output("#️⃣ ", &format!("Pull Request #{}", number))?;
+        number
+    } else {
+        return Err(Error::new("This commit does not refer to a Pull Request."));
+    };
+
+    let pull_request = match pull_requests.remove(&pull_request_number) {
+        Some(pull_request) => pull_request,
+        None => gh.get_pull_request(pull_request_number).await?,
+    };
+
+    match (pull_request.state, pull_request.merge_commit) {
+        (_, Some(merge_commit)) => {
+            output("🛬", "Landed!")?;
+
+            // `land --auto` made the Pull Request target the master branch
+            // and deleted its base branch already.
+            finish_landing(
+                jj,
+                gh,
+                config,
+                prepared_commit,
+                vec![pull_request.head.clone()],
+                next_pull_request_number,
+                Some(merge_commit),
+            )
+            .await?;
+
+            Ok(true)
+        }
+        (PullRequestState::Open, None) => {
+            output("⏳", "GitHub has not merged this Pull Request yet")?;
+            Ok(false)
+        }
+        (PullRequestState::Closed, None) => Err(Error::new(
+            "This Pull Request has been closed without being merged.",
+        )),
+    }
+}
+
+/// Let GitHub merge the Pull Request once all its requirements are met. If
+/// the base branch uses a merge queue, the Pull Request is added to it,
+/// otherwise auto-merge is enabled.
+async fn hand_off_to_github(
+    gh: &dyn crate::forge::Forge,
+    pull_request_number: u64,
+    pr_head_oid: git2::Oid,
+    merge_method: MergeMethod,
+    title: &str,
+    body: &str,
+) -> Result<()> {
+    let mergeability = gh
+        .get_pull_request_mergeability(pull_request_number)
+        .await?;
+
+    if mergeability.has_merge_queue {
+        let position = gh
+            .enqueue_pull_request(&mergeability.id, pr_head_oid)
+            .await?;
+        output(
+            "🚂",
+            &match position {
+                Some(position) => format!("Added to the merge queue at position {position}"),
+                None => "Added to the merge queue".to_string(),
+            },
+        )?;
     } else {
-        Some(
-            tokio::process::Command::new("git")
+        gh.enable_auto_merge(&mergeability.id, merge_method, pr_head_oid, title, body)
+            .await?;
+        output("🤖", "Enabled auto-merge")?;
+    }
+
+    output(
+        "⏳",
+        "GitHub will merge the Pull Request when it is ready. Run \
+         `jj spr land --resume` afterwards to finish landing it.",
+    )?;
+
+    Ok(())
+}
+
+/// Clean up after a Pull Request has been merged on GitHub: retarget the Pull
+/// Requests based on it, delete the old branches, and abandon the landed
+/// change locally.
+#[allow(clippy::too_many_arguments)]
+async fn finish_landing(
+    jj: &crate::jj::Jujutsu,
+    gh: &mut dyn crate::forge::Forge,
+    config: &crate::config::Config,
+    prepared_commit: &PreparedCommit,
+    mut old_branches: Vec<GitHubBranch>,
+    next_pull_request_number: Option<u64>,
+    merge_oid: Option<git2::Oid>,
+) -> Result<()> {
+    // The Pull Requests of the child changes, like the next one in the stack,
+    // may be based on the branches of the landed one.
+    let mut dependent_pull_request_numbers: Vec<u64> =
+        next_pull_request_number.into_iter().collect();
+    let children =
+        jj.get_prepared_commits_for_revset(config, &format!("children({})", prepared_commit.oid))?;
+    for number in children.iter().filter_map(|pc| pc.pull_request_number) {
+        if !dependent_pull_request_numbers.contains(&number) {
+            dependent_pull_request_numbers.push(number);
+        }
+    }
+
+    // If a dependent Pull Request uses a base branch, it has to target the
+    // master branch from now on. Retarget it before deleting any branches,
+    // because GitHub closes Pull Requests whose base branch is deleted. Its
+    // old base branch is not needed anymore after that.
+    for number in dependent_pull_request_numbers {
+        let next_base = gh.get_pull_request_mergeability(number).await?.base;
+
+        if !next_base.is_master_branch() {
+            gh.update_pull_request(
+                number,
+                PullRequestUpdate {
+                    base: Some(config.master_ref.branch_name().to_string()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            output(
+                "🎯",
+                &format!(
+                    "Pull Request #{} now targets {}",
+                    number,
+                    config.master_ref.branch_name()
+                ),
+            )?;
+
+            if !old_branches
+                .iter()
+                .any(|branch| branch.branch_name() == next_base.branch_name())
+            {
+                old_branches.push(next_base);
+            }
+        }
+    }
+
+    let remove_old_branch_child_processes = old_branches
+        .iter()
+        .map(|branch| {
+            config
+                .git_command()
                 .arg("push")
                 .arg("--no-verify")
                 .arg("--delete")
                 .arg("--")
-                .arg(&config.remote_name)
-                .arg(pull_request.base.on_github())
+                .arg(&config.push_remote_name)
+                .arg(branch.on_github())
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
-                .spawn()?,
-        )
-    };
+                .spawn()
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
 
     // Rebase us on top of the now-landed commit
-    if let Some(sha) = merge.sha {
+    if let Some(merge_oid) = merge_oid {
         // Try this up to three times, because fetching the very moment after
         // the merge might still not find the new commit.
         for i in 0..3 {
             // Fetch current master and the merge commit from GitHub.
-            let git_fetch = tokio::process::Command::new("git")
+            let git_fetch = config
+                .git_command()
                 .arg("fetch")
                 .arg("--no-write-fetch-head")
                 .arg("--")
                 .arg(&config.remote_name)
                 .arg(config.master_ref.on_github())
-                .arg(&sha)
+                .arg(merge_oid.to_string())
                 .stdout(Stdio::null())
                 .stderr(Stdio::piped())
                 .output()
@@ -301,21 +742,105 @@ pub async fn land(
                 return Err(Error::new("git fetch failed"));
             }
         }
-        // TODO: Implement Jujutsu-native rebase after landing
-        // For now, the user will need to manually rebase after landing
-        output(
-            "⚠️",
-            "Please manually rebase your working copy after landing",
-        )?;
+
+        // Also with --cherry-pick, where the landed change was not based on
+        // master, its descendants move on top of the new master commit.
+        let new_master_oid = jj.resolve_reference(config.master_ref.local())?;
+        if jj.abandon_landed_change(prepared_commit.oid, new_master_oid)? {
+            output("🧹", "Abandoned landed change and rebased its descendants")?;
+        } else {
+            output(
+                "❗",
+                &format!(
+                    "The landed change is not empty on top of {}, so it was \
+                     rebased with its descendants but not abandoned",
+                    config.master_ref.branch_name()
+                ),
+            )?;
+        }
     }
 
-    // Wait for the "git push" to delete the old Pull Request branch to finish,
-    // but ignore the result. GitHub may be configured to delete the branch
-    // automatically, in which case it's gone already and this command fails.
-    remove_old_branch_child_process.wait().await?;
-    if let Some(mut proc) = remove_old_base_branch_child_process {
+    // Wait for the "git push" to delete the old Pull Request branches to
+    // finish, but ignore the result. GitHub may be configured to delete the
+    // branch automatically, in which case it's gone already and this command
+    // fails.
+    for mut proc in remove_old_branch_child_processes {
         proc.wait().await?;
     }
 
     Ok(())
 }
+
+/// Poll the status checks of a Pull Request until they have passed. If the
+/// base branch requires any checks, only those are waited for, otherwise all
+/// checks reported for the head commit are. Fails as soon as one of them fails.
+async fn wait_for_checks(
+    gh: &dyn crate::forge::Forge,
+    pull_request_number: u64,
+    pr_head_oid: git2::Oid,
+) -> Result<()> {
+    let mut delay = Duration::from_secs(5);
+    let mut last_pending = Vec::new();
+
+    loop {
+        let mergeability = gh
+            .get_pull_request_mergeability(pull_request_number)
+            .await?;
+
+        if mergeability.head_oid != pr_head_oid {
+            return Err(Error::new(formatdoc!(
+                "The Pull Request seems to have been updated externally.
+                     Please try again!"
+            )));
+        }
+
+        let any_required = mergeability.checks.iter().any(|check| check.required);
+        let checks: Vec<&StatusCheck> = mergeability
+            .checks
+            .iter()
+            .filter(|check| check.required || !any_required)
+            .collect();
+
+        let failed: Vec<&StatusCheck> = checks
+            .iter()
+            .copied()
+            .filter(|check| check.state == CheckState::Failure)
+            .collect();
+        if !failed.is_empty() {
+            for check in failed {
+                output("❌", &describe_check(check))?;
+            }
+            return Err(Error::new(
+                "Status checks of the Pull Request failed. Please fix them and try again!",
+            ));
+        }
+
+        let pending: Vec<&str> = checks
+            .iter()
+            .filter(|check| check.state == CheckState::Pending)
+            .map(|check| &check.name[..])
+            .collect();
+        if pending.is_empty() {
+            output("✅", "All status checks passed")?;
+            return Ok(());
+        }
+
+        if pending != last_pending {
+            output(
+                "⏳",
+                &format!("Waiting for status checks: {}", pending.join(", ")),
+            )?;
+            last_pending = pending.into_iter().map(String::from).collect();
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_CHECK_POLL_INTERVAL);
+    }
+}
+
+fn describe_check(check: &StatusCheck) -> String {
+    match &check.url {
+        Some(url) => format!("{}: {}", check.name, url),
+        None => check.name.clone(),
+    }
+}
@@ -5,10 +5,10 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+use crate::api::ApiClient;
 use crate::error::Error;
 use crate::error::Result;
 use graphql_client::{GraphQLQuery, Response};
-use reqwest;
 
 #[allow(clippy::upper_case_acronyms)]
 type URI = String;
@@ -20,7 +20,7 @@ type URI = String;
 )]
 pub struct SearchQuery;
 
-pub async fn list(graphql_client: reqwest::Client, config: &crate::config::Config) -> Result<()> {
+pub async fn list(api: ApiClient, config: &crate::config::Config) -> Result<()> {
     let variables = search_query::Variables {
         query: format!(
             "repo:{}/{} is:open is:pr author:@me archived:false",
@@ -28,10 +28,8 @@ pub async fn list(graphql_client: reqwest::Client, config: &crate::config::Confi
         ),
     };
     let request_body = SearchQuery::build_query(variables);
-    let res = graphql_client
-        .post("https://api.github.com/graphql")
-        .json(&request_body)
-        .send()
+    let res = api
+        .send_idempotent(api.post(&config.graphql_url()).json(&request_body))
         .await?;
     let response_body: Response<search_query::ResponseData> = res.json().await?;
 
@@ -7,9 +7,12 @@
 
 pub mod amend;
 pub mod close;
+pub mod config;
 pub mod diff;
 pub mod format;
 pub mod init;
 pub mod land;
 pub mod list;
 pub mod patch;
+pub mod status;
+pub mod sync;
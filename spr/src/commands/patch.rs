@@ -5,37 +5,130 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use crate::error::Result;
+use crate::{
+    error::{Result, ResultExt},
+    message::{MessageSection, build_commit_message},
+    output::output,
+    utils::run_command,
+};
 
 #[derive(Debug, clap::Parser)]
 pub struct PatchOptions {
     /// Pull Request number
     pull_request: u64,
 
-    /// Name of the branch to be created. Defaults to `PR-<number>`
+    /// Name of the bookmark to be created. Defaults to `PR-<number>`
     #[clap(long)]
     branch_name: Option<String>,
 
-    /// If given, create new branch but do not check out
+    /// If given, create the new change but do not start a working copy change
+    /// on top of it
     #[clap(long)]
     no_checkout: bool,
 }
 
 pub async fn patch(
-    _opts: PatchOptions,
-    _jj: &crate::jj::Jujutsu,
-    _gh: &mut crate::github::GitHub,
-    _config: &crate::config::Config,
+    opts: PatchOptions,
+    jj: &crate::jj::Jujutsu,
+    gh: &mut dyn crate::forge::Forge,
+    config: &crate::config::Config,
 ) -> Result<()> {
-    // TODO: Implement Jujutsu-native patch functionality
-    // This command needs to be completely rewritten for Jujutsu workflow
-    // The current implementation uses complex Git operations that need
-    // to be translated to Jujutsu equivalents
-
-    use crate::error::Error;
-    Err(Error::new(
-        "The patch command is not yet implemented for Jujutsu workflow. \
-         Please use the GitHub web interface to create branches from pull requests for now."
-            .to_string(),
-    ))
+    // Loading the Pull Request also fetches its head and base branches from
+    // GitHub.
+    let pr = gh.get_pull_request(opts.pull_request).await?;
+    output(
+        "#️⃣ ",
+        &format!(
+            "Pull Request #{}: {}",
+            pr.number,
+            pr.sections
+                .get(&MessageSection::Title)
+                .map(|s| &s[..])
+                .unwrap_or("(no title)")
+        ),
+    )?;
+
+    let branch_name = if let Some(name) = opts.branch_name {
+        name
+    } else {
+        jj.get_pr_patch_branch_name(pr.number)?
+    };
+
+    let patch_commit_oid = if let Some(oid) = pr.merge_commit {
+        output("❗", "Pull Request has been merged")?;
+
+        run_command(
+            config
+                .git_command()
+                .arg("fetch")
+                .arg("--no-write-fetch-head")
+                .arg("--")
+                .arg(&config.remote_name)
+                .arg(format!("{}", oid)),
+        )
+        .await
+        .reword("git fetch failed".to_string())?;
+
+        oid
+    } else {
+        // Fetch current master from GitHub.
+        run_command(
+            config
+                .git_command()
+                .arg("fetch")
+                .arg("--no-write-fetch-head")
+                .arg("--")
+                .arg(&config.remote_name)
+                .arg(config.master_ref.on_github()),
+        )
+        .await
+        .reword("git fetch failed".to_string())?;
+
+        // Current oid of the master branch
+        let current_master_oid = jj.resolve_reference(config.master_ref.local())?;
+
+        // The parent commit to base the new change on shall be the master
+        // commit this PR is based on
+        let mut pr_master_base = jj.git_repo.merge_base(pr.head_oid, current_master_oid)?;
+
+        // If the PR uses a base branch, we need a commit between the master
+        // base and the new change that brings in the changes on that base
+        // branch. This way the new change contains exactly the changes of the
+        // Pull Request. A PR against the master branch needs no such commit,
+        // even if the master branch has moved on since it branched off.
+        if !pr.base.is_master_branch() {
+            pr_master_base = jj.create_derived_commit(
+                pr.base_oid,
+                &format!(
+                    "[spr] Changes on base branch of Pull Request #{}\n\n\
+                     Created using jj-spr {}",
+                    pr.number,
+                    env!("CARGO_PKG_VERSION"),
+                ),
+                jj.get_tree_oid_for_commit(pr.base_oid)?,
+                &[pr_master_base],
+            )?;
+        }
+
+        // The description of the new change is taken from the Pull Request,
+        // including the 'Pull Request' section, so that `spr diff` and
+        // `spr amend` can pick up the change later.
+        jj.create_derived_commit(
+            pr.head_oid,
+            &build_commit_message(&pr.sections),
+            jj.get_tree_oid_for_commit(pr.head_oid)?,
+            &[pr_master_base],
+        )?
+    };
+
+    jj.create_bookmark(&branch_name, patch_commit_oid)?;
+    output("🌱", &format!("Created new bookmark: {}", &branch_name))?;
+
+    if !opts.no_checkout {
+        // Start a new working copy change on top of the patched change
+        jj.new_change_on(&branch_name)?;
+        output("✅", "Checked out")?;
+    }
+
+    Ok(())
 }
@@ -0,0 +1,205 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::{
+    error::Result,
+    forge::{CheckState, PullRequestState, PullRequestStatus, ReviewStatus},
+    message::MessageSection,
+    output::output,
+};
+
+#[derive(Debug, clap::Parser)]
+pub struct StatusOptions {
+    /// Jujutsu revset of the changes to show. If not specified, uses
+    /// 'trunk()..@'.
+    #[clap(short = 'r', long)]
+    revision: Option<String>,
+}
+
+pub async fn status(
+    opts: StatusOptions,
+    jj: &crate::jj::Jujutsu,
+    gh: &mut dyn crate::forge::Forge,
+    config: &crate::config::Config,
+) -> Result<()> {
+    let revset = opts.revision.as_deref().unwrap_or("trunk()..@");
+    let prepared_commits = jj.get_prepared_commits_for_revset(config, revset)?;
+
+    if prepared_commits.is_empty() {
+        output("👋", "No commits found - nothing to do. Good bye!")?;
+        return Ok(());
+    }
+
+    let pull_request_numbers: Vec<u64> = prepared_commits
+        .iter()
+        .filter_map(|pc| pc.pull_request_number)
+        .collect();
+    let pull_requests = gh.get_pull_request_statuses(&pull_request_numbers).await?;
+
+    let term = console::Term::stdout();
+
+    // Like `jj log`, show the top of the stack first.
+    for prepared_commit in prepared_commits.iter().rev() {
+        term.write_line(&format!(
+            "{} {}",
+            console::style(prepared_commit.short_change_id()).magenta(),
+            console::style(
+                prepared_commit
+                    .message
+                    .get(&MessageSection::Title)
+                    .map(|s| &s[..])
+                    .unwrap_or("(untitled)"),
+            )
+            .yellow()
+        ))?;
+
+        let Some(number) = prepared_commit.pull_request_number else {
+            term.write_line(&format!("    {}", console::style("no Pull Request").dim()))?;
+            continue;
+        };
+
+        let Some(pull_request) = pull_requests.get(&number) else {
+            term.write_line(&format!(
+                "    #{number} {}",
+                console::style("not found on GitHub").red()
+            ))?;
+            continue;
+        };
+
+        let local_tree_oid = jj.get_tree_oid_for_commit(prepared_commit.oid)?;
+        let mut pull_request = pull_request.clone();
+        // Not every forge reports the tree of the head commit, but it can be
+        // looked up locally if the commit has been fetched.
+        if pull_request.head_tree_oid.is_none() {
+            pull_request.head_tree_oid = jj.get_tree_oid_for_commit(pull_request.head_oid).ok();
+        }
+        term.write_line(&format!(
+            "    {}",
+            describe_pull_request(&pull_request, local_tree_oid)
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn describe_pull_request(pull_request: &PullRequestStatus, local_tree_oid: git2::Oid) -> String {
+    let state = if pull_request.merged {
+        console::style("merged").magenta()
+    } else if pull_request.state == PullRequestState::Closed {
+        console::style("closed").red()
+    } else if pull_request.is_draft {
+        console::style("draft").dim()
+    } else {
+        console::style("open").green()
+    };
+
+    let mut parts = vec![format!("#{}", pull_request.number), state.to_string()];
+
+    // Review, checks and mergeability only matter while the Pull Request is
+    // open.
+    if pull_request.state == PullRequestState::Open {
+        parts.push(
+            match pull_request.review_status {
+                Some(ReviewStatus::Approved) => console::style("approved").green(),
+                Some(ReviewStatus::Rejected) => console::style("changes requested").red(),
+                Some(ReviewStatus::Requested) => console::style("review required").yellow(),
+                None => console::style("no review decision").dim(),
+            }
+            .to_string(),
+        );
+        parts.push(
+            match pull_request.checks {
+                Some(CheckState::Success) => console::style("checks passed").green(),
+                Some(CheckState::Failure) => console::style("checks failed").red(),
+                Some(CheckState::Pending) => console::style("checks pending").yellow(),
+                None => console::style("no checks").dim(),
+            }
+            .to_string(),
+        );
+        parts.push(
+            match pull_request.mergeable {
+                Some(true) => console::style("mergeable").green(),
+                Some(false) => console::style("conflicting").red(),
+                None => console::style("mergeability unknown").dim(),
+            }
+            .to_string(),
+        );
+        if pull_request.head_tree_oid != Some(local_tree_oid) {
+            parts.push(
+                console::style("local change differs, run `jj spr diff`")
+                    .yellow()
+                    .to_string(),
+            );
+        }
+    }
+
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pull_request_status() -> PullRequestStatus {
+        PullRequestStatus {
+            number: 42,
+            title: "Add a feature".into(),
+            body: String::new(),
+            state: PullRequestState::Open,
+            is_draft: false,
+            merged: false,
+            review_status: Some(ReviewStatus::Approved),
+            checks: Some(CheckState::Success),
+            mergeable: Some(true),
+            head_oid: git2::Oid::zero(),
+            head_tree_oid: Some(git2::Oid::zero()),
+        }
+    }
+
+    #[test]
+    fn test_describe_open_pull_request() {
+        console::set_colors_enabled(false);
+
+        assert_eq!(
+            describe_pull_request(&pull_request_status(), git2::Oid::zero()),
+            "#42, open, approved, checks passed, mergeable"
+        );
+
+        let tree_oid = git2::Oid::from_str("1111111111111111111111111111111111111111").unwrap();
+        assert_eq!(
+            describe_pull_request(
+                &PullRequestStatus {
+                    is_draft: true,
+                    review_status: None,
+                    checks: Some(CheckState::Failure),
+                    mergeable: None,
+                    ..pull_request_status()
+                },
+                tree_oid
+            ),
+            "#42, draft, no review decision, checks failed, mergeability unknown, \
+             local change differs, run `jj spr diff`"
+        );
+    }
+
+    #[test]
+    fn test_describe_merged_pull_request() {
+        console::set_colors_enabled(false);
+
+        assert_eq!(
+            describe_pull_request(
+                &PullRequestStatus {
+                    state: PullRequestState::Closed,
+                    merged: true,
+                    ..pull_request_status()
+                },
+                git2::Oid::zero()
+            ),
+            "#42, merged"
+        );
+    }
+}
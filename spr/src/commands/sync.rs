@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::{
+    error::{Result, ResultExt},
+    forge::PullRequestState,
+    message::MessageSection,
+    output::{output, write_commit_title},
+    utils::run_command,
+};
+
+/// The changes `sync` looks at: all of the user's changes that are not on
+/// trunk yet. The commits spr pushed to the Pull Request branches are the
+/// user's too, but jj imports them as immutable remote bookmarks, and they
+/// are not the local changes.
+const SYNC_REVSET: &str = "mine() & mutable() & ~::trunk()";
+
+#[derive(Debug, clap::Parser)]
+pub struct SyncOptions {
+    /// Do not rebase the remaining changes onto trunk
+    #[clap(long)]
+    no_rebase: bool,
+}
+
+pub async fn sync(
+    opts: SyncOptions,
+    jj: &crate::jj::Jujutsu,
+    gh: &mut dyn crate::forge::Forge,
+    config: &crate::config::Config,
+) -> Result<()> {
+    // Fetch current master from GitHub, so that trunk() includes everything
+    // that has been landed.
+    run_command(
+        config
+            .git_command()
+            .arg("fetch")
+            .arg("--no-write-fetch-head")
+            .arg("--")
+            .arg(&config.remote_name)
+            .arg(config.master_ref.on_github()),
+    )
+    .await
+    .reword("git fetch failed".to_string())?;
+
+    let mut prepared_commits = jj.get_prepared_commits_for_revset(config, SYNC_REVSET)?;
+    let mut merged_commits = Vec::new();
+
+    // Load all the Pull Requests at once.
+    let pull_request_numbers: Vec<u64> = prepared_commits
+        .iter()
+        .filter_map(|pc| pc.pull_request_number)
+        .collect();
+    let pull_requests = gh.get_pull_requests(&pull_request_numbers).await?;
+
+    for prepared_commit in prepared_commits.iter_mut() {
+        let Some(pull_request) = prepared_commit
+            .pull_request_number
+            .and_then(|number| pull_requests.get(&number))
+        else {
+            continue;
+        };
+
+        write_commit_title(prepared_commit)?;
+        output("#️⃣ ", &format!("Pull Request #{}", pull_request.number))?;
+
+        if pull_request.merge_commit.is_some() {
+            output("🛬", "Merged - abandoning the local change")?;
+            merged_commits.push(prepared_commit.oid);
+        } else if pull_request.state == PullRequestState::Closed {
+            output("📕", "Closed - removing it from the commit message")?;
+
+            // Same as `spr close`: these sections are not relevant anymore.
+            prepared_commit.message.remove(&MessageSection::PullRequest);
+            prepared_commit.message.remove(&MessageSection::ReviewedBy);
+            prepared_commit.message_changed = true;
+        } else {
+            output("📖", "Open")?;
+        }
+    }
+
+    jj.rewrite_commit_messages(&mut prepared_commits)?;
+
+    for commit_oid in merged_commits {
+        jj.abandon(commit_oid)?;
+    }
+
+    if !opts.no_rebase
+        && !jj
+            .get_prepared_commits_for_revset(config, SYNC_REVSET)?
+            .is_empty()
+    {
+        jj.rebase_roots_onto(SYNC_REVSET, "trunk()")?;
+        output("🔁", "Rebased the remaining changes onto trunk")?;
+    }
+
+    Ok(())
+}
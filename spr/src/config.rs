@@ -5,9 +5,24 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use std::collections::HashSet;
+mod auth;
+mod loader;
 
-use crate::{error::Result, github::GitHubBranch, utils::slugify};
+use std::{collections::HashSet, path::PathBuf};
+
+use crate::{
+    error::{Error, Result, ResultExt},
+    forge::{ForgeKind, MergeMethod},
+    github::GitHubBranch,
+    message::MessageSection,
+    utils::slugify,
+};
+
+pub use auth::{
+    AuthTokenSource, describe_auth_token_source, get_auth_token_with_source, git_credential_token,
+    github_cli_auth_token, local_auth_token,
+};
+pub use loader::{KEYS, REPOSITORY_CONFIG_FILE, Settings, ValueSource, toml_value_string};
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -17,9 +32,36 @@ pub struct Config {
     pub master_ref: GitHubBranch,
     pub branch_prefix: String,
     pub require_approval: bool,
+    pub merge_method: MergeMethod,
+    /// Whether new Pull Requests are created as drafts
+    pub draft: bool,
+    /// Sections that commit messages must have, in addition to the title
+    pub required_sections: Vec<MessageSection>,
+    /// Host name of the forge instance, e.g. 'github.com', the host of a GitHub
+    /// Enterprise Server, 'gitlab.com' or the host of a Gitea instance
+    pub host: String,
+    /// The service hosting the repository
+    pub forge: ForgeKind,
+    /// Base URL of the forge's API, overriding the one derived from
+    /// `host`, e.g. to talk to a local test server
+    pub api_base_url: Option<String>,
+    /// The Git repository backing the Jujutsu repository. Git commands run
+    /// in the current directory if this is not set.
+    pub git_dir: Option<PathBuf>,
+    /// Name of the Git remote the Pull Request branches are pushed to. This
+    /// is `remote_name`, unless `spr.pushRemote` names another remote, e.g.
+    /// one pointing to a fork of the repository.
+    pub push_remote_name: String,
+    /// Owner of the fork `push_remote_name` points to, if it is not the
+    /// repository itself
+    pub fork_owner: Option<String>,
 }
 
+/// Host name of the public GitHub instance
+pub const DEFAULT_GITHUB_HOST: &str = "github.com";
+
 impl Config {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         owner: String,
         repo: String,
@@ -27,22 +69,117 @@ impl Config {
         master_branch: String,
         branch_prefix: String,
         require_approval: bool,
+        merge_method: MergeMethod,
+        draft: bool,
+        required_sections: Vec<MessageSection>,
+        host: String,
+        forge: ForgeKind,
+        api_base_url: Option<String>,
     ) -> Self {
         let master_ref =
             GitHubBranch::new_from_branch_name(&master_branch, &remote_name, &master_branch);
         Self {
             owner,
             repo,
+            push_remote_name: remote_name.clone(),
             remote_name,
             master_ref,
             branch_prefix,
             require_approval,
+            merge_method,
+            draft,
+            required_sections,
+            host,
+            forge,
+            api_base_url: api_base_url.map(|url| url.trim_end_matches('/').to_string()),
+            git_dir: None,
+            fork_owner: None,
+        }
+    }
+
+    /// Push Pull Request branches to `push_remote_name`. If that remote
+    /// points to a fork, Pull Requests are opened from the fork against the
+    /// repository, so contributors without write access can use spr.
+    pub fn set_push_remote(
+        &mut self,
+        git_repo: &git2::Repository,
+        push_remote_name: String,
+    ) -> Result<()> {
+        let remote = git_repo
+            .find_remote(&push_remote_name)
+            .reword(format!("Remote '{push_remote_name}' does not exist"))?;
+        let owner = remote
+            .url()
+            .and_then(parse_remote_url)
+            .and_then(|(_, repository)| {
+                repository
+                    .rsplit_once('/')
+                    .map(|(owner, _)| owner.to_string())
+            })
+            .ok_or_else(|| {
+                Error::new(format!(
+                    "Could not tell the repository of remote '{push_remote_name}' from its URL"
+                ))
+            })?;
+
+        self.fork_owner = if owner.eq_ignore_ascii_case(&self.owner) {
+            None
+        } else if self.forge == ForgeKind::GitHub {
+            Some(owner)
+        } else {
+            return Err(Error::new(
+                "Pushing to a fork is only supported on GitHub".to_string(),
+            ));
+        };
+        self.push_remote_name = push_remote_name;
+
+        Ok(())
+    }
+
+    /// A `git` command that works on the Git repository backing the Jujutsu
+    /// repository. That is not the repository Git finds in the current
+    /// directory, unless the Jujutsu repository is colocated.
+    pub fn git_command(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new("git");
+        if let Some(git_dir) = &self.git_dir {
+            command.arg("--git-dir").arg(git_dir);
+        }
+        command
+    }
+
+    /// Base URL of the REST API of the forge
+    pub fn api_url(&self) -> String {
+        if let Some(url) = &self.api_base_url {
+            return url.clone();
+        }
+
+        match self.forge {
+            ForgeKind::GitHub => github_api_url(&self.host),
+            ForgeKind::GitLab => format!("https://{}/api/v4", self.host),
+            ForgeKind::Gitea => format!("https://{}/api/v1", self.host),
+        }
+    }
+
+    /// URL of the GitHub GraphQL API
+    pub fn graphql_url(&self) -> String {
+        if let Some(url) = &self.api_base_url {
+            format!("{url}/graphql")
+        } else if self.host.eq_ignore_ascii_case(DEFAULT_GITHUB_HOST) {
+            "https://api.github.com/graphql".to_string()
+        } else {
+            format!("https://{}/api/graphql", self.host)
         }
     }
 
     pub fn pull_request_url(&self, number: u64) -> String {
+        let path = match self.forge {
+            ForgeKind::GitHub => "pull",
+            ForgeKind::GitLab => "-/merge_requests",
+            ForgeKind::Gitea => "pulls",
+        };
         format!(
-            "https://github.com/{owner}/{repo}/pull/{number}",
+            "https://{host}/{owner}/{repo}/{path}/{number}",
+            host = &self.host,
             owner = &self.owner,
             repo = &self.repo
         )
@@ -59,15 +196,27 @@ impl Config {
             return Some(caps.get(1).unwrap().as_str().parse().unwrap());
         }
 
-        let regex = lazy_regex::regex!(
-            r#"^\s*https?://github.com/([\w\-\.]+)/([\w\-\.]+)/pull/(\d+)([/?#].*)?\s*$"#
-        );
+        let regex = match self.forge {
+            ForgeKind::GitHub => lazy_regex::regex!(
+                r#"^\s*https?://([^/\s]+)/([\w\-\.]+)/([\w\-\.]+)/pull/(\d+)([/?#].*)?\s*$"#
+            ),
+            // GitLab projects can be nested in subgroups
+            ForgeKind::GitLab => lazy_regex::regex!(
+                r#"^\s*https?://([^/\s]+)/([\w\-\./]+)/([\w\-\.]+)/-/merge_requests/(\d+)([/?#].*)?\s*$"#
+            ),
+            ForgeKind::Gitea => lazy_regex::regex!(
+                r#"^\s*https?://([^/\s]+)/([\w\-\.]+)/([\w\-\.]+)/pulls/(\d+)([/?#].*)?\s*$"#
+            ),
+        };
         let m = regex.captures(text);
         if let Some(caps) = m
-            && self.owner == caps.get(1).unwrap().as_str()
-            && self.repo == caps.get(2).unwrap().as_str()
+            && self
+                .host
+                .eq_ignore_ascii_case(caps.get(1).unwrap().as_str())
+            && self.owner == caps.get(2).unwrap().as_str()
+            && self.repo == caps.get(3).unwrap().as_str()
         {
-            return Some(caps.get(3).unwrap().as_str().parse().unwrap());
+            return Some(caps.get(4).unwrap().as_str().parse().unwrap());
         }
 
         None
@@ -88,8 +237,17 @@ impl Config {
         )
     }
 
+    /// Whether `branch_name` is one of the base branches spr creates for Pull
+    /// Requests that are not directly based on master, as opposed to e.g. the
+    /// branch of another Pull Request
+    pub fn is_base_branch_name(&self, branch_name: &str) -> bool {
+        branch_name
+            .strip_prefix(&self.branch_prefix)
+            .is_some_and(|name| name.starts_with(&format!("{}.", self.master_ref.branch_name())))
+    }
+
     fn find_unused_branch_name(&self, existing_ref_names: &HashSet<String>, slug: &str) -> String {
-        let remote_name = &self.remote_name;
+        let remote_name = &self.push_remote_name;
         let branch_prefix = &self.branch_prefix;
         let mut branch_name = format!("{branch_prefix}{slug}");
         let mut suffix = 0;
@@ -107,94 +265,54 @@ impl Config {
     }
 
     pub fn new_github_branch_from_ref(&self, ghref: &str) -> Result<GitHubBranch> {
-        GitHubBranch::new_from_ref(ghref, &self.remote_name, self.master_ref.branch_name())
+        let branch_name = ghref.strip_prefix("refs/heads/").unwrap_or(ghref);
+        GitHubBranch::new_from_ref(
+            ghref,
+            self.branch_remote_name(branch_name),
+            self.master_ref.branch_name(),
+        )
     }
 
     pub fn new_github_branch(&self, branch_name: &str) -> GitHubBranch {
         GitHubBranch::new_from_branch_name(
             branch_name,
-            &self.remote_name,
+            self.branch_remote_name(branch_name),
             self.master_ref.branch_name(),
         )
     }
-}
 
-pub enum AuthTokenSource {
-    Config(String),
-    GitHubCLI(String),
-}
-
-impl AuthTokenSource {
-    pub fn token(&self) -> &String {
-        match self {
-            AuthTokenSource::Config(token) | AuthTokenSource::GitHubCLI(token) => token,
+    /// The remote a branch lives on: the master branch is on the
+    /// repository's remote, all the branches spr creates on the push remote
+    fn branch_remote_name(&self, branch_name: &str) -> &str {
+        if branch_name == self.master_ref.branch_name() {
+            &self.remote_name
+        } else {
+            &self.push_remote_name
         }
     }
 }
 
-pub fn get_auth_token(git_config: &git2::Config) -> Option<String> {
-    get_auth_token_with_source(git_config).map(|v| v.token().to_owned())
+/// Extract the host and the 'OWNER/REPOSITORY' part from the URL of a Git
+/// remote, e.g. 'git@github.com:owner/repo.git' or
+/// 'https://github.example.com/owner/repo'.
+pub fn parse_remote_url(url: &str) -> Option<(String, String)> {
+    let regex = lazy_regex::regex!(
+        r#"^(?:[a-z+]+://)?(?:[^@/]+@)?([^/:]+)(?::\d+)?[/:]([\w\-\.]+/[\w\-\.]+?)(?:\.git)?/?$"#
+    );
+    let caps = regex.captures(url)?;
+    Some((
+        caps.get(1).unwrap().as_str().to_string(),
+        caps.get(2).unwrap().as_str().to_string(),
+    ))
 }
 
-pub fn get_auth_token_with_source(git_config: &git2::Config) -> Option<AuthTokenSource> {
-    // Prefer the configured token if it exists
-    if let Some(token) = get_config_value("spr.githubAuthToken", git_config) {
-        return Some(AuthTokenSource::Config(token));
-    }
-
-    // Try to get a token from the gh CLI
-    let output = std::process::Command::new("gh")
-        .args(["auth", "token"])
-        .stdout(std::process::Stdio::piped())
-        .output()
-        .ok()?;
-
-    if output.status.success() {
-        Some(AuthTokenSource::GitHubCLI(
-            String::from_utf8(output.stdout).ok()?.trim().to_owned(),
-        ))
+/// Base URL of the REST API of the GitHub instance on the given host
+pub fn github_api_url(github_host: &str) -> String {
+    if github_host.eq_ignore_ascii_case(DEFAULT_GITHUB_HOST) {
+        "https://api.github.com".to_string()
     } else {
-        None
-    }
-}
-
-// Helper function to get config value from jj first, then git
-pub fn get_config_value(key: &str, git_config: &git2::Config) -> Option<String> {
-    // Try jj config first
-    if let Ok(output) = std::process::Command::new("jj")
-        .args(["config", "get", key])
-        .output()
-        && output.status.success()
-        && let Ok(value) = String::from_utf8(output.stdout)
-    {
-        let trimmed = value.trim();
-        if !trimmed.is_empty() {
-            return Some(trimmed.to_string());
-        }
-    }
-
-    // Fall back to git config
-    git_config.get_string(key).ok()
-}
-
-pub fn get_config_bool(key: &str, git_config: &git2::Config) -> Option<bool> {
-    // Try jj config first
-    if let Ok(output) = std::process::Command::new("jj")
-        .args(["config", "get", key])
-        .output()
-        && output.status.success()
-        && let Ok(value) = String::from_utf8(output.stdout)
-    {
-        let trimmed = value.trim().to_lowercase();
-        if trimmed == "true" {
-            return Some(true);
-        } else if trimmed == "false" {
-            return Some(false);
-        }
+        format!("https://{}/api/v3", github_host)
     }
-
-    // Fall back to git config
-    git_config.get_bool(key).ok()
 }
 
 /// Helper function to set config value in jj (repo-level)
@@ -229,6 +347,12 @@ mod tests {
             "master".into(),
             "spr/foo/".into(),
             false,
+            MergeMethod::Squash,
+            false,
+            Vec::new(),
+            DEFAULT_GITHUB_HOST.into(),
+            ForgeKind::GitHub,
+            None,
         )
     }
 
@@ -343,6 +467,73 @@ mod tests {
         assert!(result.is_err(), "Should fail for invalid repo path");
     }
 
+    #[test]
+    fn test_parse_remote_url() {
+        let parsed =
+            |host: &str, repository: &str| Some((host.to_string(), repository.to_string()));
+
+        assert_eq!(
+            parse_remote_url("git@github.com:acme/codez.git"),
+            parsed("github.com", "acme/codez")
+        );
+        assert_eq!(
+            parse_remote_url("https://github.com/acme/codez"),
+            parsed("github.com", "acme/codez")
+        );
+        assert_eq!(
+            parse_remote_url("https://ghe.example.com/acme/codez.git"),
+            parsed("ghe.example.com", "acme/codez")
+        );
+        assert_eq!(
+            parse_remote_url("ssh://git@ghe.example.com:2222/acme/codez.git"),
+            parsed("ghe.example.com", "acme/codez")
+        );
+        assert_eq!(parse_remote_url("/local/path"), None);
+    }
+
+    #[test]
+    fn test_set_push_remote() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let repo = git2::Repository::init(temp_dir.path()).unwrap();
+        repo.remote("origin", "git@github.com:acme/codez.git")
+            .unwrap();
+        repo.remote("mirror", "https://github.com/Acme/codez")
+            .unwrap();
+        repo.remote("fork", "git@github.com:contributor/codez.git")
+            .unwrap();
+        repo.remote("local", "/local/path").unwrap();
+
+        let mut config = config_factory();
+        assert_eq!(config.push_remote_name, "origin");
+
+        // Another remote of the same repository is not a fork.
+        config.set_push_remote(&repo, "mirror".into()).unwrap();
+        assert_eq!(config.push_remote_name, "mirror");
+        assert_eq!(config.fork_owner, None);
+
+        config.set_push_remote(&repo, "fork".into()).unwrap();
+        assert_eq!(config.push_remote_name, "fork");
+        assert_eq!(config.fork_owner.as_deref(), Some("contributor"));
+
+        // The master branch is still fetched from the repository, the branches
+        // spr creates from the fork.
+        assert_eq!(config.master_ref.local(), "refs/remotes/origin/master");
+        let branch = config.new_github_branch("spr/foo/bar");
+        assert_eq!(branch.remote_name(), "fork");
+        assert_eq!(branch.local(), "refs/remotes/fork/spr/foo/bar");
+        let branch = config
+            .new_github_branch_from_ref("refs/heads/master")
+            .unwrap();
+        assert_eq!(branch.remote_name(), "origin");
+
+        assert!(config.set_push_remote(&repo, "missing".into()).is_err());
+        assert!(config.set_push_remote(&repo, "local".into()).is_err());
+
+        let mut config = config_factory();
+        config.forge = ForgeKind::Gitea;
+        assert!(config.set_push_remote(&repo, "fork".into()).is_err());
+    }
+
     #[test]
     fn test_pull_request_url() {
         let gh = config_factory();
@@ -401,4 +592,126 @@ mod tests {
             Some(123)
         );
     }
+
+    #[test]
+    fn test_github_enterprise_urls() {
+        let gh = Config {
+            host: "ghe.example.com".into(),
+            ..config_factory()
+        };
+
+        assert_eq!(&gh.api_url(), "https://ghe.example.com/api/v3");
+        assert_eq!(&gh.graphql_url(), "https://ghe.example.com/api/graphql");
+        assert_eq!(
+            &gh.pull_request_url(123),
+            "https://ghe.example.com/acme/codez/pull/123"
+        );
+        assert_eq!(
+            gh.parse_pull_request_field("https://ghe.example.com/acme/codez/pull/123"),
+            Some(123)
+        );
+        assert_eq!(
+            gh.parse_pull_request_field("https://github.com/acme/codez/pull/123"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_base_branch_name() {
+        let gh = config_factory();
+        let base_branch_name = gh.get_base_branch_name(&HashSet::new(), "Add a feature");
+
+        assert!(gh.is_base_branch_name(&base_branch_name));
+        assert!(
+            !gh.is_base_branch_name(
+                &gh.get_new_branch_name(&HashSet::new(), "master.Add a feature")
+            )
+        );
+        assert!(!gh.is_base_branch_name("master.add-a-feature"));
+    }
+
+    #[test]
+    fn test_github_host_case_insensitive() {
+        let gh = Config {
+            host: "GitHub.com".into(),
+            ..config_factory()
+        };
+
+        assert_eq!(&gh.api_url(), "https://api.github.com");
+        assert_eq!(&gh.graphql_url(), "https://api.github.com/graphql");
+    }
+
+    #[test]
+    fn test_gitlab_urls() {
+        let gl = Config {
+            owner: "acme/platform".into(),
+            host: "gitlab.com".into(),
+            forge: ForgeKind::GitLab,
+            ..config_factory()
+        };
+
+        assert_eq!(&gl.api_url(), "https://gitlab.com/api/v4");
+        assert_eq!(
+            &gl.pull_request_url(123),
+            "https://gitlab.com/acme/platform/codez/-/merge_requests/123"
+        );
+        assert_eq!(
+            gl.parse_pull_request_field(
+                "https://gitlab.com/acme/platform/codez/-/merge_requests/123/diffs"
+            ),
+            Some(123)
+        );
+        assert_eq!(
+            gl.parse_pull_request_field("https://gitlab.com/acme/codez/-/merge_requests/123"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_gitea_urls() {
+        let gt = Config {
+            host: "codeberg.org".into(),
+            forge: ForgeKind::Gitea,
+            ..config_factory()
+        };
+
+        assert_eq!(&gt.api_url(), "https://codeberg.org/api/v1");
+        assert_eq!(
+            &gt.pull_request_url(123),
+            "https://codeberg.org/acme/codez/pulls/123"
+        );
+        assert_eq!(
+            gt.parse_pull_request_field("https://codeberg.org/acme/codez/pulls/123/files"),
+            Some(123)
+        );
+        assert_eq!(
+            gt.parse_pull_request_field("https://codeberg.org/acme/codez/pull/123"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_api_base_url_override() {
+        let gh = crate::config::Config::new(
+            "acme".into(),
+            "codez".into(),
+            "origin".into(),
+            "master".into(),
+            "spr/foo/".into(),
+            false,
+            MergeMethod::Squash,
+            false,
+            Vec::new(),
+            DEFAULT_GITHUB_HOST.into(),
+            ForgeKind::GitHub,
+            Some("http://127.0.0.1:8080/".into()),
+        );
+
+        assert_eq!(&gh.api_url(), "http://127.0.0.1:8080");
+        assert_eq!(&gh.graphql_url(), "http://127.0.0.1:8080/graphql");
+        assert_eq!(
+            &gh.pull_request_url(123),
+            "https://github.com/acme/codez/pull/123"
+        );
+    }
 }
@@ -0,0 +1,502 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Finding the token spr authenticates to the forge with
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::Settings;
+use crate::error::{Error, Result, ResultExt};
+
+/// The environment variables a token is read from, in order. These are the
+/// ones the GitHub CLI reads, and GitHub Actions workflows usually pass the
+/// job's token in `GITHUB_TOKEN`.
+const TOKEN_VARIABLES: &[&str] = &["GH_TOKEN", "GITHUB_TOKEN"];
+
+/// A token and where it comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthTokenSource {
+    /// `spr.githubAuthToken`
+    Config(String),
+    /// The environment variable of the given name
+    Environment(&'static str, String),
+    /// An installation access token of a GitHub App
+    GitHubApp {
+        app_id: String,
+        installation_id: u64,
+        token: String,
+    },
+    GitHubCLI(String),
+    /// The password the Git credential helpers return for the host
+    GitCredential(String),
+}
+
+impl AuthTokenSource {
+    pub fn token(&self) -> &String {
+        match self {
+            AuthTokenSource::Config(token)
+            | AuthTokenSource::Environment(_, token)
+            | AuthTokenSource::GitHubApp { token, .. }
+            | AuthTokenSource::GitHubCLI(token)
+            | AuthTokenSource::GitCredential(token) => token,
+        }
+    }
+}
+
+impl std::fmt::Display for AuthTokenSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthTokenSource::Config(_) => f.write_str("spr.githubAuthToken"),
+            AuthTokenSource::Environment(variable, _) => {
+                write!(f, "the {variable} environment variable")
+            }
+            AuthTokenSource::GitHubApp {
+                app_id,
+                installation_id,
+                ..
+            } => write!(f, "GitHub App {app_id} (installation {installation_id})"),
+            AuthTokenSource::GitHubCLI(_) => f.write_str("the GitHub CLI"),
+            AuthTokenSource::GitCredential(_) => f.write_str("the Git credential helper"),
+        }
+    }
+}
+
+/// Find the token to authenticate with. The sources are tried in this order:
+///
+/// 1. `spr.githubAuthToken`
+/// 2. The GitHub App configured with `spr.githubAppId` and
+///    `spr.githubAppPrivateKeyFile`
+/// 3. The `GH_TOKEN` and `GITHUB_TOKEN` environment variables
+/// 4. The GitHub CLI
+/// 5. `git credential fill`
+///
+/// The installation of the GitHub App is `spr.githubAppInstallationId`, or
+/// else the one on `repository`, given as owner and name. Its token is
+/// requested from GitHub.
+pub async fn get_auth_token_with_source(
+    settings: &Settings,
+    git_dir: Option<&Path>,
+    github_host: &str,
+    api_url: &str,
+    repository: Option<(&str, &str)>,
+) -> Result<Option<AuthTokenSource>> {
+    // Prefer the configured token if it exists
+    if let Some(token) = settings.get("githubAuthToken") {
+        return Ok(Some(AuthTokenSource::Config(token.to_string())));
+    }
+
+    if let Some(app) = GitHubApp::from_settings(settings)? {
+        return Ok(Some(app.token(api_url, repository).await?));
+    }
+
+    Ok(local_auth_token(git_dir, github_host))
+}
+
+/// Describe the source [`get_auth_token_with_source`] takes the token from,
+/// without contacting GitHub. The private key of a GitHub App is read, but
+/// no token is requested for it.
+pub fn describe_auth_token_source(
+    settings: &Settings,
+    git_dir: Option<&Path>,
+    github_host: &str,
+) -> Result<Option<String>> {
+    if settings.get("githubAuthToken").is_some() {
+        return Ok(Some(AuthTokenSource::Config(String::new()).to_string()));
+    }
+
+    if let Some(app) = GitHubApp::from_settings(settings)? {
+        return Ok(Some(app.to_string()));
+    }
+
+    Ok(local_auth_token(git_dir, github_host).map(|source| source.to_string()))
+}
+
+/// The token from the environment, the GitHub CLI or the Git credential
+/// helpers, in this order. These are the sources that need no configuration
+/// of spr.
+pub fn local_auth_token(git_dir: Option<&Path>, github_host: &str) -> Option<AuthTokenSource> {
+    for variable in TOKEN_VARIABLES {
+        if let Ok(token) = std::env::var(variable)
+            && !token.trim().is_empty()
+        {
+            return Some(AuthTokenSource::Environment(
+                variable,
+                token.trim().to_string(),
+            ));
+        }
+    }
+
+    if let Some(token) = github_cli_auth_token(github_host) {
+        return Some(AuthTokenSource::GitHubCLI(token));
+    }
+
+    git_credential_token(git_dir, github_host).map(AuthTokenSource::GitCredential)
+}
+
+/// The token the GitHub CLI uses for `github_host`, if it is logged in
+pub fn github_cli_auth_token(github_host: &str) -> Option<String> {
+    let output = Command::new("gh")
+        .args(["auth", "token", "--hostname", github_host])
+        .stdout(Stdio::piped())
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+            .filter(|token| !token.is_empty())
+    } else {
+        None
+    }
+}
+
+/// The password the Git credential helpers of the repository in `git_dir`
+/// have stored for HTTPS access to `github_host`. Git is told not to prompt
+/// for one.
+pub fn git_credential_token(git_dir: Option<&Path>, github_host: &str) -> Option<String> {
+    let mut command = Command::new("git");
+    if let Some(git_dir) = git_dir {
+        command.arg("--git-dir").arg(git_dir);
+    }
+    let mut child = command
+        .args(["credential", "fill"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GCM_INTERACTIVE", "never")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    child
+        .stdin
+        .take()?
+        .write_all(format!("protocol=https\nhost={github_host}\n\n").as_bytes())
+        .ok()?;
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_credential_password(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The `password` attribute of the output of `git credential fill`
+fn parse_credential_password(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .map(str::to_string)
+        .filter(|password| !password.is_empty())
+}
+
+/// The claims of the JSON Web Token a GitHub App authenticates with
+#[derive(serde::Serialize)]
+struct AppClaims {
+    iat: u64,
+    exp: u64,
+    iss: String,
+}
+
+/// A GitHub App spr is configured to authenticate as
+struct GitHubApp<'a> {
+    app_id: &'a str,
+    key: jsonwebtoken::EncodingKey,
+    /// `spr.githubAppInstallationId`, if it is set
+    installation_id: Option<u64>,
+}
+
+impl<'a> GitHubApp<'a> {
+    /// Read the settings and the private key of the GitHub App, if one is
+    /// configured. This does not contact GitHub.
+    fn from_settings(settings: &'a Settings) -> Result<Option<Self>> {
+        let (app_id, key_file) = match (
+            settings.get("githubAppId"),
+            settings.get("githubAppPrivateKeyFile"),
+        ) {
+            (None, None) => return Ok(None),
+            (Some(app_id), Some(key_file)) => (app_id, key_file),
+            _ => {
+                return Err(Error::new(
+                    "spr.githubAppId and spr.githubAppPrivateKeyFile must be set together",
+                ));
+            }
+        };
+
+        let key = std::fs::read(key_file).reword(format!(
+            "Could not read the GitHub App private key from {key_file}"
+        ))?;
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(&key)
+            .reword(format!("{key_file} is not a PEM encoded RSA private key"))?;
+
+        let installation_id = settings
+            .get("githubAppInstallationId")
+            .map(|id| {
+                id.parse::<u64>()
+                    .reword(format!("spr.githubAppInstallationId is invalid: {id}"))
+            })
+            .transpose()?;
+
+        Ok(Some(Self {
+            app_id,
+            key,
+            installation_id,
+        }))
+    }
+
+    /// Exchange a JSON Web Token signed with the private key for an
+    /// installation access token
+    async fn token(
+        &self,
+        api_url: &str,
+        repository: Option<(&str, &str)>,
+    ) -> Result<AuthTokenSource> {
+        let app_id = self.app_id;
+
+        // GitHub accepts tokens that expire within ten minutes, and recommends
+        // backdating them against clock drift.
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let jwt = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            &AppClaims {
+                iat: now - 60,
+                exp: now + 9 * 60,
+                iss: app_id.to_string(),
+            },
+            &self.key,
+        )?;
+
+        let client = reqwest::Client::builder()
+            .user_agent(format!("spr/{}", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let request = |method: reqwest::Method, path: String| {
+            client
+                .request(method, format!("{api_url}{path}"))
+                .bearer_auth(&jwt)
+                .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        };
+
+        let installation_id = match (self.installation_id, repository) {
+            (Some(id), _) => id,
+            (None, Some((owner, repo))) => {
+                let installation = app_response(
+                    request(
+                        reqwest::Method::GET,
+                        format!("/repos/{owner}/{repo}/installation"),
+                    )
+                    .send()
+                    .await?,
+                )
+                .await
+                .context(format!(
+                    "GitHub App {app_id} is not installed on {owner}/{repo}"
+                ))?;
+                installation["id"]
+                    .as_u64()
+                    .ok_or_else(|| Error::new("GitHub returned an installation without an id"))?
+            }
+            (None, None) => {
+                return Err(Error::new(
+                    "spr.githubAppInstallationId must be set to authenticate as a GitHub App",
+                ));
+            }
+        };
+
+        let response = app_response(
+            request(
+                reqwest::Method::POST,
+                format!("/app/installations/{installation_id}/access_tokens"),
+            )
+            .send()
+            .await?,
+        )
+        .await
+        .context(format!(
+            "Could not get an access token for GitHub App {app_id}"
+        ))?;
+        let token = response["token"].as_str().ok_or_else(|| {
+            Error::new("GitHub returned an installation access token without a token")
+        })?;
+
+        Ok(AuthTokenSource::GitHubApp {
+            app_id: app_id.to_string(),
+            installation_id,
+            token: token.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for GitHubApp<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.installation_id {
+            Some(installation_id) => write!(
+                f,
+                "GitHub App {} (installation {installation_id})",
+                self.app_id
+            ),
+            None => write!(
+                f,
+                "GitHub App {} (its installation on the repository)",
+                self.app_id
+            ),
+        }
+    }
+}
+
+/// The JSON body of a response to a GitHub App request, or an error with
+/// GitHub's message
+async fn app_response(response: reqwest::Response) -> Result<serde_json::Value> {
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(Error::new(format!(
+            "GitHub responded with {status}: {}",
+            body["message"].as_str().unwrap_or("no message")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ValueSource;
+
+    #[test]
+    fn test_parse_credential_password() {
+        assert_eq!(
+            parse_credential_password(
+                "protocol=https\nhost=github.com\nusername=octocat\npassword=gho_secret\n"
+            ),
+            Some("gho_secret".to_string())
+        );
+        assert_eq!(
+            parse_credential_password("protocol=https\nhost=github.com\npassword=\n"),
+            None
+        );
+        assert_eq!(parse_credential_password(""), None);
+    }
+
+    #[test]
+    fn test_git_credential_token() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let git_dir = temp_dir.path().join("repo.git");
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("--git-dir")
+                .arg(&git_dir)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q", "--bare"]);
+        // The empty value drops the helpers from the global config.
+        git(&["config", "--add", "credential.helper", ""]);
+        git(&[
+            "config",
+            "--add",
+            "credential.helper",
+            "!f() { echo username=octocat; echo password=gho_secret; }; f",
+        ]);
+
+        assert_eq!(
+            git_credential_token(Some(&git_dir), "github.com"),
+            Some("gho_secret".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_configured_token_comes_first() {
+        let mut settings = Settings::default();
+        settings.set(
+            "githubAuthToken",
+            "ghp_configured".to_string(),
+            ValueSource::Git,
+        );
+        // Ignored, because the configured token wins
+        settings.set("githubAppId", "1234".to_string(), ValueSource::Git);
+
+        let source = get_auth_token_with_source(
+            &settings,
+            None,
+            "github.com",
+            "https://api.github.com",
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            source,
+            Some(AuthTokenSource::Config("ghp_configured".to_string()))
+        );
+        assert_eq!(source.unwrap().to_string(), "spr.githubAuthToken");
+    }
+
+    #[tokio::test]
+    async fn test_describe_github_app() {
+        let mut settings = Settings::default();
+        settings.set("githubAppId", "1234".to_string(), ValueSource::Git);
+        settings.set(
+            "githubAppPrivateKeyFile",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/github_app.pem").to_string(),
+            ValueSource::Git,
+        );
+
+        // Describing the source does not need GitHub, which is unreachable
+        // here.
+        assert_eq!(
+            describe_auth_token_source(&settings, None, "github.com").unwrap(),
+            Some("GitHub App 1234 (its installation on the repository)".to_string())
+        );
+
+        // Without a repository, the installation must be configured.
+        let error =
+            get_auth_token_with_source(&settings, None, "github.com", "http://127.0.0.1:1", None)
+                .await
+                .unwrap_err();
+        assert_eq!(
+            error.messages().last().unwrap(),
+            "spr.githubAppInstallationId must be set to authenticate as a GitHub App"
+        );
+
+        settings.set(
+            "githubAppInstallationId",
+            "5678".to_string(),
+            ValueSource::Git,
+        );
+        assert_eq!(
+            describe_auth_token_source(&settings, None, "github.com").unwrap(),
+            Some("GitHub App 1234 (installation 5678)".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_github_app() {
+        let mut settings = Settings::default();
+        settings.set("githubAppId", "1234".to_string(), ValueSource::Git);
+
+        let error = get_auth_token_with_source(
+            &settings,
+            None,
+            "github.com",
+            "https://api.github.com",
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.messages().last().unwrap(),
+            "spr.githubAppId and spr.githubAppPrivateKeyFile must be set together"
+        );
+    }
+}
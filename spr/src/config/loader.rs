@@ -0,0 +1,547 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Loading the `spr.*` settings from the repository's `.spr.toml`, the
+//! Jujutsu config, the Git config, the environment and command line flags,
+//! and checking them all at once
+
+use std::collections::BTreeMap;
+
+use super::Config;
+use crate::{
+    commands::init::validate_branch_prefix,
+    error::{Error, Result},
+    forge::{ForgeKind, MergeMethod},
+    jj::Jujutsu,
+    message::{MessageSection, message_section_by_label},
+};
+
+/// The settings spr knows, in the order `jj spr config` shows them. Names in
+/// the Git config are case-insensitive, so they are matched against these.
+pub const KEYS: &[&str] = &[
+    "githubRepository",
+    "forge",
+    "host",
+    "apiUrl",
+    "githubRemoteName",
+    "pushRemote",
+    "githubMasterBranch",
+    "branchPrefix",
+    "requireApproval",
+    "mergeMethod",
+    "draft",
+    "requiredSections",
+    "githubAuthToken",
+    "githubAppId",
+    "githubAppInstallationId",
+    "githubAppPrivateKeyFile",
+];
+
+/// Settings that have been renamed, by their old names, which still work
+const RENAMED_KEYS: &[(&str, &str)] = &[("githubHost", "host")];
+
+/// The file in the root of the repository with the team's settings
+pub const REPOSITORY_CONFIG_FILE: &str = ".spr.toml";
+
+/// The settings `.spr.toml` may set. Anything that says where to send
+/// branches or credentials is left to the user's own config.
+const POLICY_KEYS: &[&str] = &[
+    "githubMasterBranch",
+    "requireApproval",
+    "mergeMethod",
+    "draft",
+    "requiredSections",
+];
+
+/// Settings whose values must not be shown
+const SECRET_KEYS: &[&str] = &["githubAuthToken"];
+
+/// Where the value of a setting comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueSource {
+    /// spr's built-in default
+    Default,
+    /// `.spr.toml` in the trunk commit
+    RepoFile,
+    Git,
+    JjUser,
+    JjRepo,
+    Env,
+    CliFlag,
+}
+
+impl std::fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ValueSource::Default => "default",
+            ValueSource::RepoFile => REPOSITORY_CONFIG_FILE,
+            ValueSource::Git => "git config",
+            ValueSource::JjUser => "jj user config",
+            ValueSource::JjRepo => "jj repo config",
+            ValueSource::Env => "environment",
+            ValueSource::CliFlag => "command line",
+        })
+    }
+}
+
+/// The `spr.*` settings from all sources, keyed by the name without the
+/// `spr.` prefix
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    values: BTreeMap<String, (String, ValueSource)>,
+    /// Keys in `.spr.toml` that are not in [`POLICY_KEYS`]
+    ignored_repository_keys: Vec<String>,
+}
+
+impl Settings {
+    /// Read the settings from `.spr.toml` in the trunk commit, the Git
+    /// config, the Jujutsu config and the environment, each taking precedence
+    /// over the ones before
+    pub fn load(jj: &Jujutsu, git_config: &git2::Config) -> Result<Self> {
+        let mut settings = Self::default();
+
+        // The file is read from trunk, so that changes to the team's settings
+        // go through review like any other change.
+        if let Some(content) = jj.read_file_at_revision("trunk()", REPOSITORY_CONFIG_FILE)? {
+            let content = String::from_utf8(content)
+                .map_err(|_| Error::new(format!("{REPOSITORY_CONFIG_FILE} is not valid UTF-8")))?;
+            settings.set_from_repository_file(&content)?;
+        }
+
+        let mut entries = git_config.entries(Some(r"^spr\."))?;
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            if let (Some(name), Some(value)) = (entry.name(), entry.value())
+                && let Some(key) = name.strip_prefix("spr.")
+            {
+                settings.set(key, value.to_string(), ValueSource::Git);
+            }
+        }
+
+        for (key, value, source) in jj.config_entries("spr")? {
+            settings.set(&key, value, source);
+        }
+
+        if let Ok(branch_prefix) = std::env::var("JJ_SPR_BRANCH_PREFIX") {
+            settings.set("branchPrefix", branch_prefix, ValueSource::Env);
+        }
+
+        Ok(settings)
+    }
+
+    fn set_from_repository_file(&mut self, content: &str) -> Result<()> {
+        let document: toml_edit::DocumentMut = content
+            .parse()
+            .map_err(|error| Error::new(format!("{REPOSITORY_CONFIG_FILE} is invalid: {error}")))?;
+
+        for (key, item) in document.iter() {
+            match item.as_value() {
+                Some(value) if POLICY_KEYS.contains(&key) => {
+                    self.set(key, toml_value_string(value), ValueSource::RepoFile);
+                }
+                _ => self.ignored_repository_keys.push(key.to_string()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set `key`, overriding the value from any other source
+    pub fn set(&mut self, key: &str, value: String, source: ValueSource) {
+        let key = RENAMED_KEYS
+            .iter()
+            .find(|(old, _)| old.eq_ignore_ascii_case(key))
+            .map_or(key, |(_, new)| new);
+        let key = KEYS
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(key))
+            .map_or(key, |known| known);
+        self.values.insert(key.to_string(), (value, source));
+    }
+
+    /// The value of `key` if it is set to something other than an empty
+    /// string
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .get(key)
+            .map(|(value, _)| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The value `key` has in effect and its source, which is spr's default
+    /// if it is not set
+    pub fn value(&self, key: &str) -> Option<(String, ValueSource)> {
+        if let Some(value) = self.get(key) {
+            return Some((value.to_string(), self.values[key].1));
+        }
+
+        let default = match key {
+            "forge" => "github".to_string(),
+            "host" => self
+                .get("forge")
+                .and_then(|forge| forge.parse::<ForgeKind>().ok())
+                .unwrap_or_default()
+                .default_host()
+                .to_string(),
+            "githubRemoteName" => "origin".to_string(),
+            "pushRemote" => self.value("githubRemoteName")?.0,
+            "githubMasterBranch" => "main".to_string(),
+            "requireApproval" => "false".to_string(),
+            "mergeMethod" => "squash".to_string(),
+            _ => return None,
+        };
+        Some((default, ValueSource::Default))
+    }
+
+    /// The value of `key` as it may be shown, with secrets redacted
+    pub fn display_value(&self, key: &str) -> Option<(String, ValueSource)> {
+        let (value, source) = self.value(key)?;
+        if SECRET_KEYS.contains(&key) {
+            // Like GitHub, show the prefix telling the kind of the token.
+            let prefix: String = value.chars().take(4).collect();
+            Some((format!("{prefix}********"), source))
+        } else {
+            Some((value, source))
+        }
+    }
+
+    /// The settings that are set but that spr does not know
+    pub fn unknown_keys(&self) -> impl Iterator<Item = &str> {
+        self.values
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !KEYS.contains(key))
+    }
+
+    /// The keys in `.spr.toml` that are ignored, because the file may only
+    /// set the team's policies
+    pub fn ignored_repository_keys(&self) -> &[String] {
+        &self.ignored_repository_keys
+    }
+
+    /// Check all settings and build the configuration from them. All
+    /// problems are reported together.
+    pub fn to_config(&self, git_repo: &git2::Repository) -> Result<Config> {
+        let mut errors = Error::empty();
+
+        let forge: ForgeKind = self.parse("forge", &mut errors).unwrap_or_default();
+
+        let (owner, repo) = match self.get("githubRepository") {
+            None => {
+                errors.push("spr.githubRepository must be configured".to_string());
+                Default::default()
+            }
+            Some(repository) => {
+                let regex = match forge {
+                    ForgeKind::GitHub | ForgeKind::Gitea => {
+                        lazy_regex::regex!(r#"^([\w\-\.]+)/([\w\-\.]+)$"#)
+                    }
+                    // GitLab projects can be nested in subgroups
+                    ForgeKind::GitLab => lazy_regex::regex!(r#"^([\w\-\./]+)/([\w\-\.]+)$"#),
+                };
+                match regex.captures(repository) {
+                    Some(captures) => (
+                        captures.get(1).unwrap().as_str().to_string(),
+                        captures.get(2).unwrap().as_str().to_string(),
+                    ),
+                    None => {
+                        errors.push(format!(
+                            "spr.githubRepository must be given as 'OWNER/REPO', but given \
+                             value was '{repository}'"
+                        ));
+                        Default::default()
+                    }
+                }
+            }
+        };
+
+        let branch_prefix = match self.get("branchPrefix") {
+            None => {
+                errors.push("spr.branchPrefix must be configured".to_string());
+                String::new()
+            }
+            Some(branch_prefix) => {
+                if let Err(error) = validate_branch_prefix(branch_prefix) {
+                    push_invalid(&mut errors, "branchPrefix", error);
+                }
+                branch_prefix.to_string()
+            }
+        };
+
+        let require_approval = self.parse_bool("requireApproval", &mut errors);
+        let merge_method: MergeMethod = self.parse("mergeMethod", &mut errors).unwrap_or_default();
+        let draft = self.parse_bool("draft", &mut errors);
+
+        let mut required_sections = Vec::new();
+        for label in self
+            .get("requiredSections")
+            .into_iter()
+            .flat_map(list_items)
+        {
+            match message_section_by_label(label) {
+                Some(section @ (MessageSection::Summary | MessageSection::Reviewers)) => {
+                    required_sections.push(section);
+                }
+                // The title is always required.
+                Some(MessageSection::Title) => (),
+                _ => push_invalid(
+                    &mut errors,
+                    "requiredSections",
+                    Error::new(format!(
+                        "Required sections can be 'Summary' and 'Reviewers', but given \
+                         value was '{label}'"
+                    )),
+                ),
+            }
+        }
+
+        let value = |key| self.value(key).map(|(value, _)| value).unwrap_or_default();
+        let mut config = Config::new(
+            owner,
+            repo,
+            value("githubRemoteName"),
+            value("githubMasterBranch"),
+            branch_prefix,
+            require_approval,
+            merge_method,
+            draft,
+            required_sections,
+            value("host"),
+            forge,
+            self.get("apiUrl").map(String::from),
+        );
+
+        if let Some(push_remote) = self.get("pushRemote")
+            && let Err(error) = config.set_push_remote(git_repo, push_remote.to_string())
+        {
+            push_invalid(&mut errors, "pushRemote", error);
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn parse_bool(&self, key: &str, errors: &mut Error) -> bool {
+        let Some(value) = self.get(key) else {
+            return false;
+        };
+
+        // Git accepts these spellings of booleans, too.
+        match &value.to_ascii_lowercase()[..] {
+            "true" | "yes" | "on" | "1" => true,
+            "false" | "no" | "off" | "0" => false,
+            _ => {
+                push_invalid(
+                    errors,
+                    key,
+                    Error::new(format!(
+                        "Expected 'true' or 'false', but given value was '{value}'"
+                    )),
+                );
+                false
+            }
+        }
+    }
+
+    fn parse<T>(&self, key: &str, errors: &mut Error) -> Option<T>
+    where
+        T: std::str::FromStr<Err = Error>,
+    {
+        match self.get(key)?.parse() {
+            Ok(value) => Some(value),
+            Err(error) => {
+                push_invalid(errors, key, error);
+                None
+            }
+        }
+    }
+}
+
+/// A TOML value as a string: strings without quotes, lists of strings as a
+/// comma-separated list, like lists are written in the Git config
+pub fn toml_value_string(value: &toml_edit::Value) -> String {
+    match value {
+        toml_edit::Value::String(string) => string.value().trim().to_string(),
+        toml_edit::Value::Array(array) => array
+            .iter()
+            .map(toml_value_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        value => value.to_string().trim().to_string(),
+    }
+}
+
+/// The items of a comma-separated list
+fn list_items(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Add the messages of `error` about the setting `key` to `errors`
+fn push_invalid(errors: &mut Error, key: &str, error: Error) {
+    for message in error.messages() {
+        errors.push(message.clone());
+    }
+    errors.push(format!("spr.{key} is invalid"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(values: &[(&str, &str)]) -> Settings {
+        let mut settings = Settings::default();
+        for (key, value) in values {
+            settings.set(key, value.to_string(), ValueSource::JjRepo);
+        }
+        settings
+    }
+
+    #[test]
+    fn test_sources_and_defaults() {
+        let mut settings = settings(&[("githubRepository", "acme/codez")]);
+        // Git config names are lowercase.
+        settings.set("branchprefix", "git/".into(), ValueSource::Git);
+        settings.set("branchPrefix", "cli/".into(), ValueSource::CliFlag);
+        settings.set("forge", "gitlab".into(), ValueSource::JjUser);
+        settings.set("githubAuthToken", "ghp_secret".into(), ValueSource::Env);
+        settings.set("someTypo", "x".into(), ValueSource::Git);
+
+        assert_eq!(
+            settings.value("branchPrefix"),
+            Some(("cli/".to_string(), ValueSource::CliFlag))
+        );
+        assert_eq!(
+            settings.value("host"),
+            Some(("gitlab.com".to_string(), ValueSource::Default))
+        );
+        assert_eq!(
+            settings.value("pushRemote"),
+            Some(("origin".to_string(), ValueSource::Default))
+        );
+        assert_eq!(settings.value("apiUrl"), None);
+        assert_eq!(
+            settings.display_value("githubAuthToken"),
+            Some(("ghp_********".to_string(), ValueSource::Env))
+        );
+        assert_eq!(settings.unknown_keys().collect::<Vec<_>>(), ["someTypo"]);
+    }
+
+    #[test]
+    fn test_renamed_keys() {
+        let mut settings = Settings::default();
+        settings.set("githubhost", "ghe.example.com".into(), ValueSource::Git);
+
+        assert_eq!(
+            settings.value("host"),
+            Some(("ghe.example.com".to_string(), ValueSource::Git))
+        );
+        assert_eq!(settings.unknown_keys().count(), 0);
+
+        // The new name takes precedence like any setting from a later source.
+        settings.set("host", "gitlab.example.com".into(), ValueSource::JjUser);
+        assert_eq!(
+            settings.value("host"),
+            Some(("gitlab.example.com".to_string(), ValueSource::JjUser))
+        );
+    }
+
+    #[test]
+    fn test_repository_file() {
+        let mut settings = Settings::default();
+        settings
+            .set_from_repository_file(
+                "requireApproval = true\n\
+                 mergeMethod = 'rebase'\n\
+                 requiredSections = ['Summary', 'Reviewers']\n\
+                 apiUrl = 'https://attacker.example.com'\n",
+            )
+            .unwrap();
+        // The user's own config takes precedence.
+        settings.set("mergeMethod", "merge".into(), ValueSource::Git);
+
+        assert_eq!(
+            settings.value("requireApproval"),
+            Some(("true".to_string(), ValueSource::RepoFile))
+        );
+        assert_eq!(
+            settings.value("mergeMethod"),
+            Some(("merge".to_string(), ValueSource::Git))
+        );
+        assert_eq!(settings.get("requiredSections"), Some("Summary, Reviewers"));
+        assert_eq!(settings.get("apiUrl"), None);
+        assert_eq!(settings.ignored_repository_keys(), ["apiUrl"]);
+
+        assert!(
+            Settings::default()
+                .set_from_repository_file("draft = ")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_to_config() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let repo = git2::Repository::init(temp_dir.path()).unwrap();
+
+        let config = settings(&[
+            ("githubRepository", "acme/codez"),
+            ("branchPrefix", "spr/me/"),
+            ("requireApproval", "yes"),
+            ("mergeMethod", "rebase"),
+        ])
+        .to_config(&repo)
+        .unwrap();
+        assert_eq!((&config.owner[..], &config.repo[..]), ("acme", "codez"));
+        assert_eq!(config.remote_name, "origin");
+        assert_eq!(config.master_ref.branch_name(), "main");
+        assert!(config.require_approval);
+        assert_eq!(config.merge_method, MergeMethod::Rebase);
+        assert!(!config.draft);
+        assert!(config.required_sections.is_empty());
+
+        let config = settings(&[
+            ("githubRepository", "acme/codez"),
+            ("branchPrefix", "spr/me/"),
+            ("draft", "true"),
+            ("requiredSections", "title, reviewers,summary"),
+        ])
+        .to_config(&repo)
+        .unwrap();
+        assert!(config.draft);
+        assert_eq!(
+            config.required_sections,
+            [MessageSection::Reviewers, MessageSection::Summary]
+        );
+
+        // All problems are reported at once.
+        let error = settings(&[
+            ("branchPrefix", "spr me/"),
+            ("requireApproval", "maybe"),
+            ("mergeMethod", "fast-forward"),
+            ("requiredSections", "Summary, Pull Request"),
+            ("pushRemote", "fork"),
+        ])
+        .to_config(&repo)
+        .unwrap_err();
+        let messages = error.messages();
+        for message in [
+            "spr.githubRepository must be configured",
+            "spr.branchPrefix is invalid",
+            "spr.requireApproval is invalid",
+            "spr.mergeMethod is invalid",
+            "spr.requiredSections is invalid",
+            "spr.pushRemote is invalid",
+        ] {
+            assert!(messages.iter().any(|m| m == message), "{message}");
+        }
+    }
+}
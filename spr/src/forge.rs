@@ -0,0 +1,538 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! The interface spr uses to talk to the service hosting the repository (the
+//! "forge"), and the Pull Request model shared by all implementations.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::{Error, Result},
+    github::GitHubBranch,
+    message::{
+        MessageSection, MessageSectionsMap, build_github_body, split_stack_block, with_stack_block,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    pub number: u64,
+    pub state: PullRequestState,
+    pub title: String,
+    pub body: Option<String>,
+    pub sections: MessageSectionsMap,
+    pub base: GitHubBranch,
+    pub head: GitHubBranch,
+    pub base_oid: git2::Oid,
+    pub head_oid: git2::Oid,
+    pub merge_commit: Option<git2::Oid>,
+    pub reviewers: HashMap<String, ReviewStatus>,
+    pub review_status: Option<ReviewStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Requested,
+    Approved,
+    Rejected,
+}
+
+#[derive(serde::Serialize, Default, Debug)]
+pub struct PullRequestUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<PullRequestState>,
+}
+
+impl PullRequestUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.body.is_none() && self.base.is_none() && self.state.is_none()
+    }
+
+    pub fn update_message(&mut self, pull_request: &PullRequest, message: &MessageSectionsMap) {
+        let title = message.get(&MessageSection::Title);
+        if title.is_some() && title != Some(&pull_request.title) {
+            self.title = title.cloned();
+        }
+
+        // The stack navigation block is not part of the commit message, so it
+        // is neither compared nor dropped.
+        let (current_body, stack_block) =
+            split_stack_block(pull_request.body.as_deref().unwrap_or(""));
+        let body = build_github_body(message);
+        if pull_request.body.is_none() || current_body.trim() != body.trim() {
+            self.body = Some(with_stack_block(&body, stack_block));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MergeMethod {
+    #[default]
+    Squash,
+    Merge,
+    Rebase,
+}
+
+impl std::fmt::Display for MergeMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MergeMethod::Squash => "squash",
+            MergeMethod::Merge => "merge",
+            MergeMethod::Rebase => "rebase",
+        })
+    }
+}
+
+impl std::str::FromStr for MergeMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match &s.trim().to_ascii_lowercase()[..] {
+            "squash" => Ok(MergeMethod::Squash),
+            "merge" => Ok(MergeMethod::Merge),
+            "rebase" => Ok(MergeMethod::Rebase),
+            _ => Err(Error::new(format!(
+                "Merge method must be one of 'squash', 'merge' or 'rebase', but given value was '{s}'"
+            ))),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Default, Debug)]
+pub struct PullRequestRequestReviewers {
+    pub reviewers: Vec<String>,
+    pub team_reviewers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PullRequestState {
+    Open,
+    Closed,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct UserWithName {
+    pub login: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub is_collaborator: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PullRequestMergeability {
+    /// Forge-specific ID of the Pull Request, e.g. its GraphQL node ID on
+    /// GitHub
+    pub id: String,
+    pub base: GitHubBranch,
+    pub head_oid: git2::Oid,
+    pub mergeable: Option<bool>,
+    pub merge_commit: Option<git2::Oid>,
+    pub checks: Vec<StatusCheck>,
+    /// Whether the base branch merges Pull Requests through a merge queue
+    pub has_merge_queue: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckState {
+    Pending,
+    Success,
+    Failure,
+}
+
+/// A check run or commit status reported for the head of a Pull Request
+#[derive(Debug, Clone)]
+pub struct StatusCheck {
+    pub name: String,
+    pub state: CheckState,
+    pub url: Option<String>,
+    /// Whether the check must pass before the Pull Request can be merged
+    pub required: bool,
+}
+
+/// Summary of a Pull Request's state, review, CI and mergeability
+#[derive(Debug, Clone)]
+pub struct PullRequestStatus {
+    pub number: u64,
+    pub title: String,
+    pub body: String,
+    pub state: PullRequestState,
+    pub is_draft: bool,
+    pub merged: bool,
+    pub review_status: Option<ReviewStatus>,
+    /// Combined state of all status checks, if there are any
+    pub checks: Option<CheckState>,
+    pub mergeable: Option<bool>,
+    pub head_oid: git2::Oid,
+    pub head_tree_oid: Option<git2::Oid>,
+}
+
+/// The kind of service hosting the repository, configured with `spr.forge`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForgeKind {
+    #[default]
+    GitHub,
+    GitLab,
+    /// Gitea or its fork Forgejo
+    Gitea,
+}
+
+impl std::fmt::Display for ForgeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ForgeKind::GitHub => "github",
+            ForgeKind::GitLab => "gitlab",
+            ForgeKind::Gitea => "gitea",
+        })
+    }
+}
+
+impl ForgeKind {
+    /// Host name of the public instance of this forge
+    pub fn default_host(&self) -> &'static str {
+        match self {
+            ForgeKind::GitHub => crate::config::DEFAULT_GITHUB_HOST,
+            ForgeKind::GitLab => "gitlab.com",
+            ForgeKind::Gitea => "codeberg.org",
+        }
+    }
+}
+
+impl std::str::FromStr for ForgeKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match &s.trim().to_ascii_lowercase()[..] {
+            "github" => Ok(ForgeKind::GitHub),
+            "gitlab" => Ok(ForgeKind::GitLab),
+            "gitea" | "forgejo" => Ok(ForgeKind::Gitea),
+            _ => Err(Error::new(format!(
+                "Forge must be one of 'github', 'gitlab', 'gitea' or 'forgejo', but given value was '{s}'"
+            ))),
+        }
+    }
+}
+
+/// Operations on Pull Requests (called Merge Requests on GitLab) that the
+/// commands need from a forge.
+#[async_trait]
+pub trait Forge: Send + Sync {
+    /// Load a Pull Request. This also fetches its head and base branches.
+    async fn get_pull_request(&self, number: u64) -> Result<PullRequest>;
+
+    /// Load several Pull Requests, failing if any of them cannot be loaded.
+    /// Forges that can should do this in one request; by default the Pull
+    /// Requests are loaded concurrently, one request each.
+    async fn get_pull_requests(&self, numbers: &[u64]) -> Result<HashMap<u64, PullRequest>> {
+        futures::future::join_all(
+            numbers
+                .iter()
+                .map(|&number| async move { Ok((number, self.get_pull_request(number).await?)) }),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    async fn create_pull_request(
+        &self,
+        message: &MessageSectionsMap,
+        base_ref_name: String,
+        head_ref_name: String,
+        draft: bool,
+    ) -> Result<u64>;
+
+    async fn update_pull_request(&self, number: u64, updates: PullRequestUpdate) -> Result<()>;
+
+    async fn close_pull_request(&self, number: u64) -> Result<()> {
+        self.update_pull_request(
+            number,
+            PullRequestUpdate {
+                state: Some(PullRequestState::Closed),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn get_user(&self, login: &str) -> Result<UserWithName>;
+
+    /// Look up a team that can be requested for review, returning its slug
+    async fn get_team(&self, slug: &str) -> Result<String>;
+
+    async fn request_reviewers(
+        &self,
+        number: u64,
+        reviewers: PullRequestRequestReviewers,
+    ) -> Result<()>;
+
+    async fn get_pull_request_mergeability(&self, number: u64) -> Result<PullRequestMergeability>;
+
+    /// Load the status of several Pull Requests at once. Pull Requests that do
+    /// not exist are missing from the result.
+    async fn get_pull_request_statuses(
+        &self,
+        numbers: &[u64],
+    ) -> Result<HashMap<u64, PullRequestStatus>>;
+
+    async fn get_allowed_merge_methods(&self) -> Result<Vec<MergeMethod>>;
+
+    /// Merge the Pull Request, provided its head is still `head_oid`. Returns
+    /// the commit that was added to the base branch, if known.
+    async fn merge_pull_request(
+        &self,
+        number: u64,
+        merge_method: MergeMethod,
+        title: &str,
+        body: &str,
+        head_oid: git2::Oid,
+    ) -> Result<Option<git2::Oid>>;
+
+    /// Let the forge merge the Pull Request as soon as all its requirements
+    /// are met. `pull_request_id` is `PullRequestMergeability::id`.
+    async fn enable_auto_merge(
+        &self,
+        pull_request_id: &str,
+        merge_method: MergeMethod,
+        head_oid: git2::Oid,
+        title: &str,
+        body: &str,
+    ) -> Result<()>;
+
+    /// Add the Pull Request to the merge queue of its base branch, returning
+    /// its position in the queue.
+    async fn enqueue_pull_request(
+        &self,
+        _pull_request_id: &str,
+        _head_oid: git2::Oid,
+    ) -> Result<Option<i64>> {
+        Err(Error::new("This forge does not support merge queues"))
+    }
+}
+
+/// How a forge marks a draft Pull Request with a prefix of its title, for
+/// forges that have no separate draft flag spr can set
+pub(crate) struct DraftTitle {
+    /// The prefix spr adds to the title of a draft
+    prefix: &'static str,
+    /// All prefixes the forge recognizes, compared case-insensitively
+    recognized: &'static [&'static str],
+}
+
+impl DraftTitle {
+    pub(crate) const fn new(prefix: &'static str, recognized: &'static [&'static str]) -> Self {
+        Self { prefix, recognized }
+    }
+
+    /// The title without the draft prefix, if it has one
+    pub(crate) fn strip<'a>(&self, title: &'a str) -> &'a str {
+        let trimmed = title.trim_start();
+        for prefix in self.recognized {
+            if let Some(start) = trimmed.get(..prefix.len())
+                && start.eq_ignore_ascii_case(prefix)
+            {
+                return trimmed[prefix.len()..].trim_start();
+            }
+        }
+        title
+    }
+
+    /// The title marking a draft
+    pub(crate) fn add(&self, title: &str) -> String {
+        format!("{} {title}", self.prefix)
+    }
+}
+
+/// Turn an unsuccessful response of the REST API of `forge` into an error
+/// with the body it sent
+pub(crate) async fn check_status(
+    forge: &str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response> {
+    let status = response.status();
+
+    if !status.is_success() {
+        let mut error = Error::new(format!("{forge} API request failed ({status})"));
+        error.push(response.text().await.unwrap_or_default());
+        return Err(error);
+    }
+
+    Ok(response)
+}
+
+/// Deserialize a successful response of the REST API of `forge`, or return
+/// `None` if the requested resource does not exist. Any other failure is an
+/// error.
+pub(crate) async fn json_if_found<T: serde::de::DeserializeOwned>(
+    forge: &str,
+    response: reqwest::Response,
+) -> Result<Option<T>> {
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    Ok(Some(check_status(forge, response).await?.json().await?))
+}
+
+/// Fetch the head and base branches of a Pull Request from the remote and
+/// return the commits they point to. A branch that does not exist on the
+/// remote (e.g. because it has been deleted) yields the zero oid.
+pub async fn fetch_pull_request_branches(
+    config: &crate::config::Config,
+    head: &GitHubBranch,
+    base: &GitHubBranch,
+) -> Result<(git2::Oid, git2::Oid)> {
+    Ok(fetch_pull_requests_branches(config, &[(head.clone(), base.clone())]).await?[0])
+}
+
+/// Fetch the head and base branches of several Pull Requests, with one
+/// `git fetch` per remote, returning their commits in the same order. A
+/// branch that does not exist on the remote yields the zero oid.
+pub async fn fetch_pull_requests_branches(
+    config: &crate::config::Config,
+    branches: &[(GitHubBranch, GitHubBranch)],
+) -> Result<Vec<(git2::Oid, git2::Oid)>> {
+    // The branches of Pull Requests from a fork are on a different remote
+    // than their base, so collect the refspecs to fetch per remote.
+    let mut refspecs_by_remote: Vec<(&str, Vec<String>)> = Vec::new();
+    for branch in branches.iter().flat_map(|(head, base)| [head, base]) {
+        let refspec = format!("{}:{}", branch.on_github(), branch.local());
+        let index = refspecs_by_remote
+            .iter()
+            .position(|(remote, _)| *remote == branch.remote_name())
+            .unwrap_or_else(|| {
+                refspecs_by_remote.push((branch.remote_name(), Vec::new()));
+                refspecs_by_remote.len() - 1
+            });
+        let refspecs = &mut refspecs_by_remote[index].1;
+        if !refspecs.contains(&refspec) {
+            refspecs.push(refspec);
+        }
+    }
+
+    // Git refuses the whole fetch if one of the refs is missing on the
+    // remote, naming only the first one it did not find. So leave out the
+    // missing refs one by one until the rest can be fetched. Git's messages
+    // are only recognized in English, whatever the user's locale.
+    let mut missing: Vec<String> = Vec::new();
+    for (remote, mut refspecs) in refspecs_by_remote {
+        while !refspecs.is_empty() {
+            let output = config
+                .git_command()
+                .env("LC_ALL", "C")
+                .args(["fetch", "--no-write-fetch-head", remote])
+                .args(&refspecs)
+                .output()
+                .await?;
+            if output.status.success() {
+                break;
+            }
+
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let Some(index) = stderr
+                .lines()
+                .find_map(|line| line.strip_prefix("fatal: couldn't find remote ref "))
+                .and_then(|ref_name| {
+                    refspecs
+                        .iter()
+                        .position(|refspec| refspec.starts_with(&format!("{}:", ref_name.trim())))
+                })
+            else {
+                let mut error = Error::new(format!("git fetch from {remote} failed"));
+                error.push(stderr.trim().to_string());
+                return Err(error);
+            };
+            let refspec = refspecs.remove(index);
+            if let Some((_, local)) = refspec.split_once(':') {
+                missing.push(local.to_string());
+            }
+        }
+    }
+
+    let mut oids = Vec::with_capacity(branches.len());
+    for (head, base) in branches {
+        oids.push((
+            rev_parse(config, head.local(), &missing).await,
+            rev_parse(config, base.local(), &missing).await,
+        ));
+    }
+    Ok(oids)
+}
+
+/// The commit a fetched ref points to. A ref that was `missing` on the
+/// remote may still point to where the branch was before it was deleted, so
+/// it yields the zero oid.
+async fn rev_parse(
+    config: &crate::config::Config,
+    ref_name: &str,
+    missing: &[String],
+) -> git2::Oid {
+    if missing.iter().any(|missing| missing == ref_name) {
+        return git2::Oid::zero();
+    }
+
+    if let Ok(output) = config
+        .git_command()
+        .args(["rev-parse", ref_name])
+        .output()
+        .await
+        && output.status.success()
+    {
+        let oid_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        git2::Oid::from_str(&oid_str).unwrap_or(git2::Oid::zero())
+    } else {
+        git2::Oid::zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_method_from_str() {
+        assert_eq!(
+            "squash".parse::<MergeMethod>().unwrap(),
+            MergeMethod::Squash
+        );
+        assert_eq!("Merge".parse::<MergeMethod>().unwrap(), MergeMethod::Merge);
+        assert_eq!(
+            " rebase ".parse::<MergeMethod>().unwrap(),
+            MergeMethod::Rebase
+        );
+        assert!("fast-forward".parse::<MergeMethod>().is_err());
+    }
+
+    #[test]
+    fn test_draft_title() {
+        let draft_title = DraftTitle::new("Draft:", &["draft:", "[draft]"]);
+        assert_eq!(draft_title.add("Add a feature"), "Draft: Add a feature");
+        assert_eq!(draft_title.strip("Draft: Add a feature"), "Add a feature");
+        assert_eq!(draft_title.strip(" [DRAFT]Add a feature"), "Add a feature");
+        assert_eq!(
+            draft_title.strip("Add a draft: feature"),
+            "Add a draft: feature"
+        );
+        assert_eq!(draft_title.strip("Dräft"), "Dräft");
+    }
+
+    #[test]
+    fn test_forge_kind_from_str() {
+        assert_eq!("github".parse::<ForgeKind>().unwrap(), ForgeKind::GitHub);
+        assert_eq!(" GitLab ".parse::<ForgeKind>().unwrap(), ForgeKind::GitLab);
+        assert_eq!("forgejo".parse::<ForgeKind>().unwrap(), ForgeKind::Gitea);
+        assert!("bitbucket".parse::<ForgeKind>().is_err());
+    }
+}
@@ -928,6 +928,12 @@ mod tests {
             "main".into(),
             "spr/test/".into(),
             false,
+            crate::forge::MergeMethod::Squash,
+            false,
+            Vec::new(),
+            crate::config::DEFAULT_GITHUB_HOST.into(),
+            crate::forge::ForgeKind::GitHub,
+            None,
         )
     }
 
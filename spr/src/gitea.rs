@@ -0,0 +1,656 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Forge implementation for Gitea and Forgejo, whose Pull Request REST API
+//! (v1) closely follows GitHub's REST API.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    error::{Error, Result, ResultExt},
+    forge::{
+        CheckState, DraftTitle, Forge, MergeMethod, PullRequest, PullRequestMergeability,
+        PullRequestRequestReviewers, PullRequestState, PullRequestStatus, PullRequestUpdate,
+        ReviewStatus, StatusCheck, UserWithName, check_status, fetch_pull_request_branches,
+        json_if_found,
+    },
+    message::{
+        MessageSection, MessageSectionsMap, build_github_body, parse_message, split_stack_block,
+    },
+};
+
+#[derive(Clone)]
+pub struct Gitea {
+    config: crate::config::Config,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaPullRequest {
+    number: u64,
+    title: String,
+    body: Option<String>,
+    state: String,
+    #[serde(default)]
+    merged: bool,
+    merge_commit_sha: Option<String>,
+    #[serde(default)]
+    mergeable: bool,
+    #[serde(default)]
+    draft: bool,
+    head: Branch,
+    base: Branch,
+    #[serde(default)]
+    requested_reviewers: Option<Vec<User>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Branch {
+    #[serde(rename = "ref")]
+    ref_name: String,
+    sha: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct User {
+    login: String,
+    full_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Review {
+    user: Option<User>,
+    state: String,
+    #[serde(default)]
+    dismissed: bool,
+    #[serde(default)]
+    stale: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct CombinedStatus {
+    #[serde(default)]
+    statuses: Option<Vec<CommitStatus>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommitStatus {
+    context: String,
+    status: String,
+    target_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Repository {
+    #[serde(default)]
+    allow_merge_commits: bool,
+    #[serde(default)]
+    allow_rebase: bool,
+    #[serde(default)]
+    allow_squash_merge: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct TeamSearch {
+    data: Vec<Team>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Team {
+    name: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Merge {
+    #[serde(rename = "Do")]
+    merge_style: &'static str,
+    #[serde(rename = "MergeTitleField")]
+    title: String,
+    #[serde(rename = "MergeMessageField")]
+    message: String,
+    head_commit_id: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    merge_when_checks_succeed: bool,
+}
+
+impl Merge {
+    fn new(merge_method: MergeMethod, title: &str, body: &str, head_oid: git2::Oid) -> Self {
+        Self {
+            merge_style: match merge_method {
+                MergeMethod::Squash => "squash",
+                MergeMethod::Merge => "merge",
+                MergeMethod::Rebase => "rebase",
+            },
+            title: title.to_string(),
+            message: body.to_string(),
+            head_commit_id: head_oid.to_string(),
+            merge_when_checks_succeed: false,
+        }
+    }
+}
+
+impl GiteaPullRequest {
+    fn pull_request_state(&self) -> PullRequestState {
+        if self.state == "open" {
+            PullRequestState::Open
+        } else {
+            PullRequestState::Closed
+        }
+    }
+
+    fn merge_commit(&self) -> Option<git2::Oid> {
+        if !self.merged {
+            return None;
+        }
+
+        self.merge_commit_sha
+            .as_ref()
+            .and_then(|sha| git2::Oid::from_str(sha).ok())
+    }
+
+    fn head_oid(&self) -> git2::Oid {
+        git2::Oid::from_str(&self.head.sha).unwrap_or(git2::Oid::zero())
+    }
+
+    fn is_draft(&self) -> bool {
+        self.draft || DRAFT_TITLE.strip(&self.title) != self.title
+    }
+}
+
+/// The review decision of a Pull Request, given its reviews (oldest first)
+/// and whether reviews have been requested. Only the latest review of each
+/// reviewer counts.
+fn review_decision(
+    reviews: &[Review],
+    review_requested: bool,
+) -> (HashMap<String, ReviewStatus>, Option<ReviewStatus>) {
+    let mut reviewers = HashMap::new();
+    for review in reviews {
+        let Some(user) = &review.user else {
+            continue;
+        };
+        if review.dismissed || review.stale {
+            continue;
+        }
+        let status = match &review.state[..] {
+            "APPROVED" => ReviewStatus::Approved,
+            "REQUEST_CHANGES" => ReviewStatus::Rejected,
+            "REQUEST_REVIEW" => ReviewStatus::Requested,
+            _ => continue,
+        };
+        reviewers.insert(user.login.clone(), status);
+    }
+
+    let review_status = if reviewers.values().any(|s| *s == ReviewStatus::Rejected) {
+        Some(ReviewStatus::Rejected)
+    } else if reviewers.values().any(|s| *s == ReviewStatus::Approved) {
+        Some(ReviewStatus::Approved)
+    } else if review_requested || !reviewers.is_empty() {
+        Some(ReviewStatus::Requested)
+    } else {
+        None
+    };
+
+    (reviewers, review_status)
+}
+
+/// Work-in-progress Pull Requests are marked by a prefix of their title
+const DRAFT_TITLE: DraftTitle = DraftTitle::new("WIP:", &["wip:", "[wip]"]);
+
+impl Gitea {
+    pub fn new(config: crate::config::Config, client: reqwest::Client) -> Self {
+        Self { config, client }
+    }
+
+    fn repo_url(&self) -> String {
+        format!(
+            "{}/repos/{}/{}",
+            self.config.api_url(),
+            self.config.owner,
+            self.config.repo
+        )
+    }
+
+    fn pull_request_api_url(&self, number: u64) -> String {
+        format!("{}/pulls/{}", self.repo_url(), number)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        check_status("Gitea", request.send().await?).await
+    }
+
+    async fn request<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    async fn get_gitea_pull_request(&self, number: u64) -> Result<GiteaPullRequest> {
+        self.request(self.client.get(self.pull_request_api_url(number)))
+            .await
+            .context(format!("fetching PR #{number} failed"))
+    }
+
+    /// Like `get_gitea_pull_request`, but a Pull Request that does not exist
+    /// is `None`. Any other failure is an error.
+    async fn find_gitea_pull_request(&self, number: u64) -> Result<Option<GiteaPullRequest>> {
+        let response = self
+            .client
+            .get(self.pull_request_api_url(number))
+            .send()
+            .await?;
+        json_if_found("Gitea", response)
+            .await
+            .context(format!("fetching PR #{number} failed"))
+    }
+
+    async fn get_reviews(&self, number: u64) -> Result<Vec<Review>> {
+        self.request(
+            self.client
+                .get(format!("{}/reviews", self.pull_request_api_url(number))),
+        )
+        .await
+    }
+
+    async fn get_checks(&self, head_oid: git2::Oid) -> Result<Vec<StatusCheck>> {
+        let status: CombinedStatus = self
+            .request(
+                self.client
+                    .get(format!("{}/commits/{}/status", self.repo_url(), head_oid)),
+            )
+            .await?;
+
+        Ok(status
+            .statuses
+            .unwrap_or_default()
+            .into_iter()
+            .map(|status| StatusCheck {
+                name: status.context,
+                state: match &status.status[..] {
+                    "success" | "warning" => CheckState::Success,
+                    "error" | "failure" => CheckState::Failure,
+                    _ => CheckState::Pending,
+                },
+                url: status.target_url.filter(|url| !url.is_empty()),
+                // Gitea does not report which statuses branch protection
+                // requires.
+                required: false,
+            })
+            .collect())
+    }
+
+    async fn merge(&self, number: u64, merge: &Merge) -> Result<()> {
+        self.send(
+            self.client
+                .post(format!("{}/merge", self.pull_request_api_url(number)))
+                .json(merge),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Forge for Gitea {
+    async fn get_pull_request(&self, number: u64) -> Result<PullRequest> {
+        let config = &self.config;
+        let pr = self.get_gitea_pull_request(number).await?;
+        let reviews = self.get_reviews(number).await?;
+
+        let base = config.new_github_branch(&pr.base.ref_name);
+        let head = config.new_github_branch(&pr.head.ref_name);
+
+        let (head_oid, base_oid) = fetch_pull_request_branches(config, &head, &base).await?;
+
+        let body = pr.body.clone().unwrap_or_default();
+        let mut sections = parse_message(&split_stack_block(&body).0, MessageSection::Summary);
+
+        let title = DRAFT_TITLE.strip(&pr.title).trim().to_string();
+        sections.insert(
+            MessageSection::Title,
+            if title.is_empty() {
+                String::from("(untitled)")
+            } else {
+                title.clone()
+            },
+        );
+
+        sections.insert(MessageSection::PullRequest, config.pull_request_url(number));
+
+        let requested_reviewers = pr.requested_reviewers.as_deref().unwrap_or_default();
+        let (mut reviewers, review_status) =
+            review_decision(&reviews, !requested_reviewers.is_empty());
+        for user in requested_reviewers {
+            reviewers
+                .entry(user.login.clone())
+                .or_insert(ReviewStatus::Requested);
+        }
+
+        let mut reviewer_names: Vec<&str> = reviewers.keys().map(|name| name.as_str()).collect();
+        reviewer_names.sort();
+        sections.insert(MessageSection::Reviewers, reviewer_names.join(", "));
+
+        if review_status == Some(ReviewStatus::Approved) {
+            let mut approvers: Vec<&str> = reviewers
+                .iter()
+                .filter(|(_, status)| **status == ReviewStatus::Approved)
+                .map(|(name, _)| name.as_str())
+                .collect();
+            approvers.sort();
+            sections.insert(MessageSection::ReviewedBy, approvers.join(", "));
+        }
+
+        Ok(PullRequest {
+            number: pr.number,
+            state: pr.pull_request_state(),
+            title,
+            body: Some(body),
+            sections,
+            base,
+            head,
+            base_oid,
+            head_oid,
+            merge_commit: pr.merge_commit(),
+            reviewers,
+            review_status,
+        })
+    }
+
+    async fn create_pull_request(
+        &self,
+        message: &MessageSectionsMap,
+        base_ref_name: String,
+        head_ref_name: String,
+        draft: bool,
+    ) -> Result<u64> {
+        #[derive(Serialize)]
+        struct NewPullRequest {
+            head: String,
+            base: String,
+            title: String,
+            body: String,
+        }
+
+        let title = message
+            .get(&MessageSection::Title)
+            .cloned()
+            .unwrap_or_default();
+        let pr: GiteaPullRequest =
+            self.request(self.client.post(format!("{}/pulls", self.repo_url())).json(
+                &NewPullRequest {
+                    head: head_ref_name,
+                    base: base_ref_name,
+                    title: if draft {
+                        DRAFT_TITLE.add(&title)
+                    } else {
+                        title
+                    },
+                    body: build_github_body(message),
+                },
+            ))
+            .await?;
+
+        Ok(pr.number)
+    }
+
+    async fn update_pull_request(&self, number: u64, mut updates: PullRequestUpdate) -> Result<()> {
+        // A new title must keep marking a work-in-progress Pull Request.
+        if let Some(title) = &updates.title
+            && self.get_gitea_pull_request(number).await?.is_draft()
+        {
+            updates.title = Some(DRAFT_TITLE.add(title));
+        }
+
+        // The edit endpoint takes the same fields as GitHub's.
+        let _: GiteaPullRequest = self
+            .request(
+                self.client
+                    .patch(self.pull_request_api_url(number))
+                    .json(&updates),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_user(&self, login: &str) -> Result<UserWithName> {
+        let user: User = self
+            .request(
+                self.client
+                    .get(format!("{}/users/{}", self.config.api_url(), login)),
+            )
+            .await?;
+
+        Ok(UserWithName {
+            login: user.login,
+            name: user.full_name.filter(|name| !name.is_empty()),
+            is_collaborator: false,
+        })
+    }
+
+    async fn get_team(&self, slug: &str) -> Result<String> {
+        let search: TeamSearch = self
+            .request(
+                self.client
+                    .get(format!(
+                        "{}/orgs/{}/teams/search",
+                        self.config.api_url(),
+                        self.config.owner
+                    ))
+                    .query(&[("q", slug)]),
+            )
+            .await?;
+
+        search
+            .data
+            .into_iter()
+            .map(|team| team.name)
+            .find(|name| name.eq_ignore_ascii_case(slug))
+            .ok_or_else(|| Error::new(format!("Gitea team '{slug}' not found")))
+    }
+
+    async fn request_reviewers(
+        &self,
+        number: u64,
+        reviewers: PullRequestRequestReviewers,
+    ) -> Result<()> {
+        self.send(
+            self.client
+                .post(format!(
+                    "{}/requested_reviewers",
+                    self.pull_request_api_url(number)
+                ))
+                .json(&reviewers),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_pull_request_mergeability(&self, number: u64) -> Result<PullRequestMergeability> {
+        let pr = self.get_gitea_pull_request(number).await?;
+        let checks = self.get_checks(pr.head_oid()).await?;
+
+        Ok(PullRequestMergeability {
+            id: pr.number.to_string(),
+            base: self.config.new_github_branch(&pr.base.ref_name),
+            head_oid: pr.head_oid(),
+            mergeable: Some(pr.mergeable),
+            merge_commit: pr.merge_commit(),
+            checks,
+            has_merge_queue: false,
+        })
+    }
+
+    async fn get_pull_request_statuses(
+        &self,
+        numbers: &[u64],
+    ) -> Result<HashMap<u64, PullRequestStatus>> {
+        let statuses = futures::future::join_all(numbers.iter().map(|&number| async move {
+            // Pull Requests that do not exist are left out.
+            let Some(pr) = self.find_gitea_pull_request(number).await? else {
+                return Ok(None);
+            };
+            let reviews = self.get_reviews(number).await?;
+            let checks = self.get_checks(pr.head_oid()).await?;
+
+            let review_requested = pr
+                .requested_reviewers
+                .as_ref()
+                .is_some_and(|reviewers| !reviewers.is_empty());
+            let checks = if checks.is_empty() {
+                None
+            } else if checks.iter().any(|c| c.state == CheckState::Failure) {
+                Some(CheckState::Failure)
+            } else if checks.iter().any(|c| c.state == CheckState::Pending) {
+                Some(CheckState::Pending)
+            } else {
+                Some(CheckState::Success)
+            };
+
+            Ok::<_, Error>(Some(PullRequestStatus {
+                number,
+                title: DRAFT_TITLE.strip(&pr.title).to_string(),
+                body: pr.body.clone().unwrap_or_default(),
+                state: pr.pull_request_state(),
+                is_draft: pr.is_draft(),
+                merged: pr.merged,
+                review_status: review_decision(&reviews, review_requested).1,
+                checks,
+                mergeable: Some(pr.mergeable),
+                head_oid: pr.head_oid(),
+                head_tree_oid: None,
+            }))
+        }))
+        .await;
+
+        let mut result = HashMap::new();
+        for status in statuses {
+            if let Some(status) = status? {
+                result.insert(status.number, status);
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn get_allowed_merge_methods(&self) -> Result<Vec<MergeMethod>> {
+        let repository: Repository = self.request(self.client.get(self.repo_url())).await?;
+
+        let mut methods = Vec::new();
+        if repository.allow_squash_merge {
+            methods.push(MergeMethod::Squash);
+        }
+        if repository.allow_merge_commits {
+            methods.push(MergeMethod::Merge);
+        }
+        if repository.allow_rebase {
+            methods.push(MergeMethod::Rebase);
+        }
+
+        Ok(methods)
+    }
+
+    async fn merge_pull_request(
+        &self,
+        number: u64,
+        merge_method: MergeMethod,
+        title: &str,
+        body: &str,
+        head_oid: git2::Oid,
+    ) -> Result<Option<git2::Oid>> {
+        self.merge(number, &Merge::new(merge_method, title, body, head_oid))
+            .await?;
+
+        // The merge endpoint does not report the resulting commit.
+        let pr = self.get_gitea_pull_request(number).await?;
+        if !pr.merged {
+            return Err(Error::new(format!(
+                "Gitea Pull Request merge failed: Pull Request is {}",
+                pr.state
+            )));
+        }
+
+        Ok(pr.merge_commit())
+    }
+
+    async fn enable_auto_merge(
+        &self,
+        pull_request_id: &str,
+        merge_method: MergeMethod,
+        head_oid: git2::Oid,
+        title: &str,
+        body: &str,
+    ) -> Result<()> {
+        let number = pull_request_id
+            .parse()
+            .map_err(|_| Error::new(format!("invalid Pull Request id '{pull_request_id}'")))?;
+
+        self.merge(
+            number,
+            &Merge {
+                merge_when_checks_succeed: true,
+                ..Merge::new(merge_method, title, body, head_oid)
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(login: &str, state: &str) -> Review {
+        Review {
+            user: Some(User {
+                login: login.into(),
+                full_name: None,
+            }),
+            state: state.into(),
+            dismissed: false,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn test_review_decision() {
+        assert_eq!(review_decision(&[], false).1, None);
+        assert_eq!(review_decision(&[], true).1, Some(ReviewStatus::Requested));
+
+        let (reviewers, status) = review_decision(
+            &[
+                review("alice", "REQUEST_CHANGES"),
+                review("bob", "COMMENT"),
+                review("alice", "APPROVED"),
+            ],
+            false,
+        );
+        assert_eq!(status, Some(ReviewStatus::Approved));
+        assert_eq!(reviewers.get("alice"), Some(&ReviewStatus::Approved));
+        assert_eq!(reviewers.get("bob"), None);
+
+        let (_, status) = review_decision(
+            &[
+                review("alice", "APPROVED"),
+                review("bob", "REQUEST_CHANGES"),
+            ],
+            false,
+        );
+        assert_eq!(status, Some(ReviewStatus::Rejected));
+    }
+
+    #[test]
+    fn test_draft_title() {
+        assert_eq!(DRAFT_TITLE.add("Add a feature"), "WIP: Add a feature");
+        assert_eq!(DRAFT_TITLE.strip("WIP: Add a feature"), "Add a feature");
+        assert_eq!(DRAFT_TITLE.strip("[wip] Add a feature"), "Add a feature");
+        assert_eq!(DRAFT_TITLE.strip("Add a feature"), "Add a feature");
+    }
+}
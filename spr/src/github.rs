@@ -5,422 +5,590 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+use async_trait::async_trait;
 use graphql_client::{GraphQLQuery, Response};
-use serde::Deserialize;
+use serde::{Deserialize, de::DeserializeOwned};
 
 use crate::{
+    api::ApiClient,
     error::{Error, Result, ResultExt},
-    message::{MessageSection, MessageSectionsMap, build_github_body, parse_message},
+    forge::{
+        CheckState, Forge, MergeMethod, PullRequest, PullRequestMergeability,
+        PullRequestRequestReviewers, PullRequestState, PullRequestStatus, PullRequestUpdate,
+        ReviewStatus, StatusCheck, UserWithName, fetch_pull_requests_branches,
+    },
+    message::{
+        MessageSection, MessageSectionsMap, build_github_body, parse_message, split_stack_block,
+    },
 };
 use std::collections::{HashMap, HashSet};
 
 #[derive(Clone)]
 pub struct GitHub {
     config: crate::config::Config,
-    graphql_client: reqwest::Client,
+    api: ApiClient,
 }
 
-#[derive(Debug, Clone)]
-pub struct PullRequest {
-    pub number: u64,
-    pub state: PullRequestState,
-    pub title: String,
-    pub body: Option<String>,
-    pub sections: MessageSectionsMap,
-    pub base: GitHubBranch,
-    pub head: GitHubBranch,
-    pub base_oid: git2::Oid,
-    pub head_oid: git2::Oid,
-    pub merge_commit: Option<git2::Oid>,
-    pub reviewers: HashMap<String, ReviewStatus>,
-    pub review_status: Option<ReviewStatus>,
+/// Fields queried for each Pull Request in `GitHub::get_pull_requests`. Like
+/// the status fields below, they are queried with one aliased `pullRequest`
+/// field per Pull Request.
+const PULL_REQUEST_FIELDS: &str = include_str!("gql/pullrequest_fields.graphql");
+
+/// Fields queried for each Pull Request in `GitHub::get_pull_request_statuses`.
+/// The query is put together at runtime, because it contains one aliased
+/// `pullRequest` field per Pull Request, which `graphql_client` cannot express.
+const PULL_REQUEST_STATUS_FIELDS: &str = include_str!("gql/pullrequest_status_fields.graphql");
+
+#[derive(serde::Serialize)]
+struct RepositoryQueryBody<'a> {
+    query: &'a str,
+    variables: RepositoryQueryVariables<'a>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ReviewStatus {
-    Requested,
-    Approved,
-    Rejected,
+#[derive(serde::Serialize)]
+struct RepositoryQueryVariables<'a> {
+    owner: &'a str,
+    name: &'a str,
 }
 
-#[derive(serde::Serialize, Default, Debug)]
-pub struct PullRequestUpdate {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub title: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub body: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub base: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub state: Option<PullRequestState>,
+#[derive(Deserialize)]
+struct RepositoryQueryData<T> {
+    repository: Option<HashMap<String, Option<T>>>,
 }
 
-impl PullRequestUpdate {
-    pub fn is_empty(&self) -> bool {
-        self.title.is_none() && self.body.is_none() && self.base.is_none() && self.state.is_none()
-    }
+#[derive(Deserialize)]
+struct RepositoryQueryResponse<T> {
+    data: Option<RepositoryQueryData<T>>,
+    errors: Option<Vec<RepositoryQueryError>>,
+}
 
-    pub fn update_message(&mut self, pull_request: &PullRequest, message: &MessageSectionsMap) {
-        let title = message.get(&MessageSection::Title);
-        if title.is_some() && title != Some(&pull_request.title) {
-            self.title = title.cloned();
-        }
+/// A GraphQL error, with the `type` GitHub adds to it
+#[derive(Deserialize)]
+struct RepositoryQueryError {
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    #[serde(flatten)]
+    error: graphql_client::Error,
+}
 
-        let body = build_github_body(message);
-        if pull_request.body.as_ref() != Some(&body) {
-            self.body = Some(body);
-        }
+impl RepositoryQueryError {
+    /// Whether this error says that one of the aliased `pullRequest` fields
+    /// refers to a Pull Request that does not exist
+    fn is_pull_request_not_found(&self, numbers: &[u64]) -> bool {
+        use graphql_client::PathFragment;
+
+        self.error_type.as_deref() == Some("NOT_FOUND")
+            && match self.error.path.as_deref() {
+                Some([PathFragment::Key(repository), PathFragment::Key(alias)]) => {
+                    repository == "repository"
+                        && numbers.iter().any(|number| *alias == format!("pr{number}"))
+                }
+                _ => false,
+            }
     }
 }
 
-#[derive(serde::Serialize, Default, Debug)]
-pub struct PullRequestRequestReviewers {
-    pub reviewers: Vec<String>,
-    pub team_reviewers: Vec<String>,
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestNode {
+    number: u64,
+    state: String,
+    review_decision: Option<String>,
+    title: String,
+    body: String,
+    base_ref_name: String,
+    head_ref_name: String,
+    merge_commit: Option<PullRequestCommit>,
+    latest_opinionated_reviews: Option<PullRequestNodes<PullRequestReview>>,
+    review_requests: Option<PullRequestNodes<PullRequestReviewRequest>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum PullRequestState {
-    Open,
-    Closed,
+#[derive(Deserialize)]
+struct PullRequestNodes<T> {
+    nodes: Option<Vec<Option<T>>>,
 }
 
-#[derive(serde::Deserialize, Debug, Clone)]
-pub struct UserWithName {
-    pub login: String,
-    pub name: Option<String>,
-    #[serde(default)]
-    pub is_collaborator: bool,
+#[derive(Deserialize)]
+struct PullRequestCommit {
+    oid: String,
 }
 
-#[derive(Debug, Clone)]
-pub struct PullRequestMergeability {
-    pub base: GitHubBranch,
-    pub head_oid: git2::Oid,
-    pub mergeable: Option<bool>,
-    pub merge_commit: Option<git2::Oid>,
+#[derive(Deserialize)]
+struct PullRequestReview {
+    author: Option<PullRequestReviewAuthor>,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequestReviewAuthor {
+    login: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestReviewRequest {
+    requested_reviewer: Option<RequestedReviewer>,
 }
 
+#[derive(Deserialize)]
+#[serde(tag = "__typename")]
+enum RequestedReviewer {
+    User {
+        login: String,
+    },
+    Team {
+        slug: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestStatusNode {
+    number: u64,
+    title: String,
+    body: String,
+    state: String,
+    is_draft: bool,
+    review_decision: Option<String>,
+    mergeable: String,
+    head_ref_oid: String,
+    status_check_rollup: Option<PullRequestStatusRollup>,
+    commits: PullRequestStatusCommits,
+}
+
+#[derive(Deserialize)]
+struct PullRequestStatusRollup {
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequestStatusCommits {
+    nodes: Option<Vec<Option<PullRequestStatusCommitNode>>>,
+}
+
+#[derive(Deserialize)]
+struct PullRequestStatusCommitNode {
+    commit: PullRequestStatusCommit,
+}
+
+#[derive(Deserialize)]
+struct PullRequestStatusCommit {
+    tree: PullRequestStatusTree,
+}
+
+#[derive(Deserialize)]
+struct PullRequestStatusTree {
+    oid: String,
+}
+
+type GitObjectID = String;
+#[allow(clippy::upper_case_acronyms)]
+type URI = String;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "src/gql/schema.docs.graphql",
-    query_path = "src/gql/pullrequest_query.graphql",
+    query_path = "src/gql/pullrequest_mergeability_query.graphql",
     response_derives = "Debug"
 )]
-pub struct PullRequestQuery;
-type GitObjectID = String;
+pub struct PullRequestMergeabilityQuery;
 
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "src/gql/schema.docs.graphql",
-    query_path = "src/gql/pullrequest_mergeability_query.graphql",
+    query_path = "src/gql/repository_merge_methods_query.graphql",
     response_derives = "Debug"
 )]
-pub struct PullRequestMergeabilityQuery;
+pub struct RepositoryMergeMethodsQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/gql/schema.docs.graphql",
+    query_path = "src/gql/enable_auto_merge_mutation.graphql",
+    response_derives = "Debug"
+)]
+pub struct EnableAutoMergeMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/gql/schema.docs.graphql",
+    query_path = "src/gql/enqueue_pull_request_mutation.graphql",
+    response_derives = "Debug"
+)]
+pub struct EnqueuePullRequestMutation;
 
 impl GitHub {
-    pub fn new(config: crate::config::Config, graphql_client: reqwest::Client) -> Self {
-        Self {
-            config,
-            graphql_client,
-        }
+    pub fn new(config: crate::config::Config, api: ApiClient) -> Self {
+        Self { config, api }
     }
 
-    pub async fn get_github_user(login: String) -> Result<UserWithName> {
-        octocrab::instance()
-            .get::<UserWithName, _, _>(format!("/users/{}", login), None::<&()>)
-            .await
-            .map_err(Error::from)
+    /// URL of a REST API endpoint of the repository
+    fn repo_url(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/{}",
+            self.config.api_url(),
+            self.config.owner,
+            self.config.repo,
+            path
+        )
     }
 
-    pub async fn get_github_team(
-        owner: String,
-        team: String,
-    ) -> Result<octocrab::models::teams::Team> {
-        octocrab::instance()
-            .teams(owner)
-            .get(team)
-            .await
-            .map_err(Error::from)
+    /// Send a GraphQL query, which is safe to repeat
+    async fn graphql_query<Q: serde::Serialize, R: DeserializeOwned>(
+        &self,
+        request_body: &Q,
+    ) -> Result<R> {
+        let request = self.api.post(&self.config.graphql_url()).json(request_body);
+        Ok(self.api.send_idempotent(request).await?.json().await?)
     }
 
-    pub async fn get_pull_request(self, number: u64) -> Result<PullRequest> {
-        let GitHub {
-            config,
-            graphql_client,
-        } = self;
+    /// Send a GraphQL mutation
+    async fn graphql_mutation<Q: serde::Serialize, T: DeserializeOwned>(
+        &self,
+        request_body: &Q,
+    ) -> Result<Response<T>> {
+        let request = self.api.post(&self.config.graphql_url()).json(request_body);
+        Ok(self.api.send(request).await?.json().await?)
+    }
 
-        let variables = pull_request_query::Variables {
-            name: config.repo.clone(),
-            owner: config.owner.clone(),
-            number: number as i64,
+    /// Query several Pull Requests at once, with one aliased `pullRequest`
+    /// field per Pull Request selecting the fields of `fragment`. Pull Requests
+    /// that do not exist are missing from the result, any other error fails
+    /// the whole query.
+    async fn query_pull_requests<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        fragment_name: &str,
+        fragment: &str,
+        numbers: &[u64],
+    ) -> Result<Vec<T>> {
+        if numbers.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let fields = numbers
+            .iter()
+            .map(|number| {
+                format!("pr{number}: pullRequest(number: {number}) {{ ...{fragment_name} }}")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let query = format!(
+            "query {operation}($owner: String!, $name: String!) {{\n\
+             repository(owner: $owner, name: $name) {{\n{fields}\n}}\n\
+             }}\n{fragment}"
+        );
+        let request_body = RepositoryQueryBody {
+            query: &query,
+            variables: RepositoryQueryVariables {
+                owner: &self.config.owner,
+                name: &self.config.repo,
+            },
         };
-        let request_body = PullRequestQuery::build_query(variables);
-        let res = graphql_client
-            .post("https://api.github.com/graphql")
-            .json(&request_body)
-            .send()
-            .await?;
-        let response_body: Response<pull_request_query::ResponseData> = res.json().await?;
+        let response_body: RepositoryQueryResponse<T> = self.graphql_query(&request_body).await?;
 
-        if let Some(errors) = response_body.errors {
-            let error = Err(Error::new(format!("fetching PR #{number} failed")));
+        let errors: Vec<_> = response_body
+            .errors
+            .into_iter()
+            .flatten()
+            .filter(|error| !error.is_pull_request_not_found(numbers))
+            .collect();
+        if !errors.is_empty() {
+            let numbers = numbers
+                .iter()
+                .map(|number| format!("#{number}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let error = Err(Error::new(format!("fetching PRs {numbers} failed")));
             return errors
                 .into_iter()
-                .fold(error, |err, e| err.context(e.to_string()));
+                .fold(error, |err, e| err.context(e.error.to_string()));
         }
 
-        let pr = response_body
+        Ok(response_body
             .data
-            .ok_or_else(|| Error::new("failed to fetch PR"))?
+            .ok_or_else(|| Error::new("failed to fetch Pull Requests"))?
             .repository
             .ok_or_else(|| Error::new("failed to find repository"))?
-            .pull_request
-            .ok_or_else(|| Error::new("failed to find PR"))?;
+            .into_values()
+            .flatten()
+            .collect())
+    }
+}
 
-        let base = config.new_github_branch_from_ref(&pr.base_ref_name)?;
-        let head = config.new_github_branch_from_ref(&pr.head_ref_name)?;
-
-        // Fetch refs from remote using git (since we're in a colocated repo)
-        let _fetch_result = tokio::process::Command::new("git")
-            .args([
-                "fetch",
-                "--no-write-fetch-head",
-                &config.remote_name,
-                &format!("{}:{}", head.on_github(), head.local()),
-                &format!("{}:{}", base.on_github(), base.local()),
-            ])
-            .output()
-            .await;
-
-        // Convert branch refs to OIDs
-        let base_oid = if let Ok(output) = tokio::process::Command::new("git")
-            .args(["rev-parse", base.local()])
-            .output()
-            .await
-        {
-            if output.status.success() {
-                let oid_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                git2::Oid::from_str(&oid_str).unwrap_or(git2::Oid::zero())
-            } else {
-                git2::Oid::zero()
-            }
+fn pull_request_from_node(
+    config: &crate::config::Config,
+    pr: PullRequestNode,
+    head: GitHubBranch,
+    base: GitHubBranch,
+    head_oid: git2::Oid,
+    base_oid: git2::Oid,
+) -> PullRequest {
+    let mut sections = parse_message(&split_stack_block(&pr.body).0, MessageSection::Summary);
+
+    let title = pr.title.trim().to_string();
+    sections.insert(
+        MessageSection::Title,
+        if title.is_empty() {
+            String::from("(untitled)")
         } else {
-            git2::Oid::zero()
-        };
+            title
+        },
+    );
 
-        let head_oid = if let Ok(output) = tokio::process::Command::new("git")
-            .args(["rev-parse", head.local()])
-            .output()
-            .await
-        {
-            if output.status.success() {
-                let oid_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                git2::Oid::from_str(&oid_str).unwrap_or(git2::Oid::zero())
+    sections.insert(
+        MessageSection::PullRequest,
+        config.pull_request_url(pr.number),
+    );
+
+    let reviewers: HashMap<String, ReviewStatus> = pr
+        .latest_opinionated_reviews
+        .iter()
+        .flat_map(|all_reviews| &all_reviews.nodes)
+        .flatten()
+        .flatten()
+        .flat_map(|review| {
+            let user_name = review.author.as_ref()?.login.clone();
+            let status = match &review.state[..] {
+                "APPROVED" => ReviewStatus::Approved,
+                "CHANGES_REQUESTED" => ReviewStatus::Rejected,
+                _ => ReviewStatus::Requested,
+            };
+            Some((user_name, status))
+        })
+        .collect();
+
+    let review_status = match pr.review_decision.as_deref() {
+        Some("APPROVED") => Some(ReviewStatus::Approved),
+        Some("CHANGES_REQUESTED") => Some(ReviewStatus::Rejected),
+        Some("REVIEW_REQUIRED") => Some(ReviewStatus::Requested),
+        _ => None,
+    };
+
+    let requested_reviewers: Vec<String> = pr
+        .review_requests
+        .iter()
+        .flat_map(|x| &x.nodes)
+        .flatten()
+        .flatten()
+        .flat_map(|x| &x.requested_reviewer)
+        .flat_map(|reviewer| match reviewer {
+            RequestedReviewer::User { login } => Some(login.clone()),
+            RequestedReviewer::Team { slug } => Some(format!("#{}", slug)),
+            RequestedReviewer::Other => None,
+        })
+        .chain(reviewers.keys().cloned())
+        .collect::<HashSet<String>>() // de-duplicate
+        .into_iter()
+        .collect();
+
+    sections.insert(
+        MessageSection::Reviewers,
+        requested_reviewers.iter().fold(String::new(), |out, slug| {
+            if out.is_empty() {
+                slug.to_string()
             } else {
-                git2::Oid::zero()
+                format!("{}, {}", out, slug)
             }
-        } else {
-            git2::Oid::zero()
-        };
-
-        let mut sections = parse_message(&pr.body, MessageSection::Summary);
+        }),
+    );
 
-        let title = pr.title.trim().to_string();
+    if review_status == Some(ReviewStatus::Approved) {
         sections.insert(
-            MessageSection::Title,
-            if title.is_empty() {
-                String::from("(untitled)")
-            } else {
-                title
-            },
+            MessageSection::ReviewedBy,
+            reviewers
+                .iter()
+                .filter_map(|(k, v)| {
+                    if v == &ReviewStatus::Approved {
+                        Some(k)
+                    } else {
+                        None
+                    }
+                })
+                .fold(String::new(), |out, slug| {
+                    if out.is_empty() {
+                        slug.to_string()
+                    } else {
+                        format!("{}, {}", out, slug)
+                    }
+                }),
         );
+    }
 
-        sections.insert(MessageSection::PullRequest, config.pull_request_url(number));
+    PullRequest {
+        number: pr.number,
+        state: match &pr.state[..] {
+            "OPEN" => PullRequestState::Open,
+            _ => PullRequestState::Closed,
+        },
+        title: pr.title,
+        body: Some(pr.body),
+        sections,
+        base,
+        head,
+        base_oid,
+        head_oid,
+        reviewers,
+        review_status,
+        merge_commit: pr
+            .merge_commit
+            .and_then(|sha| git2::Oid::from_str(&sha.oid).ok()),
+    }
+}
 
-        let reviewers: HashMap<String, ReviewStatus> = pr
-            .latest_opinionated_reviews
-            .iter()
-            .flat_map(|all_reviews| &all_reviews.nodes)
-            .flatten()
-            .flatten()
-            .flat_map(|review| {
-                let user_name = review.author.as_ref()?.login.clone();
-                let status = match review.state {
-                    pull_request_query::PullRequestReviewState::APPROVED => ReviewStatus::Approved,
-                    pull_request_query::PullRequestReviewState::CHANGES_REQUESTED => {
-                        ReviewStatus::Rejected
-                    }
-                    _ => ReviewStatus::Requested,
-                };
-                Some((user_name, status))
-            })
-            .collect();
+#[async_trait]
+impl Forge for GitHub {
+    async fn get_user(&self, login: &str) -> Result<UserWithName> {
+        let url = format!("{}/users/{}", self.config.api_url(), login);
+        Ok(self.api.send(self.api.get(&url)).await?.json().await?)
+    }
 
-        let review_status = match pr.review_decision {
-            Some(pull_request_query::PullRequestReviewDecision::APPROVED) => {
-                Some(ReviewStatus::Approved)
-            }
-            Some(pull_request_query::PullRequestReviewDecision::CHANGES_REQUESTED) => {
-                Some(ReviewStatus::Rejected)
-            }
-            Some(pull_request_query::PullRequestReviewDecision::REVIEW_REQUIRED) => {
-                Some(ReviewStatus::Requested)
-            }
-            _ => None,
-        };
+    async fn get_team(&self, slug: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Team {
+            slug: String,
+        }
+        let url = format!(
+            "{}/orgs/{}/teams/{}",
+            self.config.api_url(),
+            self.config.owner,
+            slug
+        );
+        let team: Team = self.api.send(self.api.get(&url)).await?.json().await?;
 
-        let requested_reviewers: Vec<String> = pr.review_requests
-            .iter()
-            .flat_map(|x| &x.nodes)
-            .flatten()
-            .flatten()
-            .flat_map(|x| &x.requested_reviewer)
-            .flat_map(|reviewer| {
-              type UserType = pull_request_query::PullRequestQueryRepositoryPullRequestReviewRequestsNodesRequestedReviewer;
-              match reviewer {
-                UserType::User(user) => Some(user.login.clone()),
-                UserType::Team(team) => Some(format!("#{}", team.slug)),
-                _ => None,
-              }
-            })
-            .chain(reviewers.keys().cloned())
-            .collect::<HashSet<String>>() // de-duplicate
-            .into_iter()
-            .collect();
+        Ok(team.slug)
+    }
 
-        sections.insert(
-            MessageSection::Reviewers,
-            requested_reviewers.iter().fold(String::new(), |out, slug| {
-                if out.is_empty() {
-                    slug.to_string()
-                } else {
-                    format!("{}, {}", out, slug)
-                }
-            }),
-        );
+    async fn get_pull_request(&self, number: u64) -> Result<PullRequest> {
+        self.get_pull_requests(&[number])
+            .await?
+            .remove(&number)
+            .ok_or_else(|| Error::new(format!("Pull Request #{number} not found")))
+    }
 
-        if review_status == Some(ReviewStatus::Approved) {
-            sections.insert(
-                MessageSection::ReviewedBy,
-                reviewers
-                    .iter()
-                    .filter_map(|(k, v)| {
-                        if v == &ReviewStatus::Approved {
-                            Some(k)
-                        } else {
-                            None
-                        }
-                    })
-                    .fold(String::new(), |out, slug| {
-                        if out.is_empty() {
-                            slug.to_string()
-                        } else {
-                            format!("{}, {}", out, slug)
-                        }
-                    }),
-            );
+    /// Load several Pull Requests with a single GraphQL query, fetching their
+    /// branches with one `git fetch` per remote.
+    async fn get_pull_requests(&self, numbers: &[u64]) -> Result<HashMap<u64, PullRequest>> {
+        let config = &self.config;
+        let nodes: Vec<PullRequestNode> = self
+            .query_pull_requests(
+                "PullRequestsQuery",
+                "PullRequestFields",
+                PULL_REQUEST_FIELDS,
+                numbers,
+            )
+            .await?;
+
+        if let Some(number) = numbers
+            .iter()
+            .find(|&&number| !nodes.iter().any(|pr| pr.number == number))
+        {
+            return Err(Error::new(format!("Pull Request #{number} not found")));
         }
 
-        Ok::<_, Error>(PullRequest {
-            number: pr.number as u64,
-            state: match pr.state {
-                pull_request_query::PullRequestState::OPEN => PullRequestState::Open,
-                _ => PullRequestState::Closed,
-            },
-            title: pr.title,
-            body: Some(pr.body),
-            sections,
-            base,
-            head,
-            base_oid,
-            head_oid,
-            reviewers,
-            review_status,
-            merge_commit: pr
-                .merge_commit
-                .and_then(|sha| git2::Oid::from_str(&sha.oid).ok()),
-        })
+        let branches = nodes
+            .iter()
+            .map(|pr| {
+                Ok((
+                    config.new_github_branch_from_ref(&pr.head_ref_name)?,
+                    config.new_github_branch_from_ref(&pr.base_ref_name)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let oids = fetch_pull_requests_branches(config, &branches).await?;
+
+        Ok(nodes
+            .into_iter()
+            .zip(branches)
+            .zip(oids)
+            .map(|((pr, (head, base)), (head_oid, base_oid))| {
+                (
+                    pr.number,
+                    pull_request_from_node(config, pr, head, base, head_oid, base_oid),
+                )
+            })
+            .collect())
     }
 
-    pub async fn create_pull_request(
+    async fn create_pull_request(
         &self,
         message: &MessageSectionsMap,
         base_ref_name: String,
         head_ref_name: String,
         draft: bool,
     ) -> Result<u64> {
-        let number = octocrab::instance()
-            .pulls(self.config.owner.clone(), self.config.repo.clone())
-            .create(
-                message
+        #[derive(serde::Serialize)]
+        struct NewPullRequest<'a> {
+            title: &'a str,
+            body: String,
+            head: String,
+            base: String,
+            draft: bool,
+        }
+        #[derive(Deserialize)]
+        struct Created {
+            number: u64,
+        }
+
+        let request = self
+            .api
+            .post(&self.repo_url("pulls"))
+            .json(&NewPullRequest {
+                title: message
                     .get(&MessageSection::Title)
-                    .unwrap_or(&String::new()),
-                head_ref_name,
-                base_ref_name,
-            )
-            .body(build_github_body(message))
-            .draft(Some(draft))
-            .send()
-            .await?
-            .number;
+                    .map(|title| &title[..])
+                    .unwrap_or_default(),
+                body: build_github_body(message),
+                // Branches in a fork are qualified with the fork's owner.
+                head: match &self.config.fork_owner {
+                    Some(owner) => format!("{owner}:{head_ref_name}"),
+                    None => head_ref_name,
+                },
+                base: base_ref_name,
+                draft,
+            });
+        let created: Created = self.api.send(request).await?.json().await?;
 
-        Ok(number)
+        Ok(created.number)
     }
 
-    pub async fn update_pull_request(&self, number: u64, updates: PullRequestUpdate) -> Result<()> {
-        octocrab::instance()
-            .patch::<octocrab::models::pulls::PullRequest, _, _>(
-                format!(
-                    "/repos/{}/{}/pulls/{}",
-                    self.config.owner, self.config.repo, number
-                ),
-                Some(&updates),
-            )
-            .await?;
+    async fn update_pull_request(&self, number: u64, updates: PullRequestUpdate) -> Result<()> {
+        // Setting the same values again changes nothing.
+        let request = self
+            .api
+            .patch(&self.repo_url(&format!("pulls/{number}")))
+            .json(&updates);
+        self.api.send_idempotent(request).await?;
 
         Ok(())
     }
 
-    pub async fn request_reviewers(
+    async fn request_reviewers(
         &self,
         number: u64,
         reviewers: PullRequestRequestReviewers,
     ) -> Result<()> {
-        #[derive(Deserialize)]
-        struct Ignore {}
-        let _: Ignore = octocrab::instance()
-            .post(
-                format!(
-                    "/repos/{}/{}/pulls/{}/requested_reviewers",
-                    self.config.owner, self.config.repo, number
-                ),
-                Some(&reviewers),
-            )
-            .await?;
+        // Requesting the same reviewers again changes nothing.
+        let request = self
+            .api
+            .post(&self.repo_url(&format!("pulls/{number}/requested_reviewers")))
+            .json(&reviewers);
+        self.api.send_idempotent(request).await?;
 
         Ok(())
     }
 
-    pub async fn get_pull_request_mergeability(
-        &self,
-        number: u64,
-    ) -> Result<PullRequestMergeability> {
+    async fn get_pull_request_mergeability(&self, number: u64) -> Result<PullRequestMergeability> {
         let variables = pull_request_mergeability_query::Variables {
             name: self.config.repo.clone(),
             owner: self.config.owner.clone(),
             number: number as i64,
         };
         let request_body = PullRequestMergeabilityQuery::build_query(variables);
-        let res = self
-            .graphql_client
-            .post("https://api.github.com/graphql")
-            .json(&request_body)
-            .send()
-            .await?;
         let response_body: Response<pull_request_mergeability_query::ResponseData> =
-            res.json().await?;
+            self.graphql_query(&request_body).await?;
 
         if let Some(errors) = response_body.errors {
             let error = Err(Error::new(format!(
@@ -439,7 +607,64 @@ impl GitHub {
             .pull_request
             .ok_or_else(|| Error::new("failed to find PR"))?;
 
+        let mut checks: Vec<StatusCheck> = pr
+            .status_check_rollup
+            .into_iter()
+            .flat_map(|rollup| rollup.contexts.nodes.into_iter().flatten().flatten())
+            .map(|node| {
+                use pull_request_mergeability_query::{
+                    CheckConclusionState, CheckStatusState,
+                    PullRequestMergeabilityQueryRepositoryPullRequestStatusCheckRollupContextsNodes as Node,
+                    StatusState,
+                };
+                match node {
+                    Node::CheckRun(run) => StatusCheck {
+                        state: match (run.status, run.conclusion) {
+                            (CheckStatusState::COMPLETED, Some(conclusion)) => match conclusion {
+                                CheckConclusionState::SUCCESS
+                                | CheckConclusionState::NEUTRAL
+                                | CheckConclusionState::SKIPPED => CheckState::Success,
+                                _ => CheckState::Failure,
+                            },
+                            _ => CheckState::Pending,
+                        },
+                        name: run.name,
+                        url: run.details_url,
+                        required: run.is_required,
+                    },
+                    Node::StatusContext(status) => StatusCheck {
+                        state: match status.state {
+                            StatusState::SUCCESS => CheckState::Success,
+                            StatusState::ERROR | StatusState::FAILURE => CheckState::Failure,
+                            _ => CheckState::Pending,
+                        },
+                        name: status.context,
+                        url: status.target_url,
+                        required: status.is_required,
+                    },
+                }
+            })
+            .collect();
+
+        // Required checks that have not reported anything yet are pending.
+        let required_contexts = pr
+            .base_ref
+            .and_then(|base_ref| base_ref.branch_protection_rule)
+            .and_then(|rule| rule.required_status_check_contexts)
+            .unwrap_or_default();
+        for context in required_contexts.into_iter().flatten() {
+            if !checks.iter().any(|check| check.name == context) {
+                checks.push(StatusCheck {
+                    name: context,
+                    state: CheckState::Pending,
+                    url: None,
+                    required: true,
+                });
+            }
+        }
+
         Ok::<_, Error>(PullRequestMergeability {
+            id: pr.id,
             base: self.config.new_github_branch_from_ref(&pr.base_ref_name)?,
             head_oid: git2::Oid::from_str(&pr.head_ref_oid)?,
             mergeable: match pr.mergeable {
@@ -451,14 +676,223 @@ impl GitHub {
             merge_commit: pr
                 .merge_commit
                 .and_then(|sha| git2::Oid::from_str(&sha.oid).ok()),
+            checks,
+            has_merge_queue: pr.merge_queue.is_some(),
         })
     }
+
+    async fn get_allowed_merge_methods(&self) -> Result<Vec<MergeMethod>> {
+        let variables = repository_merge_methods_query::Variables {
+            name: self.config.repo.clone(),
+            owner: self.config.owner.clone(),
+        };
+        let request_body = RepositoryMergeMethodsQuery::build_query(variables);
+        let response_body: Response<repository_merge_methods_query::ResponseData> =
+            self.graphql_query(&request_body).await?;
+
+        if let Some(errors) = response_body.errors {
+            let error = Err(Error::new("querying allowed merge methods failed"));
+            return errors
+                .into_iter()
+                .fold(error, |err, e| err.context(e.to_string()));
+        }
+
+        let repository = response_body
+            .data
+            .ok_or_else(|| Error::new("failed to fetch repository"))?
+            .repository
+            .ok_or_else(|| Error::new("failed to find repository"))?;
+
+        Ok([
+            (MergeMethod::Squash, repository.squash_merge_allowed),
+            (MergeMethod::Merge, repository.merge_commit_allowed),
+            (MergeMethod::Rebase, repository.rebase_merge_allowed),
+        ]
+        .into_iter()
+        .filter_map(|(method, allowed)| allowed.then_some(method))
+        .collect())
+    }
+
+    /// Load the status of several Pull Requests with a single GraphQL query.
+    /// Pull Requests that do not exist are missing from the result.
+    async fn get_pull_request_statuses(
+        &self,
+        numbers: &[u64],
+    ) -> Result<HashMap<u64, PullRequestStatus>> {
+        let pull_requests: Vec<PullRequestStatusNode> = self
+            .query_pull_requests(
+                "PullRequestStatusQuery",
+                "PullRequestStatusFields",
+                PULL_REQUEST_STATUS_FIELDS,
+                numbers,
+            )
+            .await?;
+
+        pull_requests
+            .into_iter()
+            .map(|pr| {
+                let head_tree_oid = pr
+                    .commits
+                    .nodes
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .next_back()
+                    .and_then(|node| git2::Oid::from_str(&node.commit.tree.oid).ok());
+
+                Ok((
+                    pr.number,
+                    PullRequestStatus {
+                        number: pr.number,
+                        title: pr.title,
+                        body: pr.body,
+                        state: if pr.state == "OPEN" {
+                            PullRequestState::Open
+                        } else {
+                            PullRequestState::Closed
+                        },
+                        is_draft: pr.is_draft,
+                        merged: pr.state == "MERGED",
+                        review_status: match pr.review_decision.as_deref() {
+                            Some("APPROVED") => Some(ReviewStatus::Approved),
+                            Some("CHANGES_REQUESTED") => Some(ReviewStatus::Rejected),
+                            Some("REVIEW_REQUIRED") => Some(ReviewStatus::Requested),
+                            _ => None,
+                        },
+                        checks: pr
+                            .status_check_rollup
+                            .map(|rollup| match &rollup.state[..] {
+                                "SUCCESS" => CheckState::Success,
+                                "ERROR" | "FAILURE" => CheckState::Failure,
+                                _ => CheckState::Pending,
+                            }),
+                        mergeable: match &pr.mergeable[..] {
+                            "MERGEABLE" => Some(true),
+                            "CONFLICTING" => Some(false),
+                            _ => None,
+                        },
+                        head_oid: git2::Oid::from_str(&pr.head_ref_oid)?,
+                        head_tree_oid,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    async fn merge_pull_request(
+        &self,
+        number: u64,
+        merge_method: MergeMethod,
+        title: &str,
+        body: &str,
+        head_oid: git2::Oid,
+    ) -> Result<Option<git2::Oid>> {
+        #[derive(serde::Serialize)]
+        struct Merge<'a> {
+            commit_title: &'a str,
+            commit_message: &'a str,
+            sha: String,
+            merge_method: String,
+        }
+        #[derive(Deserialize)]
+        struct Merged {
+            merged: bool,
+            message: Option<String>,
+            sha: Option<String>,
+        }
+
+        let request = self
+            .api
+            .put(&self.repo_url(&format!("pulls/{number}/merge")))
+            .json(&Merge {
+                commit_title: title,
+                commit_message: body,
+                sha: head_oid.to_string(),
+                merge_method: merge_method.to_string(),
+            });
+        let merge: Merged = self.api.send(request).await?.json().await?;
+
+        if !merge.merged {
+            return Err(Error::new(format!(
+                "GitHub Pull Request merge failed: {}",
+                merge.message.unwrap_or_default()
+            )));
+        }
+
+        Ok(merge.sha.and_then(|sha| git2::Oid::from_str(&sha).ok()))
+    }
+
+    /// Let GitHub merge the Pull Request as soon as all its requirements are
+    /// met. `pull_request_id` is the GraphQL node ID of the Pull Request.
+    async fn enable_auto_merge(
+        &self,
+        pull_request_id: &str,
+        merge_method: MergeMethod,
+        expected_head_oid: git2::Oid,
+        commit_headline: &str,
+        commit_body: &str,
+    ) -> Result<()> {
+        let variables = enable_auto_merge_mutation::Variables {
+            pull_request_id: pull_request_id.to_string(),
+            merge_method: match merge_method {
+                MergeMethod::Squash => enable_auto_merge_mutation::PullRequestMergeMethod::SQUASH,
+                MergeMethod::Merge => enable_auto_merge_mutation::PullRequestMergeMethod::MERGE,
+                MergeMethod::Rebase => enable_auto_merge_mutation::PullRequestMergeMethod::REBASE,
+            },
+            expected_head_oid: expected_head_oid.to_string(),
+            commit_headline: commit_headline.to_string(),
+            commit_body: commit_body.to_string(),
+        };
+        let request_body = EnableAutoMergeMutation::build_query(variables);
+        let response_body: Response<enable_auto_merge_mutation::ResponseData> =
+            self.graphql_mutation(&request_body).await?;
+
+        if let Some(errors) = response_body.errors {
+            let error = Err(Error::new("enabling auto-merge failed"));
+            return errors
+                .into_iter()
+                .fold(error, |err, e| err.context(e.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Add the Pull Request to the merge queue of its base branch, returning
+    /// its position in the queue. `pull_request_id` is the GraphQL node ID of
+    /// the Pull Request.
+    async fn enqueue_pull_request(
+        &self,
+        pull_request_id: &str,
+        expected_head_oid: git2::Oid,
+    ) -> Result<Option<i64>> {
+        let variables = enqueue_pull_request_mutation::Variables {
+            pull_request_id: pull_request_id.to_string(),
+            expected_head_oid: expected_head_oid.to_string(),
+        };
+        let request_body = EnqueuePullRequestMutation::build_query(variables);
+        let response_body: Response<enqueue_pull_request_mutation::ResponseData> =
+            self.graphql_mutation(&request_body).await?;
+
+        if let Some(errors) = response_body.errors {
+            let error = Err(Error::new("adding Pull Request to the merge queue failed"));
+            return errors
+                .into_iter()
+                .fold(error, |err, e| err.context(e.to_string()));
+        }
+
+        Ok(response_body
+            .data
+            .and_then(|data| data.enqueue_pull_request)
+            .and_then(|payload| payload.merge_queue_entry)
+            .map(|entry| entry.position))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct GitHubBranch {
     ref_on_github: String,
     ref_local: String,
+    remote_name: String,
     is_master_branch: bool,
 }
 
@@ -483,6 +917,7 @@ impl GitHubBranch {
         Ok(Self {
             ref_on_github,
             ref_local,
+            remote_name: remote_name.to_string(),
             is_master_branch,
         })
     }
@@ -495,6 +930,7 @@ impl GitHubBranch {
         Self {
             ref_on_github: format!("refs/heads/{branch_name}"),
             ref_local: format!("refs/remotes/{remote_name}/{branch_name}"),
+            remote_name: remote_name.to_string(),
             is_master_branch: branch_name == master_branch_name,
         }
     }
@@ -507,6 +943,11 @@ impl GitHubBranch {
         &self.ref_local
     }
 
+    /// The Git remote the branch is fetched from and pushed to
+    pub fn remote_name(&self) -> &str {
+        &self.remote_name
+    }
+
     pub fn is_master_branch(&self) -> bool {
         self.is_master_branch
     }
@@ -604,4 +1045,28 @@ mod tests {
         assert_eq!(r.branch_name(), "refs/heads/foo");
         assert!(!r.is_master_branch());
     }
+
+    #[test]
+    fn test_pull_request_not_found_error() {
+        let error = |json: &str| serde_json::from_str::<RepositoryQueryError>(json).unwrap();
+
+        let not_found = error(
+            r#"{"type": "NOT_FOUND", "path": ["repository", "pr42"],
+                "message": "Could not resolve to a PullRequest with the number of 42."}"#,
+        );
+        assert!(not_found.is_pull_request_not_found(&[1, 42]));
+        assert!(!not_found.is_pull_request_not_found(&[1, 4]));
+
+        let repository_not_found = error(
+            r#"{"type": "NOT_FOUND", "path": ["repository"],
+                "message": "Could not resolve to a Repository with the name 'acme/codez'."}"#,
+        );
+        assert!(!repository_not_found.is_pull_request_not_found(&[42]));
+
+        let forbidden = error(
+            r#"{"type": "FORBIDDEN", "path": ["repository", "pr42"],
+                "message": "Resource not accessible by integration"}"#,
+        );
+        assert!(!forbidden.is_pull_request_not_found(&[42]));
+    }
 }
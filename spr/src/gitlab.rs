@@ -0,0 +1,637 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Forge implementation for GitLab, using Merge Requests through the REST API
+//! (v4). Merge Requests are identified by their project-scoped `iid`, which
+//! spr treats as the Pull Request number.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    error::{Error, Result, ResultExt},
+    forge::{
+        CheckState, DraftTitle, Forge, MergeMethod, PullRequest, PullRequestMergeability,
+        PullRequestRequestReviewers, PullRequestState, PullRequestStatus, PullRequestUpdate,
+        ReviewStatus, StatusCheck, UserWithName, check_status, fetch_pull_request_branches,
+        json_if_found,
+    },
+    message::{
+        MessageSection, MessageSectionsMap, build_github_body, parse_message, split_stack_block,
+    },
+};
+
+#[derive(Clone)]
+pub struct GitLab {
+    config: crate::config::Config,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct MergeRequest {
+    iid: u64,
+    title: String,
+    description: Option<String>,
+    state: String,
+    source_branch: String,
+    target_branch: String,
+    sha: Option<String>,
+    merge_commit_sha: Option<String>,
+    squash_commit_sha: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    detailed_merge_status: Option<String>,
+    head_pipeline: Option<Pipeline>,
+    #[serde(default)]
+    reviewers: Vec<User>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Pipeline {
+    status: String,
+    web_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct User {
+    id: u64,
+    username: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Approvals {
+    approved: bool,
+    #[serde(default)]
+    approvals_left: u64,
+    #[serde(default)]
+    approved_by: Vec<Approver>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Approver {
+    user: User,
+}
+
+#[derive(Deserialize, Debug)]
+struct Project {
+    merge_method: String,
+    squash_option: Option<String>,
+}
+
+#[derive(Serialize, Default, Debug)]
+struct MergeRequestUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_event: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reviewer_ids: Option<Vec<u64>>,
+}
+
+#[derive(Serialize, Debug)]
+struct Merge {
+    sha: String,
+    squash: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    squash_commit_message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merge_commit_message: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    merge_when_pipeline_succeeds: bool,
+}
+
+impl Merge {
+    fn new(merge_method: MergeMethod, title: &str, body: &str, head_oid: git2::Oid) -> Self {
+        let message = if body.is_empty() {
+            title.to_string()
+        } else {
+            format!("{title}\n\n{body}")
+        };
+        let squash = merge_method == MergeMethod::Squash;
+
+        Self {
+            sha: head_oid.to_string(),
+            squash,
+            squash_commit_message: squash.then(|| message.clone()),
+            merge_commit_message: (merge_method == MergeMethod::Merge).then_some(message),
+            merge_when_pipeline_succeeds: false,
+        }
+    }
+}
+
+impl MergeRequest {
+    fn pull_request_state(&self) -> PullRequestState {
+        if self.state == "opened" {
+            PullRequestState::Open
+        } else {
+            PullRequestState::Closed
+        }
+    }
+
+    /// The commit the Merge Request added to its target branch. GitLab does
+    /// not report one for fast-forward merges, in which case it is the head
+    /// of the Merge Request.
+    fn merge_commit(&self) -> Option<git2::Oid> {
+        if self.state != "merged" {
+            return None;
+        }
+
+        self.squash_commit_sha
+            .as_ref()
+            .or(self.merge_commit_sha.as_ref())
+            .or(self.sha.as_ref())
+            .and_then(|sha| git2::Oid::from_str(sha).ok())
+    }
+
+    fn head_oid(&self) -> git2::Oid {
+        self.sha
+            .as_ref()
+            .and_then(|sha| git2::Oid::from_str(sha).ok())
+            .unwrap_or(git2::Oid::zero())
+    }
+
+    /// Whether the changes of the Merge Request can be merged into its target
+    /// branch. Other merge blockers, like missing approvals, do not count.
+    fn mergeable(&self) -> Option<bool> {
+        match self.detailed_merge_status.as_deref() {
+            None | Some("unchecked" | "checking" | "preparing" | "approvals_syncing") => None,
+            Some("conflict" | "need_rebase" | "broken_status") => Some(false),
+            Some(_) => Some(true),
+        }
+    }
+
+    fn checks(&self) -> Vec<StatusCheck> {
+        self.head_pipeline
+            .iter()
+            .map(|pipeline| StatusCheck {
+                name: "pipeline".to_string(),
+                state: match &pipeline.status[..] {
+                    "success" | "skipped" => CheckState::Success,
+                    "failed" | "canceled" => CheckState::Failure,
+                    _ => CheckState::Pending,
+                },
+                url: pipeline.web_url.clone(),
+                required: true,
+            })
+            .collect()
+    }
+}
+
+impl Approvals {
+    fn review_status(&self) -> Option<ReviewStatus> {
+        if self.approvals_left > 0 {
+            Some(ReviewStatus::Requested)
+        } else if self.approved && !self.approved_by.is_empty() {
+            Some(ReviewStatus::Approved)
+        } else {
+            None
+        }
+    }
+}
+
+/// Draft Merge Requests are marked by a prefix of their title
+const DRAFT_TITLE: DraftTitle = DraftTitle::new("Draft:", &["draft:", "[draft]", "(draft)"]);
+
+impl GitLab {
+    pub fn new(config: crate::config::Config, client: reqwest::Client) -> Self {
+        Self { config, client }
+    }
+
+    /// URL of the project in the API. The project is identified by its
+    /// URL-encoded path.
+    fn project_url(&self) -> String {
+        format!(
+            "{}/projects/{}%2F{}",
+            self.config.api_url(),
+            self.config.owner.replace('/', "%2F"),
+            self.config.repo
+        )
+    }
+
+    fn merge_request_url(&self, number: u64) -> String {
+        format!("{}/merge_requests/{}", self.project_url(), number)
+    }
+
+    async fn request<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        Ok(check_status("GitLab", request.send().await?)
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn get_merge_request(&self, number: u64) -> Result<MergeRequest> {
+        self.request(self.client.get(self.merge_request_url(number)))
+            .await
+            .context(format!("fetching Merge Request !{number} failed"))
+    }
+
+    /// Like `get_merge_request`, but a Merge Request that does not exist is
+    /// `None`. Any other failure is an error.
+    async fn find_merge_request(&self, number: u64) -> Result<Option<MergeRequest>> {
+        let response = self
+            .client
+            .get(self.merge_request_url(number))
+            .send()
+            .await?;
+        json_if_found("GitLab", response)
+            .await
+            .context(format!("fetching Merge Request !{number} failed"))
+    }
+
+    async fn get_approvals(&self, number: u64) -> Result<Approvals> {
+        self.request(
+            self.client
+                .get(format!("{}/approvals", self.merge_request_url(number))),
+        )
+        .await
+    }
+
+    async fn find_user(&self, username: &str) -> Result<User> {
+        let users: Vec<User> = self
+            .request(
+                self.client
+                    .get(format!("{}/users", self.config.api_url()))
+                    .query(&[("username", username)]),
+            )
+            .await?;
+
+        users
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::new(format!("GitLab user '{username}' not found")))
+    }
+
+    async fn put_merge_request(&self, number: u64, update: &MergeRequestUpdate) -> Result<()> {
+        let _: MergeRequest = self
+            .request(self.client.put(self.merge_request_url(number)).json(update))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn merge(&self, number: u64, merge: &Merge) -> Result<MergeRequest> {
+        self.request(
+            self.client
+                .put(format!("{}/merge", self.merge_request_url(number)))
+                .json(merge),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl Forge for GitLab {
+    async fn get_pull_request(&self, number: u64) -> Result<PullRequest> {
+        let config = &self.config;
+        let mr = self.get_merge_request(number).await?;
+        let approvals = self.get_approvals(number).await?;
+
+        let base = config.new_github_branch(&mr.target_branch);
+        let head = config.new_github_branch(&mr.source_branch);
+
+        let (head_oid, base_oid) = fetch_pull_request_branches(config, &head, &base).await?;
+
+        let body = mr.description.clone().unwrap_or_default();
+        let mut sections = parse_message(&split_stack_block(&body).0, MessageSection::Summary);
+
+        let title = DRAFT_TITLE.strip(&mr.title).trim().to_string();
+        sections.insert(
+            MessageSection::Title,
+            if title.is_empty() {
+                String::from("(untitled)")
+            } else {
+                title.clone()
+            },
+        );
+
+        sections.insert(MessageSection::PullRequest, config.pull_request_url(number));
+
+        let mut reviewers: HashMap<String, ReviewStatus> = mr
+            .reviewers
+            .iter()
+            .map(|user| (user.username.clone(), ReviewStatus::Requested))
+            .collect();
+        for approver in &approvals.approved_by {
+            reviewers.insert(approver.user.username.clone(), ReviewStatus::Approved);
+        }
+
+        let mut reviewer_names: Vec<&String> = reviewers.keys().collect();
+        reviewer_names.sort();
+        sections.insert(
+            MessageSection::Reviewers,
+            reviewer_names
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+
+        let review_status = approvals.review_status();
+        if review_status == Some(ReviewStatus::Approved) {
+            sections.insert(
+                MessageSection::ReviewedBy,
+                approvals
+                    .approved_by
+                    .iter()
+                    .map(|approver| approver.user.username.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+
+        Ok(PullRequest {
+            number: mr.iid,
+            state: mr.pull_request_state(),
+            title,
+            body: Some(body),
+            sections,
+            base,
+            head,
+            base_oid,
+            head_oid,
+            merge_commit: mr.merge_commit(),
+            reviewers,
+            review_status,
+        })
+    }
+
+    async fn create_pull_request(
+        &self,
+        message: &MessageSectionsMap,
+        base_ref_name: String,
+        head_ref_name: String,
+        draft: bool,
+    ) -> Result<u64> {
+        #[derive(Serialize)]
+        struct NewMergeRequest {
+            source_branch: String,
+            target_branch: String,
+            title: String,
+            description: String,
+        }
+
+        let title = message
+            .get(&MessageSection::Title)
+            .cloned()
+            .unwrap_or_default();
+        let mr: MergeRequest = self
+            .request(
+                self.client
+                    .post(format!("{}/merge_requests", self.project_url()))
+                    .json(&NewMergeRequest {
+                        source_branch: head_ref_name,
+                        target_branch: base_ref_name,
+                        title: if draft {
+                            DRAFT_TITLE.add(&title)
+                        } else {
+                            title
+                        },
+                        description: build_github_body(message),
+                    }),
+            )
+            .await?;
+
+        Ok(mr.iid)
+    }
+
+    async fn update_pull_request(&self, number: u64, updates: PullRequestUpdate) -> Result<()> {
+        // A new title must keep marking a draft Merge Request as draft.
+        let title = match updates.title {
+            Some(title) if self.get_merge_request(number).await?.draft => {
+                Some(DRAFT_TITLE.add(&title))
+            }
+            title => title,
+        };
+
+        self.put_merge_request(
+            number,
+            &MergeRequestUpdate {
+                title,
+                description: updates.body,
+                target_branch: updates.base,
+                state_event: updates.state.map(|state| match state {
+                    PullRequestState::Open => "reopen",
+                    PullRequestState::Closed => "close",
+                }),
+                reviewer_ids: None,
+            },
+        )
+        .await
+    }
+
+    async fn get_user(&self, login: &str) -> Result<UserWithName> {
+        let user = self.find_user(login).await?;
+
+        Ok(UserWithName {
+            login: user.username,
+            name: user.name,
+            is_collaborator: false,
+        })
+    }
+
+    async fn get_team(&self, slug: &str) -> Result<String> {
+        Err(Error::new(format!(
+            "Cannot request a review from '#{slug}': GitLab does not support team reviewers"
+        )))
+    }
+
+    async fn request_reviewers(
+        &self,
+        number: u64,
+        reviewers: PullRequestRequestReviewers,
+    ) -> Result<()> {
+        if let Some(team) = reviewers.team_reviewers.first() {
+            return self.get_team(team).await.map(|_| ());
+        }
+
+        let mut reviewer_ids = Vec::new();
+        for username in &reviewers.reviewers {
+            reviewer_ids.push(self.find_user(username).await?.id);
+        }
+
+        self.put_merge_request(
+            number,
+            &MergeRequestUpdate {
+                reviewer_ids: Some(reviewer_ids),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn get_pull_request_mergeability(&self, number: u64) -> Result<PullRequestMergeability> {
+        let mr = self.get_merge_request(number).await?;
+
+        Ok(PullRequestMergeability {
+            id: mr.iid.to_string(),
+            base: self.config.new_github_branch(&mr.target_branch),
+            head_oid: mr.head_oid(),
+            mergeable: mr.mergeable(),
+            merge_commit: mr.merge_commit(),
+            checks: mr.checks(),
+            has_merge_queue: false,
+        })
+    }
+
+    async fn get_pull_request_statuses(
+        &self,
+        numbers: &[u64],
+    ) -> Result<HashMap<u64, PullRequestStatus>> {
+        let statuses = futures::future::join_all(numbers.iter().map(|&number| async move {
+            // Merge Requests that do not exist are left out.
+            let Some(mr) = self.find_merge_request(number).await? else {
+                return Ok(None);
+            };
+            let approvals = self.get_approvals(number).await?;
+
+            Ok::<_, Error>(Some(PullRequestStatus {
+                number,
+                title: DRAFT_TITLE.strip(&mr.title).to_string(),
+                body: mr.description.clone().unwrap_or_default(),
+                state: mr.pull_request_state(),
+                is_draft: mr.draft,
+                merged: mr.state == "merged",
+                review_status: approvals.review_status(),
+                checks: mr.checks().first().map(|check| check.state),
+                mergeable: mr.mergeable(),
+                head_oid: mr.head_oid(),
+                head_tree_oid: None,
+            }))
+        }))
+        .await;
+
+        let mut result = HashMap::new();
+        for status in statuses {
+            if let Some(status) = status? {
+                result.insert(status.number, status);
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn get_allowed_merge_methods(&self) -> Result<Vec<MergeMethod>> {
+        let project: Project = self.request(self.client.get(self.project_url())).await?;
+
+        // Whether a Merge Request is squashed is chosen per Merge Request,
+        // unless the project enforces it. How the result ends up on the
+        // target branch is a project setting.
+        let squash_option = project.squash_option.as_deref().unwrap_or("default_off");
+        let mut methods = Vec::new();
+        if squash_option != "never" {
+            methods.push(MergeMethod::Squash);
+        }
+        if squash_option != "always" {
+            methods.push(if project.merge_method == "merge" {
+                MergeMethod::Merge
+            } else {
+                MergeMethod::Rebase
+            });
+        }
+
+        Ok(methods)
+    }
+
+    async fn merge_pull_request(
+        &self,
+        number: u64,
+        merge_method: MergeMethod,
+        title: &str,
+        body: &str,
+        head_oid: git2::Oid,
+    ) -> Result<Option<git2::Oid>> {
+        let mr = self
+            .merge(number, &Merge::new(merge_method, title, body, head_oid))
+            .await?;
+
+        if mr.state != "merged" {
+            return Err(Error::new(format!(
+                "GitLab Merge Request merge failed: Merge Request is {}",
+                mr.state
+            )));
+        }
+
+        Ok(mr.merge_commit())
+    }
+
+    async fn enable_auto_merge(
+        &self,
+        pull_request_id: &str,
+        merge_method: MergeMethod,
+        head_oid: git2::Oid,
+        title: &str,
+        body: &str,
+    ) -> Result<()> {
+        let number = pull_request_id
+            .parse()
+            .map_err(|_| Error::new(format!("invalid Merge Request id '{pull_request_id}'")))?;
+
+        self.merge(
+            number,
+            &Merge {
+                merge_when_pipeline_succeeds: true,
+                ..Merge::new(merge_method, title, body, head_oid)
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draft_title() {
+        assert_eq!(DRAFT_TITLE.add("Add a feature"), "Draft: Add a feature");
+        assert_eq!(DRAFT_TITLE.strip("Draft: Add a feature"), "Add a feature");
+        assert_eq!(DRAFT_TITLE.strip("[Draft] Add a feature"), "Add a feature");
+        assert_eq!(DRAFT_TITLE.strip("(draft)Add a feature"), "Add a feature");
+        assert_eq!(
+            DRAFT_TITLE.strip("Add a draft feature"),
+            "Add a draft feature"
+        );
+    }
+
+    #[test]
+    fn test_merge_request_mergeable() {
+        let mr = MergeRequest {
+            iid: 7,
+            title: "Draft: Add a feature".into(),
+            description: None,
+            state: "merged".into(),
+            source_branch: "spr/foo/add-a-feature".into(),
+            target_branch: "main".into(),
+            sha: Some("1111111111111111111111111111111111111111".into()),
+            merge_commit_sha: None,
+            squash_commit_sha: Some("2222222222222222222222222222222222222222".into()),
+            draft: true,
+            detailed_merge_status: Some("conflict".into()),
+            head_pipeline: Some(Pipeline {
+                status: "running".into(),
+                web_url: None,
+            }),
+            reviewers: vec![],
+        };
+
+        assert_eq!(mr.mergeable(), Some(false));
+        assert_eq!(mr.pull_request_state(), PullRequestState::Closed);
+        assert_eq!(
+            mr.merge_commit(),
+            Some(git2::Oid::from_str("2222222222222222222222222222222222222222").unwrap())
+        );
+        assert_eq!(mr.checks()[0].state, CheckState::Pending);
+    }
+}
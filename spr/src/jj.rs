@@ -5,58 +5,136 @@
  * LICENSE file in the root directory of this source tree.
  */
 
-use std::{
-    ffi::OsStr,
-    path::PathBuf,
-    process::{Command, Stdio},
-};
+#[cfg(not(feature = "jj-lib"))]
+mod cli;
+#[cfg(feature = "jj-lib")]
+mod workspace;
 
 use crate::{
-    config::Config,
+    config::{Config, ValueSource},
     error::{Error, Result, ResultExt},
     message::{MessageSection, MessageSectionsMap, build_commit_message, parse_message},
 };
 use git2::Oid;
+use std::{collections::HashMap, path::Path};
+
+#[cfg(not(feature = "jj-lib"))]
+use cli::Backend;
+#[cfg(feature = "jj-lib")]
+use workspace::Backend;
+
+/// Length of the change IDs jj shows by default
+const SHORT_CHANGE_ID_LENGTH: usize = 12;
 
 #[derive(Debug)]
 pub struct PreparedCommit {
     pub oid: Oid,
     pub short_id: String,
+    pub change_id: String,
     pub parent_oid: Oid,
     pub message: MessageSectionsMap,
     pub pull_request_number: Option<u64>,
     pub message_changed: bool,
+    pub empty: bool,
+    pub conflict: bool,
+    pub divergent: bool,
+}
+
+impl PreparedCommit {
+    pub fn short_change_id(&self) -> &str {
+        &self.change_id[..self.change_id.len().min(SHORT_CHANGE_ID_LENGTH)]
+    }
+
+    /// The revision that identifies the change of this commit, even after the
+    /// commit has been rewritten. A divergent change ID is ambiguous, so
+    /// those are identified by their commit ID.
+    fn revision(&self) -> String {
+        if self.divergent {
+            self.oid.to_string()
+        } else {
+            self.change_id.clone()
+        }
+    }
+}
+
+/// Commits connected through parent relationships, ancestors first
+#[derive(Debug)]
+pub struct Stack {
+    /// The commit on the master branch the stack is based on
+    pub master_base_oid: Oid,
+    pub commits: Vec<PreparedCommit>,
+}
+
+/// A commit as listed by jj, with everything needed to prepare it
+struct LogEntry {
+    commit_oid: Oid,
+    change_id: String,
+    parent_oids: Vec<Oid>,
+    description: String,
+    empty: bool,
+    conflict: bool,
+    divergent: bool,
 }
 
 pub struct Jujutsu {
-    repo_path: PathBuf,
-    jj_bin: PathBuf,
+    backend: Backend,
     pub git_repo: git2::Repository,
 }
 
 impl Jujutsu {
+    /// Open the Jujutsu workspace of `git_repo`: the workspace it is
+    /// colocated with, or the one whose repository stores it, e.g. in
+    /// `.jj/repo/store/git`.
     pub fn new(git_repo: git2::Repository) -> Result<Self> {
-        let repo_path = git_repo
-            .workdir()
-            .ok_or_else(|| Error::new("Repository must have a working directory".to_string()))?
-            .to_path_buf();
-
-        // Verify this is a Jujutsu repository
-        let jj_dir = repo_path.join(".jj");
-        if !jj_dir.exists() {
-            return Err(Error::new(
-                "This is not a Jujutsu repository. Run 'jj git init --colocate' to create one."
-                    .to_string(),
-            ));
-        }
+        let path = git_repo.workdir().unwrap_or_else(|| git_repo.path());
+        let backend = Backend::load(path).context(
+            "This is not a Jujutsu repository. Run 'jj git init --colocate' to create one."
+                .to_string(),
+        )?;
+
+        Ok(Self { backend, git_repo })
+    }
+
+    /// Open the Jujutsu workspace that contains `path`, and the Git
+    /// repository backing it. Unlike [`Jujutsu::new`], this also works in
+    /// secondary workspaces created with `jj workspace add`, whose Git
+    /// repository is elsewhere.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let backend = Backend::load(path)?;
+        let git_repo = git2::Repository::open(backend.git_repo_path()?)?;
 
-        let jj_bin = get_jj_bin();
+        Ok(Self { backend, git_repo })
+    }
+
+    /// The value of a Jujutsu config key, e.g. `spr.branchPrefix`
+    pub fn config_value(&self, key: &str) -> Option<String> {
+        self.backend.config_value(key)
+    }
 
-        Ok(Self {
-            repo_path,
-            jj_bin,
-            git_repo,
-        })
+    pub fn config_bool(&self, key: &str) -> Option<bool> {
+        self.backend.config_bool(key)
+    }
+
+    /// The keys and values in a table of the Jujutsu config, e.g. `spr`,
+    /// with where they are set. Values with lower precedence come first.
+    pub fn config_entries(&self, table: &str) -> Result<Vec<(String, String, ValueSource)>> {
+        self.backend.config_entries(table)
+    }
+
+    /// The content of the file at `path` in the commit `revision`, unless
+    /// the commit does not have that file
+    pub fn read_file_at_revision(&self, revision: &str, path: &str) -> Result<Option<Vec<u8>>> {
+        let commit_oid = self.resolve_revision_to_commit_id(revision)?;
+        // Jujutsu's root commit is not a Git commit.
+        let Ok(commit) = self.git_repo.find_commit(commit_oid) else {
+            return Ok(None);
+        };
+        let Ok(entry) = commit.tree()?.get_path(Path::new(path)) else {
+            return Ok(None);
+        };
+        let blob = entry.to_object(&self.git_repo)?.peel_to_blob()?;
+
+        Ok(Some(blob.content().to_vec()))
     }
 
     pub fn get_prepared_commit_for_revision(
@@ -64,67 +142,70 @@ impl Jujutsu {
         config: &Config,
         revision: &str,
     ) -> Result<PreparedCommit> {
-        let commit_oid = self.resolve_revision_to_commit_id(revision)?;
-        self.prepare_commit(config, commit_oid)
+        let entry = single_commit(revision, self.backend.log(revision)?)?;
+        Ok(self.prepare_commit(config, entry))
     }
 
-    pub fn get_master_base_for_commit(&self, config: &Config, commit_oid: Oid) -> Result<Oid> {
-        // Find the merge base between the commit and master
-        let master_oid = self.resolve_revision_to_commit_id(config.master_ref.local())?;
-        let merge_base = self.git_repo.merge_base(commit_oid, master_oid)?;
-        Ok(merge_base)
+    pub fn reload_prepared_commit(
+        &self,
+        config: &Config,
+        commit_oid: Oid,
+    ) -> Result<PreparedCommit> {
+        // The commit may have been rewritten since it was prepared, e.g. by a
+        // rebase. Its change ID stays the same, so use that to find the
+        // current commit.
+        let change_id = self.get_change_id_for_commit(commit_oid)?;
+        self.get_prepared_commit_for_revision(config, &change_id)
     }
 
-    pub fn get_prepared_commits_from_to(
+    /// Prepare all commits in the given revset, sorted topologically with
+    /// ancestors first
+    pub fn get_prepared_commits_for_revset(
         &self,
         config: &Config,
-        from_revision: &str,
-        to_revision: &str,
-        is_inclusive: bool,
+        revset: &str,
     ) -> Result<Vec<PreparedCommit>> {
-        // Get commit range using jj
-        let operator = if is_inclusive { "::" } else { ".." };
-        let output = self.run_captured_with_args([
-            "log",
-            "--no-graph",
-            "-r",
-            &format!("{}{}{}", from_revision, operator, to_revision),
-            "--template",
-            "commit_id ++ \"\\n\"",
-        ])?;
-
-        let mut commits = Vec::new();
-        for line in output.lines() {
-            let line = line.trim();
-            if !line.is_empty() {
-                let commit_oid = Oid::from_str(line).map_err(|e| {
-                    Error::new(format!("Failed to parse commit ID '{}': {}", line, e))
-                })?;
-                commits.push(self.prepare_commit(config, commit_oid)?);
-            }
-        }
+        // A single `jj log` lists everything needed about the commits.
+        let entries = self.backend.log(revset)?;
 
-        commits.reverse();
+        Ok(sort_topologically(entries)
+            .into_iter()
+            .map(|entry| self.prepare_commit(config, entry))
+            .collect())
+    }
 
-        Ok(commits)
+    /// Split topologically sorted commits into stacks of commits that are
+    /// connected through their parents, and find the commit on the master
+    /// branch each stack is based on
+    pub fn get_stacks(&self, config: &Config, commits: Vec<PreparedCommit>) -> Result<Vec<Stack>> {
+        let mut stacks: Vec<Stack> = Vec::new();
+        let mut stack_of_commit = HashMap::new();
+
+        for commit in commits {
+            let index = match stack_of_commit.get(&commit.parent_oid) {
+                Some(&index) => index,
+                None => {
+                    // The parent of the bottom commit of a stack is outside of
+                    // the stack. Should it not be on master, the stack is based
+                    // on where it branched off master.
+                    let master_oid =
+                        self.resolve_revision_to_commit_id(config.master_ref.local())?;
+                    stacks.push(Stack {
+                        master_base_oid: self.git_repo.merge_base(commit.parent_oid, master_oid)?,
+                        commits: Vec::new(),
+                    });
+                    stacks.len() - 1
+                }
+            };
+            stack_of_commit.insert(commit.oid, index);
+            stacks[index].commits.push(commit);
+        }
+
+        Ok(stacks)
     }
 
     pub fn check_no_uncommitted_changes(&self) -> Result<()> {
-        let output = self.run_captured_with_args(["status"])?;
-
-        // Check if there are any changes
-        // Jujutsu reports "The working copy has no changes" when clean
-        if output.trim().is_empty()
-            || output.contains("No changes.")
-            || output.contains("The working copy has no changes")
-        {
-            Ok(())
-        } else {
-            Err(Error::new(format!(
-                "You have uncommitted changes:\n{}",
-                output
-            )))
-        }
+        self.backend.check_no_uncommitted_changes()
     }
 
     pub fn get_all_ref_names(&self) -> Result<std::collections::HashSet<String>> {
@@ -142,6 +223,65 @@ impl Jujutsu {
         Ok(ref_names)
     }
 
+    pub fn get_pr_patch_branch_name(&self, pr_number: u64) -> Result<String> {
+        let ref_names = self.get_all_ref_names()?;
+        let default_name = format!("PR-{}", pr_number);
+        if !ref_names.contains(&format!("refs/heads/{}", default_name)) {
+            return Ok(default_name);
+        }
+
+        let mut count = 1;
+        loop {
+            let name = format!("PR-{}-{}", pr_number, count);
+            if !ref_names.contains(&format!("refs/heads/{}", name)) {
+                return Ok(name);
+            }
+            count += 1;
+        }
+    }
+
+    pub fn create_bookmark(&self, name: &str, commit_oid: Oid) -> Result<()> {
+        // Commits created through git2 are unknown to Jujutsu until they are
+        // reachable from a ref it has imported. Point a Git branch at the
+        // commit and import it, which turns the commit into a change with a
+        // bookmark of the same name.
+        self.git_repo.reference(
+            &format!("refs/heads/{}", name),
+            commit_oid,
+            false,
+            "spr created bookmark",
+        )?;
+        self.backend.import_git_refs()
+    }
+
+    pub fn new_change_on(&self, revision: &str) -> Result<()> {
+        self.backend.new_change_on(revision)
+    }
+
+    /// Rebase a landed change and its descendants onto the new master
+    /// commit. The new master commit already contains the changes of the
+    /// landed change, so it becomes empty and is abandoned, while its
+    /// descendants end up on top of the new master commit. Should the landed
+    /// change not become empty, it is kept so nothing gets lost. Returns
+    /// whether the landed change was abandoned.
+    pub fn abandon_landed_change(&self, commit_oid: Oid, new_master_oid: Oid) -> Result<bool> {
+        self.backend.rebase_landed(commit_oid, new_master_oid)
+    }
+
+    pub fn rebase_roots_onto(&self, revset: &str, destination: &str) -> Result<()> {
+        // Changes that become empty because their contents are already in the
+        // destination have been landed by other means, so they are abandoned.
+        self.backend
+            .rebase_skip_emptied(&format!("roots({})", revset), destination)
+    }
+
+    pub fn abandon(&self, commit_oid: Oid) -> Result<()> {
+        let change_id = self.get_change_id_for_commit(commit_oid)?;
+
+        // Descendants of the abandoned change are rebased onto its parent.
+        self.backend.abandon(&change_id)
+    }
+
     pub fn resolve_reference(&self, ref_name: &str) -> Result<Oid> {
         let reference = self.git_repo.find_reference(ref_name)?;
         reference
@@ -211,152 +351,187 @@ impl Jujutsu {
         Ok(index)
     }
 
+    pub fn merge_commits(&self, our_oid: Oid, their_oid: Oid) -> Result<git2::Index> {
+        let our_commit = self.git_repo.find_commit(our_oid)?;
+        let their_commit = self.git_repo.find_commit(their_oid)?;
+
+        Ok(self
+            .git_repo
+            .merge_commits(&our_commit, &their_commit, None)?)
+    }
+
     pub fn write_index(&self, mut index: git2::Index) -> Result<Oid> {
         Ok(index.write_tree_to(&self.git_repo)?)
     }
 
-    pub fn rewrite_commit_messages(&self, commits: &mut [PreparedCommit]) -> Result<()> {
+    pub fn rewrite_commit_messages<'a>(
+        &self,
+        commits: impl IntoIterator<Item = &'a mut PreparedCommit>,
+    ) -> Result<()> {
+        let commits: Vec<_> = commits.into_iter().collect();
         if commits.is_empty() {
             return Ok(());
         }
 
-        // Use jj describe to update commit messages, but only for commits that actually changed
-        for prepared_commit in commits.iter_mut() {
-            // Only update commits whose messages were actually modified
-            if !prepared_commit.message_changed {
-                continue;
-            }
-
-            let new_message = build_commit_message(&prepared_commit.message);
-
-            // Get the change ID for this commit
-            let change_id = self.get_change_id_for_commit(prepared_commit.oid)?;
-
-            // Update the commit message using jj describe
-            let mut cmd = Command::new(&self.jj_bin);
-            cmd.args(["describe", "-r", &change_id, "-m", &new_message])
-                .current_dir(&self.repo_path)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
-
-            let output = cmd.output()?;
-            if !output.status.success() {
-                return Err(Error::new(format!(
-                    "Failed to update commit message: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                )));
-            }
-
-            // Reset the flag after successful update
+        // Only update commits whose messages were actually modified, all in a
+        // single jj operation
+        let descriptions: Vec<_> = commits
+            .iter()
+            .filter(|prepared_commit| prepared_commit.message_changed)
+            .map(|prepared_commit| {
+                (
+                    prepared_commit.revision(),
+                    build_commit_message(&prepared_commit.message),
+                )
+            })
+            .collect();
+        self.backend.describe(&descriptions)?;
+
+        for prepared_commit in commits {
             prepared_commit.message_changed = false;
         }
 
         Ok(())
     }
 
-    fn prepare_commit(&self, config: &Config, commit_oid: Oid) -> Result<PreparedCommit> {
-        let commit = self.git_repo.find_commit(commit_oid)?;
-        let short_id = format!("{:.7}", commit_oid);
+    fn prepare_commit(&self, config: &Config, entry: LogEntry) -> PreparedCommit {
+        let short_id = format!("{:.7}", entry.commit_oid);
 
-        let parent_oid = if commit.parents().count() > 0 {
-            commit.parent(0)?.id()
-        } else {
-            // For initial commit, use a null OID or the commit itself
-            commit_oid
-        };
+        // For the root commit, which has no parents, use the commit itself
+        let parent_oid = entry
+            .parent_oids
+            .first()
+            .copied()
+            .unwrap_or(entry.commit_oid);
 
-        let message_text = commit.message().unwrap_or("").to_string();
-        let message = parse_message(&message_text, MessageSection::Title);
+        let message = parse_message(&entry.description, MessageSection::Title);
 
         let pull_request_number = message
             .get(&MessageSection::PullRequest)
             .and_then(|url| config.parse_pull_request_field(url));
 
-        Ok(PreparedCommit {
-            oid: commit_oid,
+        PreparedCommit {
+            oid: entry.commit_oid,
             short_id,
+            change_id: entry.change_id,
             parent_oid,
             message,
             pull_request_number,
             message_changed: false,
-        })
+            empty: entry.empty,
+            conflict: entry.conflict,
+            divergent: entry.divergent,
+        }
     }
 
     fn resolve_revision_to_commit_id(&self, revision: &str) -> Result<Oid> {
-        let output = self.run_captured_with_args([
-            "log",
-            "--no-graph",
-            "-r",
-            revision,
-            "--template",
-            "commit_id",
-        ])?;
-
-        let commit_id_str = output.trim();
-        Oid::from_str(commit_id_str).map_err(|e| {
-            Error::new(format!(
-                "Failed to parse commit ID '{}' from jj output: {}",
-                commit_id_str, e
-            ))
-        })
+        single_commit(revision, self.backend.resolve_revset(revision)?)
     }
 
     fn get_change_id_for_commit(&self, commit_oid: Oid) -> Result<String> {
-        // Get the change ID for a given commit OID
-        let output = self.run_captured_with_args([
-            "log",
-            "--no-graph",
-            "-r",
-            &commit_oid.to_string(),
-            "--template",
-            "change_id",
-        ])?;
-
-        Ok(output.trim().to_string())
-    }
-
-    fn run_captured_with_args<I, S>(&self, args: I) -> Result<String>
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<OsStr>,
-    {
-        let mut command = Command::new(&self.jj_bin);
-        command.args(args);
-        command.current_dir(&self.repo_path);
-        command.stdout(Stdio::piped());
-
-        let child = command.spawn().context("jj failed to spawn".to_string())?;
-        let output = child
-            .wait_with_output()
-            .context("failed to wait for jj to exit".to_string())?;
-
-        if output.status.success() {
-            let output = String::from_utf8(output.stdout)
-                .context("jujutsu output was not valid UTF-8".to_string())?;
-            Ok(output)
-        } else {
-            Err(Error::new(format!(
-                "jujutsu exited with code {}, stderr:\n{}",
-                output
-                    .status
-                    .code()
-                    .map_or_else(|| "(unknown)".to_string(), |c| c.to_string()),
-                String::from_utf8_lossy(&output.stderr)
-            )))
+        self.backend.change_id(commit_oid)
+    }
+}
+
+/// Sort the commits jj listed, newest first, so that every commit comes
+/// after its parents. Commits that do not depend on each other keep jj's
+/// order, reversed.
+fn sort_topologically(mut entries: Vec<LogEntry>) -> Vec<LogEntry> {
+    entries.reverse();
+    let index_of_commit: HashMap<Oid, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (entry.commit_oid, index))
+        .collect();
+
+    // Depth-first search, adding each commit once its parents are added
+    let mut visited = vec![false; entries.len()];
+    let mut order = Vec::with_capacity(entries.len());
+    for start in 0..entries.len() {
+        let mut to_visit = vec![(start, false)];
+        while let Some((index, parents_added)) = to_visit.pop() {
+            if parents_added {
+                order.push(index);
+                continue;
+            }
+            if visited[index] {
+                continue;
+            }
+            visited[index] = true;
+
+            to_visit.push((index, true));
+            for parent_oid in entries[index].parent_oids.iter().rev() {
+                match index_of_commit.get(parent_oid) {
+                    Some(&parent) if !visited[parent] => to_visit.push((parent, false)),
+                    _ => {}
+                }
+            }
         }
     }
+
+    let mut entries: Vec<_> = entries.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|index| entries[index].take())
+        .collect()
 }
 
-fn get_jj_bin() -> PathBuf {
-    std::env::var_os("JJ").map_or_else(|| "jj".into(), |v| v.into())
+/// The only commit `revision` resolved to
+fn single_commit<T>(revision: &str, mut commits: Vec<T>) -> Result<T> {
+    match commits.len() {
+        1 => Ok(commits.remove(0)),
+        0 => Err(Error::new(format!(
+            "Revision '{}' did not resolve to any commit",
+            revision
+        ))),
+        _ => Err(Error::new(format!(
+            "Revision '{}' resolved to more than one commit",
+            revision
+        ))),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{fs, path::Path};
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
     use tempfile::TempDir;
 
+    fn log_entry(commit: u8, parents: &[u8]) -> LogEntry {
+        let oid = |n: u8| Oid::from_bytes(&[n; 20]).unwrap();
+        LogEntry {
+            commit_oid: oid(commit),
+            change_id: String::new(),
+            parent_oids: parents.iter().copied().map(oid).collect(),
+            description: String::new(),
+            empty: false,
+            conflict: false,
+            divergent: false,
+        }
+    }
+
+    #[test]
+    fn test_sort_topologically() {
+        // 1 <- 2 <- 4, 1 <- 3, and 5 on its own, listed in an order where
+        // 2 comes before its parent.
+        let entries = vec![
+            log_entry(5, &[9]),
+            log_entry(4, &[2]),
+            log_entry(1, &[9]),
+            log_entry(3, &[1]),
+            log_entry(2, &[1]),
+        ];
+
+        let order: Vec<u8> = sort_topologically(entries)
+            .iter()
+            .map(|entry| entry.commit_oid.as_bytes()[0])
+            .collect();
+        assert_eq!(order, [1, 2, 3, 4, 5]);
+    }
+
     fn create_test_config() -> Config {
         Config::new(
             "test_owner".into(),
@@ -365,6 +540,12 @@ mod tests {
             "main".into(),
             "spr/test/".into(),
             false,
+            crate::forge::MergeMethod::Squash,
+            false,
+            Vec::new(),
+            crate::config::DEFAULT_GITHUB_HOST.into(),
+            crate::forge::ForgeKind::GitHub,
+            None,
         )
     }
 
@@ -438,8 +619,36 @@ mod tests {
         let git_repo = git2::Repository::open(&repo_path).expect("Failed to open git repository");
 
         let jj = Jujutsu::new(git_repo).expect("Failed to create Jujutsu instance");
-        assert!(jj.repo_path.exists());
-        assert!(jj.repo_path.join(".jj").exists());
+        let repo_path = jj
+            .git_repo
+            .workdir()
+            .expect("Repository has a working directory");
+        assert!(repo_path.exists());
+        assert!(repo_path.join(".jj").exists());
+    }
+
+    #[cfg(feature = "jj-lib")]
+    #[test]
+    fn test_jujutsu_creation_not_colocated() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path().join("workspace");
+        fs::create_dir(&path).unwrap();
+        let settings = jj_lib::settings::UserSettings::from_config(
+            jj_lib::config::StackedConfig::with_defaults(),
+        )
+        .unwrap();
+        jj_lib::workspace::Workspace::init_internal_git(&settings, &path).unwrap();
+
+        // The Git repository is inside the workspace, without a working
+        // directory of its own.
+        let git_repo = git2::Repository::open(path.join(".jj/repo/store/git")).unwrap();
+        let jj = Jujutsu::new(git_repo).expect("Failed to create Jujutsu instance");
+        let config = create_test_config();
+        assert!(jj.get_prepared_commit_for_revision(&config, "@").is_ok());
+
+        // A plain Git repository is no Jujutsu repository.
+        let git_repo = git2::Repository::init(temp_dir.path().join("git")).unwrap();
+        assert!(Jujutsu::new(git_repo).is_err());
     }
 
     #[test]
@@ -485,7 +694,7 @@ mod tests {
         let jj = Jujutsu::new(git_repo).expect("Failed to create Jujutsu instance");
 
         // Test getting commit range
-        let result = jj.get_prepared_commits_from_to(&config, "@----", "@-", false);
+        let result = jj.get_prepared_commits_for_revset(&config, "@----..@-");
         assert!(
             result.is_ok(),
             "Failed to get commit range: {:?}",
@@ -0,0 +1,509 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Jujutsu operations that run the `jj` binary. This is the backend when spr
+//! is built without the `jj-lib` feature.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use git2::Oid;
+use serde::Deserialize;
+
+use super::LogEntry;
+use crate::{
+    config::{ValueSource, toml_value_string},
+    error::{Error, Result, ResultExt},
+};
+
+/// Template for `jj log` that prints each commit as a line of JSON
+const LOG_TEMPLATE: &str = r#"
+"{" ++
+  "\"commit_id\":\"" ++ commit_id ++ "\"," ++
+  "\"change_id\":\"" ++ change_id ++ "\"," ++
+  "\"parents\":[" ++ parents.map(|c| "\"" ++ c.commit_id() ++ "\"").join(",") ++ "]," ++
+  "\"description\":" ++ description.escape_json() ++ "," ++
+  "\"empty\":" ++ if(empty, "true", "false") ++ "," ++
+  "\"conflict\":" ++ if(conflict, "true", "false") ++ "," ++
+  "\"divergent\":" ++ if(divergent, "true", "false") ++
+"}\n"
+"#;
+
+/// Template for `jj config list` that prints the source, name and value of
+/// each setting, separated by tabs
+const CONFIG_LIST_TEMPLATE: &str = r#"source ++ "\t" ++ name ++ "\t" ++ value ++ "\n""#;
+
+/// A line printed by `jj log` with [`LOG_TEMPLATE`]
+#[derive(Deserialize)]
+struct LogLine {
+    commit_id: String,
+    change_id: String,
+    parents: Vec<String>,
+    description: String,
+    empty: bool,
+    conflict: bool,
+    divergent: bool,
+}
+
+pub(super) struct Backend {
+    repo_path: PathBuf,
+    jj_bin: PathBuf,
+}
+
+impl Backend {
+    /// Load the workspace that contains `path`
+    pub(super) fn load(path: &Path) -> Result<Self> {
+        let backend = Self {
+            repo_path: path.to_path_buf(),
+            jj_bin: get_jj_bin(),
+        };
+        let workspace_root = backend.run_captured_with_args(["root"]).context(format!(
+            "There is no Jujutsu repository in {}",
+            path.display()
+        ))?;
+
+        Ok(Self {
+            repo_path: PathBuf::from(workspace_root.trim_end_matches(['\n', '\r'])),
+            ..backend
+        })
+    }
+
+    /// The Git repository backing the Jujutsu repository, e.g. the `.git`
+    /// directory of a colocated workspace or `.jj/repo/store/git`
+    pub(super) fn git_repo_path(&self) -> Result<PathBuf> {
+        let output = self.run_captured_with_args(["git", "root"])?;
+        Ok(PathBuf::from(output.trim_end_matches(['\n', '\r'])))
+    }
+
+    pub(super) fn config_value(&self, key: &str) -> Option<String> {
+        // Unlike other commands, the output of `jj config get` is captured
+        // entirely, as it complains on stderr about keys that are not set.
+        let output = Command::new(&self.jj_bin)
+            .args(["config", "get", key])
+            .current_dir(&self.repo_path)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let value = String::from_utf8(output.stdout).ok()?;
+        let value = value.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    }
+
+    pub(super) fn config_bool(&self, key: &str) -> Option<bool> {
+        match self.config_value(key)?.to_lowercase().as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    pub(super) fn config_entries(&self, table: &str) -> Result<Vec<(String, String, ValueSource)>> {
+        let output =
+            self.run_captured_with_args(["config", "list", "-T", CONFIG_LIST_TEMPLATE, table])?;
+
+        Ok(parse_config_list_output(&output, table))
+    }
+
+    /// The commits in `revset`, descendants first
+    pub(super) fn resolve_revset(&self, revset: &str) -> Result<Vec<Oid>> {
+        let output = self.run_captured_with_args([
+            "log",
+            "--no-graph",
+            "-r",
+            revset,
+            "--template",
+            "commit_id ++ \"\\n\"",
+        ])?;
+
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                Oid::from_str(line)
+                    .map_err(|e| Error::new(format!("Failed to parse commit ID '{}': {}", line, e)))
+            })
+            .collect()
+    }
+
+    /// The commits in `revset`, descendants first
+    pub(super) fn log(&self, revset: &str) -> Result<Vec<LogEntry>> {
+        let output = self.run_captured_with_args([
+            "log",
+            "--no-graph",
+            "-r",
+            revset,
+            "--template",
+            LOG_TEMPLATE,
+        ])?;
+
+        parse_log_output(&output)
+    }
+
+    pub(super) fn change_id(&self, commit_oid: Oid) -> Result<String> {
+        let output = self.run_captured_with_args([
+            "log",
+            "--no-graph",
+            "-r",
+            &commit_oid.to_string(),
+            "--template",
+            "change_id",
+        ])?;
+
+        Ok(output.trim().to_string())
+    }
+
+    pub(super) fn check_no_uncommitted_changes(&self) -> Result<()> {
+        let output = self.run_captured_with_args(["status"])?;
+
+        // Check if there are any changes
+        // Jujutsu reports "The working copy has no changes" when clean
+        if output.trim().is_empty()
+            || output.contains("No changes.")
+            || output.contains("The working copy has no changes")
+        {
+            Ok(())
+        } else {
+            Err(Error::new(format!(
+                "You have uncommitted changes:\n{}",
+                output
+            )))
+        }
+    }
+
+    pub(super) fn import_git_refs(&self) -> Result<()> {
+        self.run_captured_with_args(["git", "import"])?;
+        Ok(())
+    }
+
+    pub(super) fn new_change_on(&self, revision: &str) -> Result<()> {
+        self.run_captured_with_args(["new", revision])?;
+        Ok(())
+    }
+
+    pub(super) fn rebase_skip_emptied(&self, source: &str, destination: &str) -> Result<()> {
+        self.run_captured_with_args([
+            "rebase",
+            "--skip-emptied",
+            "--source",
+            source,
+            "--destination",
+            destination,
+        ])?;
+        Ok(())
+    }
+
+    /// Rebase the landed commit and its descendants onto the new master
+    /// commit, abandoning commits that become empty. jj imports the fetched
+    /// refs as part of the same command. Returns whether the landed commit
+    /// was abandoned.
+    pub(super) fn rebase_landed(&self, commit_oid: Oid, new_master_oid: Oid) -> Result<bool> {
+        let change_id = self.change_id(commit_oid)?;
+        self.rebase_skip_emptied(&change_id, &new_master_oid.to_string())?;
+
+        let remaining = self.resolve_revset(&format!("present({})", change_id))?;
+        Ok(remaining.is_empty())
+    }
+
+    pub(super) fn abandon(&self, revision: &str) -> Result<()> {
+        self.run_captured_with_args(["abandon", revision])?;
+        Ok(())
+    }
+
+    /// Set the descriptions of the given revisions, which are change IDs or
+    /// commit IDs, with a single `jj describe`
+    pub(super) fn describe(&self, descriptions: &[(String, String)]) -> Result<()> {
+        let [(revision, description)] = descriptions else {
+            return self.describe_several(descriptions);
+        };
+
+        self.run_captured_with_args(["describe", "-r", revision, "-m", description])
+            .context("Failed to update commit message".to_string())?;
+        Ok(())
+    }
+
+    fn describe_several(&self, descriptions: &[(String, String)]) -> Result<()> {
+        if descriptions.is_empty() {
+            return Ok(());
+        }
+
+        // With `-m`, `jj describe` gives all revisions the same description.
+        // Separate descriptions can only be entered in the editor, keyed by
+        // commit ID, so look up the current commits of the revisions and let
+        // the "editor" copy the prepared descriptions into place.
+        let revset = descriptions
+            .iter()
+            .map(|(revision, _)| format!("({})", revision))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let entries = self.log(&revset)?;
+
+        let mut commit_ids = Vec::new();
+        let mut descriptions_by_commit = Vec::new();
+        for (revision, description) in descriptions {
+            let entry = entries
+                .iter()
+                .find(|entry| {
+                    &entry.change_id == revision || &entry.commit_oid.to_string() == revision
+                })
+                .ok_or_else(|| Error::new(format!("Revision '{}' not found", revision)))?;
+            commit_ids.push(entry.commit_oid.to_string());
+            descriptions_by_commit.push((entry.commit_oid, description.as_str()));
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("spr-describe-{}.jjdescription", std::process::id()));
+        std::fs::write(&path, build_bulk_description(&descriptions_by_commit))
+            .context("Failed to write commit messages".to_string())?;
+
+        let editor = serde_json::to_string(&["cp", &path.to_string_lossy()])?;
+        let editor_config = format!("ui.editor={}", editor);
+        let mut args = vec!["describe", "--config", &editor_config];
+        args.extend(commit_ids.iter().map(String::as_str));
+        let result = self.run_captured_with_args(args);
+        std::fs::remove_file(&path).ok();
+
+        result.context("Failed to update commit messages".to_string())?;
+        Ok(())
+    }
+
+    fn run_captured_with_args<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new(&self.jj_bin);
+        command.args(args);
+        command.current_dir(&self.repo_path);
+        command.stdout(Stdio::piped());
+
+        let child = command.spawn().context("jj failed to spawn".to_string())?;
+        let output = child
+            .wait_with_output()
+            .context("failed to wait for jj to exit".to_string())?;
+
+        if output.status.success() {
+            let output = String::from_utf8(output.stdout)
+                .context("jujutsu output was not valid UTF-8".to_string())?;
+            Ok(output)
+        } else {
+            Err(Error::new(format!(
+                "jujutsu exited with code {}, stderr:\n{}",
+                output
+                    .status
+                    .code()
+                    .map_or_else(|| "(unknown)".to_string(), |c| c.to_string()),
+                String::from_utf8_lossy(&output.stderr)
+            )))
+        }
+    }
+}
+
+fn parse_log_output(output: &str) -> Result<Vec<LogEntry>> {
+    let parse_oid = |id: &str| {
+        Oid::from_str(id)
+            .map_err(|e| Error::new(format!("Failed to parse commit ID '{}': {}", id, e)))
+    };
+
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let line: LogLine = serde_json::from_str(line)
+                .map_err(|e| Error::new(format!("Failed to parse jj log output: {}", e)))?;
+            Ok(LogEntry {
+                commit_oid: parse_oid(&line.commit_id)?,
+                change_id: line.change_id,
+                parent_oids: line
+                    .parents
+                    .iter()
+                    .map(|id| parse_oid(id))
+                    .collect::<Result<_>>()?,
+                description: line.description,
+                empty: line.empty,
+                conflict: line.conflict,
+                divergent: line.divergent,
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of `jj config list` with [`CONFIG_LIST_TEMPLATE`].
+/// Overridden values are not listed, and jj lists values with lower
+/// precedence first.
+fn parse_config_list_output(output: &str, table: &str) -> Vec<(String, String, ValueSource)> {
+    let prefix = format!("{table}.");
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let source = match fields.next()? {
+                "default" => ValueSource::Default,
+                "env" => ValueSource::Env,
+                "user" => ValueSource::JjUser,
+                "repo" => ValueSource::JjRepo,
+                _ => ValueSource::CliFlag,
+            };
+            let key = fields.next()?.strip_prefix(&prefix)?;
+            let value = parse_toml_value(fields.next()?);
+            Some((key.to_string(), value, source))
+        })
+        .collect()
+}
+
+/// A value as jj prints it in TOML syntax, without the quotes around strings
+fn parse_toml_value(value: &str) -> String {
+    match value.parse::<toml_edit::Value>() {
+        Ok(value) => toml_value_string(&value),
+        Err(_) => value.trim().to_string(),
+    }
+}
+
+/// The content of the file `jj describe` lets the user edit when it
+/// describes several commits. Lines starting with `JJ:` are comments, so
+/// they are dropped from the descriptions. jj finds the commits by the
+/// 12-digit short commit IDs in the headers, and rejects full commit IDs.
+fn build_bulk_description(descriptions: &[(Oid, &str)]) -> String {
+    descriptions
+        .iter()
+        .map(|(commit_oid, description)| {
+            format!(
+                "JJ: describe {} -------\n{}\n",
+                &commit_oid.to_string()[..12],
+                description.trim_end()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn get_jj_bin() -> PathBuf {
+    std::env::var_os("JJ").map_or_else(|| "jj".into(), |v| v.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_output() {
+        let output = concat!(
+            r#"{"commit_id":"1111111111111111111111111111111111111111","change_id":"kkkk","parents":["2222222222222222222222222222222222222222"],"description":"Second\n\nWith \"quotes\"\n","empty":false,"conflict":true,"divergent":false}"#,
+            "\n",
+            r#"{"commit_id":"2222222222222222222222222222222222222222","change_id":"zzzz","parents":[],"description":"","empty":true,"conflict":false,"divergent":true}"#,
+            "\n",
+        );
+
+        let entries = parse_log_output(output).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].change_id, "kkkk");
+        assert_eq!(entries[0].parent_oids, [entries[1].commit_oid]);
+        assert_eq!(entries[0].description, "Second\n\nWith \"quotes\"\n");
+        assert!(entries[0].conflict && !entries[0].empty && !entries[0].divergent);
+        assert!(entries[1].parent_oids.is_empty());
+        assert!(entries[1].empty && entries[1].divergent);
+
+        assert!(parse_log_output("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_config_list_output() {
+        let output = "user\tspr.branchPrefix\t\"jj-spr/me/\"\n\
+                      repo\tspr.requireApproval\ttrue\n\
+                      repo\tspr.githubRepository\t'acme/codez'\n\
+                      cli\tspr.mergeMethod\t\"re\\\"base\"\n";
+
+        assert_eq!(
+            parse_config_list_output(output, "spr"),
+            [
+                (
+                    "branchPrefix".to_string(),
+                    "jj-spr/me/".to_string(),
+                    ValueSource::JjUser
+                ),
+                (
+                    "requireApproval".to_string(),
+                    "true".to_string(),
+                    ValueSource::JjRepo
+                ),
+                (
+                    "githubRepository".to_string(),
+                    "acme/codez".to_string(),
+                    ValueSource::JjRepo
+                ),
+                (
+                    "mergeMethod".to_string(),
+                    "re\"base".to_string(),
+                    ValueSource::CliFlag
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_bulk_description() {
+        let first = Oid::from_str("1111111111111111111111111111111111111111").unwrap();
+        let second = Oid::from_str("2222222222222222222222222222222222222222").unwrap();
+
+        assert_eq!(
+            build_bulk_description(&[(first, "First\n\nBody\n"), (second, "Second")]),
+            "JJ: describe 111111111111 -------\n\
+             First\n\nBody\n\n\
+             JJ: describe 222222222222 -------\n\
+             Second\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_several() {
+        // This needs the jj binary.
+        if Command::new(get_jj_bin())
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let backend = Backend {
+            repo_path: temp_dir.path().to_path_buf(),
+            jj_bin: get_jj_bin(),
+        };
+        backend.run_captured_with_args(["git", "init"]).unwrap();
+        backend.run_captured_with_args(["new"]).unwrap();
+
+        let entries = backend.log("root()..@").unwrap();
+        assert_eq!(entries.len(), 2);
+        backend
+            .describe_several(&[
+                (entries[0].change_id.clone(), "First\n\nBody\n".to_string()),
+                (entries[1].change_id.clone(), "Second\n".to_string()),
+            ])
+            .unwrap();
+
+        let descriptions: Vec<_> = backend
+            .log("root()..@")
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.change_id, entry.description))
+            .collect();
+        assert_eq!(
+            descriptions,
+            [
+                (entries[0].change_id.clone(), "First\n\nBody\n".to_string()),
+                (entries[1].change_id.clone(), "Second\n".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1022 @@
+/*
+ * Copyright (c) Radical HQ Limited
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Jujutsu operations performed in-process with jj-lib. The workspace is
+//! loaded, and its working copy snapshotted, once per spr invocation instead
+//! of once per `jj` command.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
+use git2::Oid;
+use jj_lib::{
+    backend::CommitId,
+    commit::Commit,
+    config::{
+        ConfigLayer, ConfigNamePathBuf, ConfigResolutionContext, ConfigSource, StackedConfig,
+        resolve,
+    },
+    fileset::{self, FilesetDiagnostics},
+    git,
+    gitignore::GitIgnoreFile,
+    hex_util::encode_reverse_hex,
+    object_id::ObjectId,
+    ref_name::WorkspaceNameBuf,
+    repo::{ReadonlyRepo, Repo, StoreFactories},
+    repo_path::RepoPathUiConverter,
+    revset::{
+        self, DefaultSymbolResolver, RevsetAliasesMap, RevsetDiagnostics, RevsetExtensions,
+        RevsetParseContext, RevsetWorkspaceContext, UserRevsetExpression,
+    },
+    rewrite::{EmptyBehaviour, MoveCommitsTarget, RebaseOptions, RewriteRefsOptions, move_commits},
+    settings::UserSettings,
+    working_copy::{CheckoutOptions, SnapshotOptions, WorkingCopyFreshness},
+    workspace::{
+        DefaultWorkspaceLoaderFactory, Workspace, WorkspaceLoaderFactory,
+        default_working_copy_factories,
+    },
+};
+
+use super::{LogEntry, SHORT_CHANGE_ID_LENGTH};
+use crate::{
+    config::{ValueSource, toml_value_string},
+    error::{Error, Result, ResultExt},
+};
+
+pub(super) struct Backend {
+    workspace: RefCell<Workspace>,
+    repo: RefCell<Arc<ReadonlyRepo>>,
+    settings: UserSettings,
+    workspace_name: WorkspaceNameBuf,
+    revset_aliases: RevsetAliasesMap,
+    revset_extensions: RevsetExtensions,
+    path_converter: RepoPathUiConverter,
+    /// Whether the workspace shares its working copy with the Git repository,
+    /// whose refs and HEAD then have to be kept in sync.
+    colocated: bool,
+}
+
+impl Backend {
+    /// Load the workspace that contains `path`
+    pub(super) fn load(path: &Path) -> Result<Self> {
+        // Like jj, look for the workspace root in `path` and its ancestors.
+        let workspace_root = path
+            .ancestors()
+            .find(|dir| dir.join(".jj").is_dir())
+            .ok_or_else(|| {
+                Error::new(format!(
+                    "There is no Jujutsu repository in {}",
+                    path.display()
+                ))
+            })?;
+
+        let loader = DefaultWorkspaceLoaderFactory.create(workspace_root)?;
+        let config = load_config(loader.workspace_root(), loader.repo_path())
+            .context("could not load the Jujutsu configuration".to_string())?;
+        let settings = UserSettings::from_config(config)?;
+
+        let workspace = loader.load(
+            &settings,
+            &StoreFactories::default(),
+            &default_working_copy_factories(),
+        )?;
+        let repo = workspace.repo_loader().load_at_head()?;
+
+        let workspace_root = workspace.workspace_root().to_path_buf();
+        let colocated = git::get_git_backend(repo.store())
+            .ok()
+            .and_then(|backend| backend.git_workdir()?.canonicalize().ok())
+            .is_some_and(|workdir| Some(workdir) == workspace_root.canonicalize().ok());
+
+        let backend = Self {
+            workspace_name: workspace.workspace_name().to_owned(),
+            workspace: RefCell::new(workspace),
+            repo: RefCell::new(repo),
+            revset_aliases: load_revset_aliases(&settings)?,
+            revset_extensions: RevsetExtensions::default(),
+            path_converter: RepoPathUiConverter::Fs {
+                cwd: std::env::current_dir()?,
+                base: workspace_root,
+            },
+            settings,
+            colocated,
+        };
+
+        // The same preparations the jj command line tool makes before every
+        // command.
+        if backend.colocated {
+            backend.import_git_head()?;
+        }
+        let git_repo = git2::Repository::open(backend.git_repo_path()?)?;
+        backend.snapshot_working_copy(&git_repo)?;
+        backend.import_git_refs()?;
+
+        Ok(backend)
+    }
+
+    /// The Git repository backing the Jujutsu repository, e.g. the `.git`
+    /// directory of a colocated workspace or `.jj/repo/store/git`
+    pub(super) fn git_repo_path(&self) -> Result<PathBuf> {
+        let repo = self.repo.borrow();
+        Ok(git::get_git_backend(repo.store())?
+            .git_repo_path()
+            .to_path_buf())
+    }
+
+    pub(super) fn config_value(&self, key: &str) -> Option<String> {
+        let name: ConfigNamePathBuf = key.parse().ok()?;
+        let value = toml_value_string(&self.settings.get_value(&name).ok()?);
+        (!value.is_empty()).then_some(value)
+    }
+
+    pub(super) fn config_bool(&self, key: &str) -> Option<bool> {
+        let name: ConfigNamePathBuf = key.parse().ok()?;
+        let value = self.settings.get_value(&name).ok()?;
+        value
+            .as_bool()
+            .or_else(|| match value.as_str()?.trim().to_lowercase().as_str() {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => None,
+            })
+    }
+
+    pub(super) fn config_entries(&self, table: &str) -> Result<Vec<(String, String, ValueSource)>> {
+        let name = ConfigNamePathBuf::from_iter([table]);
+        let mut entries = Vec::new();
+        // The layers are sorted by precedence, lowest first.
+        for layer in self.settings.config().layers() {
+            let Ok(Some(table)) = layer.look_up_table(&name) else {
+                continue;
+            };
+            let source = match layer.source {
+                ConfigSource::Default => ValueSource::Default,
+                ConfigSource::EnvBase | ConfigSource::EnvOverrides => ValueSource::Env,
+                ConfigSource::User => ValueSource::JjUser,
+                ConfigSource::Repo => ValueSource::JjRepo,
+                ConfigSource::CommandArg => ValueSource::CliFlag,
+            };
+            for (key, item) in table.iter() {
+                if let Some(value) = item.as_value() {
+                    entries.push((key.to_string(), toml_value_string(value), source));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The commits in `revset`, descendants first
+    pub(super) fn resolve_revset(&self, revset: &str) -> Result<Vec<Oid>> {
+        // Git commands spr runs, like fetching, may have moved refs since the
+        // workspace was loaded.
+        self.import_git_refs()?;
+
+        self.evaluate(revset)?.iter().map(oid).collect()
+    }
+
+    /// The commits in `revset`, descendants first
+    pub(super) fn log(&self, revset: &str) -> Result<Vec<LogEntry>> {
+        self.import_git_refs()?;
+
+        let repo = self.repo.borrow().clone();
+        let commit_ids = self.evaluate(revset)?;
+        self.commits(&commit_ids)?
+            .into_iter()
+            .map(|commit| {
+                let divergent = repo
+                    .resolve_change_id(commit.change_id())
+                    .is_some_and(|commit_ids| commit_ids.len() > 1);
+                Ok(LogEntry {
+                    commit_oid: oid(commit.id())?,
+                    change_id: encode_reverse_hex(commit.change_id().as_bytes()),
+                    parent_oids: commit.parent_ids().iter().map(oid).collect::<Result<_>>()?,
+                    description: commit.description().to_string(),
+                    empty: commit.is_empty(repo.as_ref())?,
+                    conflict: commit.has_conflict()?,
+                    divergent,
+                })
+            })
+            .collect()
+    }
+
+    pub(super) fn change_id(&self, commit_oid: Oid) -> Result<String> {
+        let commit = self.commit(commit_oid)?;
+        Ok(encode_reverse_hex(commit.change_id().as_bytes()))
+    }
+
+    pub(super) fn check_no_uncommitted_changes(&self) -> Result<()> {
+        let repo = self.repo.borrow().clone();
+        let Some(wc_commit) = self.wc_commit(&repo)? else {
+            return Ok(());
+        };
+
+        if wc_commit.is_empty(repo.as_ref())? {
+            Ok(())
+        } else {
+            Err(Error::new(format!(
+                "You have uncommitted changes in the working copy ({})",
+                &encode_reverse_hex(wc_commit.change_id().as_bytes())[..SHORT_CHANGE_ID_LENGTH]
+            )))
+        }
+    }
+
+    pub(super) fn import_git_refs(&self) -> Result<()> {
+        let git_settings = self.settings.git_settings()?;
+        self.transact("import git refs", |tx| {
+            git::import_refs(tx.repo_mut(), &git_settings)?;
+            Ok(())
+        })
+    }
+
+    pub(super) fn new_change_on(&self, revision: &str) -> Result<()> {
+        let parent = self.resolve_single(revision)?;
+        self.transact("new empty commit", |tx| {
+            tx.repo_mut()
+                .check_out(self.workspace_name.clone(), &parent)?;
+            Ok(())
+        })
+    }
+
+    pub(super) fn rebase_skip_emptied(&self, source: &str, destination: &str) -> Result<()> {
+        self.import_git_refs()?;
+        let roots = self.evaluate(source)?;
+        if roots.is_empty() {
+            return Ok(());
+        }
+        self.check_rewritable(&roots)?;
+        let destination = self.resolve_single(destination)?;
+
+        let roots = self.commits(&roots)?;
+        self.transact("rebase commits", |tx| {
+            move_commits(
+                tx.repo_mut(),
+                &[destination.id().clone()],
+                &[],
+                &MoveCommitsTarget::Roots(roots),
+                &RebaseOptions {
+                    empty: EmptyBehaviour::AbandonNewlyEmpty,
+                    ..Default::default()
+                },
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Import the Git refs, which brings in the new master commit, and rebase
+    /// the landed commit and its descendants onto it, abandoning commits that
+    /// become empty, all in one operation. Returns whether the landed commit
+    /// was abandoned.
+    pub(super) fn rebase_landed(&self, commit_oid: Oid, new_master_oid: Oid) -> Result<bool> {
+        let commit = self.commit(commit_oid)?;
+        self.check_rewritable(&[commit.id().clone()])?;
+        let git_settings = self.settings.git_settings()?;
+
+        let mut abandoned = false;
+        self.transact("rebase onto landed commit", |tx| {
+            git::import_refs(tx.repo_mut(), &git_settings)?;
+            move_commits(
+                tx.repo_mut(),
+                &[CommitId::from_bytes(new_master_oid.as_bytes())],
+                &[],
+                &MoveCommitsTarget::Roots(vec![commit.clone()]),
+                &RebaseOptions {
+                    empty: EmptyBehaviour::AbandonNewlyEmpty,
+                    ..Default::default()
+                },
+            )?;
+
+            // An abandoned commit is replaced by its parents, a rebased one by
+            // a commit of the same change.
+            let replacements = tx.repo().new_parents(&[commit.id().clone()]);
+            abandoned = !replacements.iter().any(|commit_id| {
+                tx.repo()
+                    .store()
+                    .get_commit(commit_id)
+                    .is_ok_and(|replacement| replacement.change_id() == commit.change_id())
+            });
+            Ok(())
+        })?;
+
+        Ok(abandoned)
+    }
+
+    pub(super) fn abandon(&self, revision: &str) -> Result<()> {
+        self.import_git_refs()?;
+        let commit_ids = self.evaluate(revision)?;
+        self.check_rewritable(&commit_ids)?;
+
+        let commits = self.commits(&commit_ids)?;
+        self.transact("abandon commits", |tx| {
+            for commit in &commits {
+                tx.repo_mut().record_abandoned_commit(commit);
+            }
+            // Like `jj abandon`, delete the bookmarks of abandoned commits.
+            tx.repo_mut().rebase_descendants_with_options(
+                &RebaseOptions {
+                    rewrite_refs: RewriteRefsOptions {
+                        delete_abandoned_bookmarks: true,
+                    },
+                    ..Default::default()
+                },
+                |_, _| {},
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Set the descriptions of the given revisions, all in a single
+    /// operation
+    pub(super) fn describe(&self, descriptions: &[(String, String)]) -> Result<()> {
+        let mut new_descriptions = HashMap::new();
+        for (revision, description) in descriptions {
+            let commit = self
+                .resolve_single(revision)
+                .context("Failed to update commit message".to_string())?;
+
+            let description = complete_newline(description);
+            if commit.description() != description {
+                new_descriptions.insert(commit.id().clone(), description);
+            }
+        }
+        if new_descriptions.is_empty() {
+            return Ok(());
+        }
+
+        let commit_ids: Vec<_> = new_descriptions.keys().cloned().collect();
+        self.check_rewritable(&commit_ids)?;
+
+        let description = format!("describe {} commits", commit_ids.len());
+        self.transact(&description, |tx| {
+            tx.repo_mut()
+                .transform_descendants(commit_ids, |rewriter| {
+                    let description = new_descriptions.remove(rewriter.old_commit().id());
+                    if description.is_none() && !rewriter.parents_changed() {
+                        return Ok(());
+                    }
+
+                    let mut commit_builder = rewriter.reparent();
+                    if let Some(description) = description {
+                        commit_builder = commit_builder.set_description(description);
+                    }
+                    commit_builder.write()?;
+                    Ok(())
+                })?;
+            Ok(())
+        })
+    }
+
+    fn commit(&self, commit_oid: Oid) -> Result<Commit> {
+        let repo = self.repo.borrow();
+        Ok(repo
+            .store()
+            .get_commit(&CommitId::from_bytes(commit_oid.as_bytes()))?)
+    }
+
+    fn commits(&self, commit_ids: &[CommitId]) -> Result<Vec<Commit>> {
+        let repo = self.repo.borrow();
+        commit_ids
+            .iter()
+            .map(|commit_id| Ok(repo.store().get_commit(commit_id)?))
+            .collect()
+    }
+
+    fn wc_commit(&self, repo: &ReadonlyRepo) -> Result<Option<Commit>> {
+        repo.view()
+            .get_wc_commit_id(&self.workspace_name)
+            .map(|commit_id| Ok(repo.store().get_commit(commit_id)?))
+            .transpose()
+    }
+
+    fn evaluate(&self, revset: &str) -> Result<Vec<CommitId>> {
+        let repo = self.repo.borrow().clone();
+        let context = RevsetParseContext {
+            aliases_map: &self.revset_aliases,
+            local_variables: HashMap::new(),
+            user_email: self.settings.user_email(),
+            date_pattern_context: chrono::Local::now().into(),
+            extensions: &self.revset_extensions,
+            workspace: Some(RevsetWorkspaceContext {
+                path_converter: &self.path_converter,
+                workspace_name: &self.workspace_name,
+            }),
+        };
+
+        let expression = revset::parse(&mut RevsetDiagnostics::new(), revset, &context)
+            .map_err(|e| Error::new(format!("Failed to parse revset '{}': {}", revset, e)))?;
+        self.evaluate_expression(&repo, &expression)
+            .context(format!("Failed to evaluate revset '{}'", revset))
+    }
+
+    fn evaluate_expression(
+        &self,
+        repo: &ReadonlyRepo,
+        expression: &Rc<UserRevsetExpression>,
+    ) -> Result<Vec<CommitId>> {
+        let symbol_resolver =
+            DefaultSymbolResolver::new(repo, self.revset_extensions.symbol_resolvers());
+        let revset = expression
+            .resolve_user_expression(repo, &symbol_resolver)?
+            .evaluate(repo)?;
+        Ok(revset.iter().collect::<std::result::Result<_, _>>()?)
+    }
+
+    fn resolve_single(&self, revision: &str) -> Result<Commit> {
+        self.import_git_refs()?;
+        match self.evaluate(revision)?.as_slice() {
+            [commit_id] => Ok(self.repo.borrow().store().get_commit(commit_id)?),
+            [] => Err(Error::new(format!(
+                "Revset '{}' did not resolve to any revision",
+                revision
+            ))),
+            _ => Err(Error::new(format!(
+                "Revset '{}' resolved to more than one revision",
+                revision
+            ))),
+        }
+    }
+
+    /// Fail if any of the commits is immutable, just like jj refuses to
+    /// rewrite them.
+    fn check_rewritable(&self, commit_ids: &[CommitId]) -> Result<()> {
+        let immutable = self.evaluate("immutable()")?;
+        match commit_ids.iter().find(|id| immutable.contains(id)) {
+            Some(commit_id) => Err(Error::new(format!(
+                "Commit {:.7} is immutable",
+                commit_id.hex()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Run `f` in a transaction and, if it changed anything, commit it as
+    /// a new operation and update the working copy and the Git repository.
+    fn transact<F>(&self, description: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut jj_lib::transaction::Transaction) -> Result<()>,
+    {
+        let old_repo = self.repo.borrow().clone();
+        let mut tx = old_repo.start_transaction();
+        tx.set_tag(
+            "args".to_string(),
+            std::env::args().collect::<Vec<_>>().join(" "),
+        );
+
+        f(&mut tx)?;
+        if !tx.repo().has_changes() {
+            return Ok(());
+        }
+        tx.repo_mut().rebase_descendants()?;
+
+        let old_wc_commit = self.wc_commit(&old_repo)?;
+        let new_wc_commit = tx
+            .repo()
+            .view()
+            .get_wc_commit_id(&self.workspace_name)
+            .map(|commit_id| tx.repo().store().get_commit(commit_id))
+            .transpose()?;
+
+        if self.colocated {
+            if let Some(wc_commit) = &new_wc_commit {
+                git::reset_head(tx.repo_mut(), wc_commit)?;
+            }
+            git::export_refs(tx.repo_mut())?;
+        }
+
+        let repo = tx.commit(format!("spr: {}", description))?;
+        if let Some(new_wc_commit) = &new_wc_commit {
+            self.update_working_copy(&repo, old_wc_commit.as_ref(), new_wc_commit)?;
+        }
+        *self.repo.borrow_mut() = repo;
+
+        Ok(())
+    }
+
+    fn update_working_copy(
+        &self,
+        repo: &ReadonlyRepo,
+        old_wc_commit: Option<&Commit>,
+        new_wc_commit: &Commit,
+    ) -> Result<()> {
+        let mut workspace = self.workspace.borrow_mut();
+        let old_tree_id = old_wc_commit.map(|commit| commit.tree_id().clone());
+        if Some(new_wc_commit.tree_id()) != old_tree_id.as_ref() {
+            workspace.check_out(
+                repo.op_id().clone(),
+                old_tree_id.as_ref(),
+                new_wc_commit,
+                &self.checkout_options()?,
+            )?;
+        } else {
+            // Only record that the working copy is up to date with the new
+            // operation.
+            let locked_workspace = workspace.start_working_copy_mutation()?;
+            locked_workspace.finish(repo.op_id().clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Make a working copy commit on top of Git's HEAD if something other
+    /// than jj moved it, e.g. `git checkout`.
+    fn import_git_head(&self) -> Result<()> {
+        let repo = self.repo.borrow().clone();
+        let mut tx = repo.start_transaction();
+        git::import_head(tx.repo_mut())?;
+        if !tx.repo().has_changes() {
+            return Ok(());
+        }
+
+        let mut workspace = self.workspace.borrow_mut();
+        let mut locked_workspace = workspace.start_working_copy_mutation()?;
+        if let Some(git_head_id) = tx.repo().view().git_head().as_normal().cloned() {
+            let git_head = tx.repo().store().get_commit(&git_head_id)?;
+            let wc_commit = tx
+                .repo_mut()
+                .check_out(self.workspace_name.clone(), &git_head)?;
+            // Git has already updated the files when it moved HEAD.
+            locked_workspace.locked_wc().reset(&wc_commit)?;
+            tx.repo_mut().rebase_descendants()?;
+        }
+
+        let repo = tx.commit("spr: import git head")?;
+        locked_workspace.finish(repo.op_id().clone())?;
+        *self.repo.borrow_mut() = repo;
+
+        Ok(())
+    }
+
+    /// Record the files in the working copy in the working copy commit, like
+    /// jj does at the start of every command.
+    fn snapshot_working_copy(&self, git_repo: &git2::Repository) -> Result<()> {
+        let repo = self.repo.borrow().clone();
+        let Some(wc_commit) = self.wc_commit(&repo)? else {
+            return Ok(());
+        };
+
+        let base_ignores = base_ignores(git_repo)?;
+        let auto_track = self.settings.get_string("snapshot.auto-track")?;
+        let start_tracking_matcher = fileset::parse(
+            &mut FilesetDiagnostics::new(),
+            &auto_track,
+            &self.path_converter,
+        )
+        .map_err(|e| Error::new(format!("snapshot.auto-track is invalid: {}", e)))?
+        .to_matcher();
+        let max_new_file_size = self
+            .settings
+            .get_value("snapshot.max-new-file-size")
+            .ok()
+            .and_then(|value| match value.as_integer() {
+                Some(size) => u64::try_from(size).ok(),
+                None => parse_file_size(value.as_str()?),
+            })
+            .ok_or_else(|| Error::new("snapshot.max-new-file-size is invalid"))?;
+        let options = SnapshotOptions {
+            base_ignores,
+            fsmonitor_settings: self.settings.fsmonitor_settings()?,
+            progress: None,
+            start_tracking_matcher: start_tracking_matcher.as_ref(),
+            max_new_file_size,
+            conflict_marker_style: self.checkout_options()?.conflict_marker_style,
+        };
+
+        let mut workspace = self.workspace.borrow_mut();
+        let mut locked_workspace = workspace.start_working_copy_mutation()?;
+        match WorkingCopyFreshness::check_stale(locked_workspace.locked_wc(), &wc_commit, &repo)? {
+            WorkingCopyFreshness::Fresh => (),
+            _ => {
+                return Err(Error::new(
+                    "The working copy is stale. Run 'jj workspace update-stale' and try again.",
+                ));
+            }
+        }
+
+        let (tree_id, _stats) = locked_workspace.locked_wc().snapshot(&options)?;
+        let repo = if tree_id != *wc_commit.tree_id() {
+            let mut tx = repo.start_transaction();
+            tx.set_is_snapshot(true);
+            let commit = tx
+                .repo_mut()
+                .rewrite_commit(&wc_commit)
+                .set_tree_id(tree_id)
+                .write()?;
+            tx.repo_mut()
+                .set_wc_commit(self.workspace_name.clone(), commit.id().clone())?;
+            tx.repo_mut().rebase_descendants()?;
+            if self.colocated {
+                git::export_refs(tx.repo_mut())?;
+            }
+            tx.commit("snapshot working copy")?
+        } else {
+            repo
+        };
+
+        locked_workspace.finish(repo.op_id().clone())?;
+        *self.repo.borrow_mut() = repo;
+
+        Ok(())
+    }
+
+    fn checkout_options(&self) -> Result<CheckoutOptions> {
+        Ok(CheckoutOptions {
+            conflict_marker_style: self.settings.get("ui.conflict-marker-style")?,
+        })
+    }
+}
+
+/// Load the configuration the jj command line tool would use in the
+/// workspace at `workspace_root`, whose repository is at `repo_path`
+fn load_config(workspace_root: &Path, repo_path: &Path) -> Result<StackedConfig> {
+    let mut config = StackedConfig::with_defaults();
+    config.add_layer(ConfigLayer::parse(
+        ConfigSource::Default,
+        include_str!("config.toml"),
+    )?);
+
+    let mut env_base = ConfigLayer::empty(ConfigSource::EnvBase);
+    if let Ok(username) = std::env::var("USER").or_else(|_| std::env::var("USERNAME")) {
+        env_base.set_value("operation.username", username)?;
+    }
+    if let Ok(hostname) = std::env::var("HOSTNAME") {
+        env_base.set_value("operation.hostname", hostname)?;
+    }
+    config.add_layer(env_base);
+
+    for path in user_config_paths() {
+        if path.is_dir() {
+            config.load_dir(ConfigSource::User, &path)?;
+        } else if path.is_file() {
+            config.load_file(ConfigSource::User, path)?;
+        }
+    }
+
+    let repo_config = repo_path.join("config.toml");
+    if repo_config.is_file() {
+        config.load_file(ConfigSource::Repo, repo_config)?;
+    }
+
+    let mut env_overrides = ConfigLayer::empty(ConfigSource::EnvOverrides);
+    if let Ok(name) = std::env::var("JJ_USER") {
+        env_overrides.set_value("user.name", name)?;
+    }
+    if let Ok(email) = std::env::var("JJ_EMAIL") {
+        env_overrides.set_value("user.email", email)?;
+    }
+    config.add_layer(env_overrides);
+
+    let home_dir = std::env::home_dir();
+    Ok(resolve(
+        &config,
+        &ConfigResolutionContext {
+            home_dir: home_dir.as_deref(),
+            repo_path: Some(workspace_root),
+            command: None,
+        },
+    )?)
+}
+
+/// The user configuration files and directories jj reads, in order:
+/// `$JJ_CONFIG` if set, otherwise `~/.jjconfig.toml` and `jj/config.toml`
+/// and `jj/conf.d` in the configuration directory
+fn user_config_paths() -> Vec<PathBuf> {
+    if let Some(paths) = std::env::var_os("JJ_CONFIG") {
+        return std::env::split_paths(&paths)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect();
+    }
+
+    let home_dir = std::env::home_dir();
+    let mut config_dirs: Vec<PathBuf> = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| Some(home_dir.as_ref()?.join(".config")))
+        .into_iter()
+        .collect();
+    if cfg!(target_os = "macos")
+        && let Some(home_dir) = &home_dir
+    {
+        config_dirs.push(home_dir.join("Library").join("Application Support"));
+    }
+
+    let mut paths: Vec<_> = home_dir
+        .iter()
+        .map(|home_dir| home_dir.join(".jjconfig.toml"))
+        .collect();
+    for config_dir in config_dirs {
+        paths.push(config_dir.join("jj").join("config.toml"));
+        paths.push(config_dir.join("jj").join("conf.d"));
+    }
+    paths
+}
+
+fn load_revset_aliases(settings: &UserSettings) -> Result<RevsetAliasesMap> {
+    let mut aliases = RevsetAliasesMap::new();
+    for declaration in settings.table_keys("revset-aliases") {
+        let definition = settings.get_string(["revset-aliases", declaration])?;
+        aliases
+            .insert(declaration, definition)
+            .map_err(|e| Error::new(format!("Invalid revset alias '{}': {}", declaration, e)))?;
+    }
+    Ok(aliases)
+}
+
+/// The ignore patterns that apply to the whole working copy besides its
+/// `.gitignore` files, as Git would apply them
+fn base_ignores(git_repo: &git2::Repository) -> Result<Arc<GitIgnoreFile>> {
+    let excludes_file = git_repo
+        .config()?
+        .get_path("core.excludesFile")
+        .ok()
+        .or_else(|| {
+            let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| Some(std::env::home_dir()?.join(".config")))?;
+            Some(config_dir.join("git").join("ignore"))
+        });
+
+    let mut ignores = GitIgnoreFile::empty();
+    if let Some(excludes_file) = excludes_file {
+        ignores = ignores.chain_with_file("", excludes_file)?;
+    }
+    ignores = ignores.chain_with_file("", git_repo.path().join("info").join("exclude"))?;
+    Ok(ignores)
+}
+
+/// Parse a file size like jj's `snapshot.max-new-file-size`: a number of
+/// bytes, optionally followed by a unit like `KB` or `MiB`
+fn parse_file_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let factor: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "k" | "kB" | "KB" => 1_000,
+        "KiB" => 1 << 10,
+        "M" | "MB" => 1_000_000,
+        "MiB" => 1 << 20,
+        "G" | "GB" => 1_000_000_000,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * factor as f64) as u64)
+}
+
+/// Descriptions written by jj end with a newline.
+fn complete_newline(text: &str) -> String {
+    let mut text = text.to_string();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+fn oid(commit_id: &CommitId) -> Result<Oid> {
+    Ok(Oid::from_bytes(commit_id.as_bytes())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn settings() -> UserSettings {
+        let mut config = StackedConfig::with_defaults();
+        config.add_layer(
+            ConfigLayer::parse(
+                ConfigSource::User,
+                "user.name = 'Test User'\nuser.email = 'test@example.com'",
+            )
+            .unwrap(),
+        );
+        UserSettings::from_config(config).unwrap()
+    }
+
+    /// Create a colocated workspace whose repository configures the user and
+    /// `spr.branchPrefix`
+    fn init_workspace() -> (TempDir, PathBuf) {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path().canonicalize().unwrap();
+        Workspace::init_colocated_git(&settings(), &path).unwrap();
+
+        std::fs::write(
+            path.join(".jj/repo/config.toml"),
+            "[user]\nname = 'Test User'\nemail = 'test@example.com'\n\n\
+             [spr]\nbranchPrefix = 'test/'\nrequireApproval = true\n",
+        )
+        .unwrap();
+
+        (temp_dir, path)
+    }
+
+    fn load(path: &Path) -> Backend {
+        Backend::load(path).unwrap()
+    }
+
+    fn operation_count(backend: &Backend) -> usize {
+        let repo = backend.repo.borrow();
+        let mut count = 0;
+        let mut operation = Some(repo.operation().clone());
+        while let Some(op) = operation {
+            count += 1;
+            operation = op.parents().next().map(|parent| parent.unwrap());
+        }
+        count
+    }
+
+    #[test]
+    fn test_config() {
+        let (_temp_dir, path) = init_workspace();
+        let backend = load(&path);
+
+        assert_eq!(
+            backend.config_value("spr.branchPrefix"),
+            Some("test/".to_string())
+        );
+        assert_eq!(backend.config_bool("spr.requireApproval"), Some(true));
+        assert_eq!(backend.config_value("spr.missing"), None);
+
+        let entries = backend.config_entries("spr").unwrap();
+        assert!(entries.contains(&(
+            "branchPrefix".to_string(),
+            "test/".to_string(),
+            ValueSource::JjRepo
+        )));
+        assert!(entries.contains(&(
+            "requireApproval".to_string(),
+            "true".to_string(),
+            ValueSource::JjRepo
+        )));
+    }
+
+    #[test]
+    fn test_load_non_colocated_from_subdirectory() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path().canonicalize().unwrap();
+        Workspace::init_internal_git(&settings(), &path).unwrap();
+        std::fs::create_dir(path.join("subdir")).unwrap();
+
+        let backend = load(&path.join("subdir"));
+        assert!(!backend.colocated);
+        assert_eq!(
+            backend.git_repo_path().unwrap().canonicalize().unwrap(),
+            path.join(".jj/repo/store/git")
+        );
+
+        assert!(Backend::load(temp_dir.path().parent().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_describe_and_rebase() {
+        let (_temp_dir, path) = init_workspace();
+
+        // Every load snapshots the working copy, just like a jj command.
+        std::fs::write(path.join("a.txt"), "a\n").unwrap();
+        let backend = load(&path);
+        assert!(backend.check_no_uncommitted_changes().is_err());
+        backend.describe(&[("@".into(), "First".into())]).unwrap();
+        backend.new_change_on("@").unwrap();
+
+        std::fs::write(path.join("b.txt"), "b\n").unwrap();
+        let backend = load(&path);
+        backend.describe(&[("@".into(), "Second".into())]).unwrap();
+        backend.new_change_on("@").unwrap();
+        backend.check_no_uncommitted_changes().unwrap();
+
+        let stack = backend.log("::@- & ~root()").unwrap();
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack[0].parent_oids, [stack[1].commit_oid]);
+        assert_eq!(stack[0].description, "Second\n");
+        assert!(!stack[0].empty && !stack[0].conflict && !stack[0].divergent);
+        assert!(backend.log("@").unwrap()[0].empty);
+
+        // Rewriting the messages of the whole stack is a single operation,
+        // which keeps the changes and rebases the descendants.
+        let operations = operation_count(&backend);
+        backend
+            .describe(&[
+                (stack[0].change_id.clone(), "Second, reworded".into()),
+                (stack[1].change_id.clone(), "First, reworded".into()),
+            ])
+            .unwrap();
+        assert_eq!(operation_count(&backend), operations + 1);
+
+        let git_repo = git2::Repository::open(&path).unwrap();
+        let reworded = backend.log("::@- & ~root()").unwrap();
+        let messages = reworded
+            .iter()
+            .map(|entry| {
+                let commit = git_repo.find_commit(entry.commit_oid).unwrap();
+                commit.message().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["Second, reworded\n", "First, reworded\n"]);
+        for (entry, old_entry) in reworded.iter().zip(&stack) {
+            assert_eq!(entry.change_id, old_entry.change_id);
+        }
+
+        // The root commit can not be rewritten.
+        assert!(backend.abandon("root()").is_err());
+        backend.abandon(&stack[0].change_id).unwrap();
+        assert_eq!(backend.log("::@- & ~root()").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rebase_landed() {
+        let (_temp_dir, path) = init_workspace();
+        std::fs::write(path.join("a.txt"), "a\n").unwrap();
+        let backend = load(&path);
+        backend.describe(&[("@".into(), "First".into())]).unwrap();
+        backend.new_change_on("@").unwrap();
+        std::fs::write(path.join("b.txt"), "b\n").unwrap();
+        let backend = load(&path);
+        backend.describe(&[("@".into(), "Second".into())]).unwrap();
+        backend.new_change_on("@").unwrap();
+        let stack = backend.log("::@- & ~root()").unwrap();
+        let (second, first) = (&stack[0], &stack[1]);
+
+        // GitHub squashed the first change into master, which is fetched but
+        // not imported yet.
+        let git_repo = git2::Repository::open(&path).unwrap();
+        let signature = git2::Signature::now("Test User", "test@example.com").unwrap();
+        let landed_tree = git_repo
+            .find_commit(first.commit_oid)
+            .unwrap()
+            .tree()
+            .unwrap();
+        let master_oid = git_repo
+            .commit(
+                Some("refs/remotes/origin/main"),
+                &signature,
+                &signature,
+                "First (#1)",
+                &landed_tree,
+                &[],
+            )
+            .unwrap();
+
+        // Importing the new master and rebasing is a single operation, which
+        // abandons the landed change.
+        let operations = operation_count(&backend);
+        assert!(backend.rebase_landed(first.commit_oid, master_oid).unwrap());
+        assert_eq!(operation_count(&backend), operations + 1);
+        assert!(
+            backend
+                .log(&format!("present({})", first.change_id))
+                .unwrap()
+                .is_empty()
+        );
+        let rebased = &backend.log(&second.change_id).unwrap()[0];
+        assert_eq!(rebased.parent_oids, [master_oid]);
+
+        // A change whose contents are not on master is kept.
+        let mut builder = git_repo.treebuilder(Some(&landed_tree)).unwrap();
+        let blob = git_repo.blob(b"c\n").unwrap();
+        builder.insert("c.txt", blob, 0o100644).unwrap();
+        let tree = git_repo.find_tree(builder.write().unwrap()).unwrap();
+        let master = git_repo.find_commit(master_oid).unwrap();
+        let new_master_oid = git_repo
+            .commit(
+                Some("refs/remotes/origin/main"),
+                &signature,
+                &signature,
+                "Other (#2)",
+                &tree,
+                &[&master],
+            )
+            .unwrap();
+        assert!(
+            !backend
+                .rebase_landed(rebased.commit_oid, new_master_oid)
+                .unwrap()
+        );
+        let kept = &backend.log(&second.change_id).unwrap()[0];
+        assert_eq!(kept.parent_oids, [new_master_oid]);
+    }
+
+    #[test]
+    fn test_parse_file_size() {
+        assert_eq!(parse_file_size("1024"), Some(1024));
+        assert_eq!(parse_file_size("1MiB"), Some(1 << 20));
+        assert_eq!(parse_file_size("1.5 KB"), Some(1500));
+        assert_eq!(parse_file_size("10 parsecs"), None);
+    }
+
+    #[test]
+    fn test_complete_newline() {
+        assert_eq!(complete_newline(""), "");
+        assert_eq!(complete_newline("Title"), "Title\n");
+        assert_eq!(complete_newline("Title\n"), "Title\n");
+    }
+}
@@ -5,11 +5,15 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+pub mod api;
 pub mod commands;
 pub mod config;
 pub mod error;
+pub mod forge;
 pub mod git;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
 pub mod jj;
 pub mod message;
 pub mod output;
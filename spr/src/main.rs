@@ -11,9 +11,11 @@
 
 use clap::{Parser, Subcommand};
 use jj_spr::{
+    api::ApiClient,
     commands,
-    config::{get_auth_token, get_config_bool, get_config_value},
+    config::{Settings, ValueSource, get_auth_token_with_source},
     error::{Error, Result, ResultExt},
+    forge::{Forge, ForgeKind},
     output::output,
 };
 use reqwest::{self, header};
@@ -26,7 +28,8 @@ use reqwest::{self, header};
 )]
 pub struct Cli {
     /// GitHub personal access token (if not given taken from jj config
-    /// spr.githubAuthToken)
+    /// spr.githubAuthToken, a GitHub App, the GH_TOKEN or GITHUB_TOKEN
+    /// environment variables, the GitHub CLI or the Git credential helper)
     #[clap(long)]
     github_auth_token: Option<String>,
 
@@ -41,6 +44,11 @@ pub struct Cli {
     #[clap(long)]
     branch_prefix: Option<String>,
 
+    /// Print details about the requests sent to GitHub, such as the remaining
+    /// API rate limit
+    #[clap(long, short = 'v', global = true)]
+    verbose: bool,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -67,17 +75,22 @@ enum Commands {
     /// List open Pull Requests on GitHub and their review decision
     List,
 
-    /// Create a new branch with the contents of an existing Pull Request
+    /// Create a new change with the contents of an existing Pull Request
     Patch(commands::patch::PatchOptions),
 
     /// Close a Pull request
     Close(commands::close::CloseOptions),
-}
 
-#[derive(Debug, thiserror::Error)]
-pub enum OptionsError {
-    #[error("GitHub repository must be given as 'OWNER/REPO', but given value was '{0}'")]
-    InvalidRepository(String),
+    /// Show the Pull Requests of a stack of changes with their review, CI and
+    /// mergeability status
+    Status(commands::status::StatusOptions),
+
+    /// Abandon changes whose Pull Requests were merged, forget closed Pull
+    /// Requests, and rebase the remaining changes onto trunk
+    Sync(commands::sync::SyncOptions),
+
+    /// Show the settings in effect and where they come from, and check them
+    Config,
 }
 
 pub async fn spr() -> Result<()> {
@@ -87,90 +100,46 @@ pub async fn spr() -> Result<()> {
         return commands::init::init().await;
     }
 
-    // Discover the Jujutsu repository and get the colocated Git repo
+    // Discover the Jujutsu workspace and the Git repo backing it, which is
+    // only in the workspace if the repository is colocated
     let current_dir = std::env::current_dir()?;
-    let repo = git2::Repository::discover(&current_dir)?;
-
-    // Verify this is a Jujutsu repository by checking for .jj directory
-    let repo_path = repo
-        .workdir()
-        .ok_or_else(|| Error::new("Repository must have a working directory".to_string()))?
-        .to_path_buf();
-
-    let jj_dir = repo_path.join(".jj");
-    if !jj_dir.exists() {
-        return Err(Error::new(
-            "This command requires a Jujutsu repository. Run 'jj git init --colocate' to create one.".to_string()
-        ));
-    }
-
-    let git_config = repo.config()?;
-
-    // Try to get config from jj first, fall back to git config
-    let github_repository = match cli.github_repository {
-        Some(v) => Ok(v),
-        None => {
-            // Try jj config first
-            if let Ok(output) = std::process::Command::new("jj")
-                .args(["config", "get", "spr.githubRepository"])
-                .output()
-            {
-                if output.status.success() {
-                    Ok(String::from_utf8(output.stdout)?.trim().to_string())
-                } else {
-                    git_config.get_string("spr.githubRepository")
-                }
-            } else {
-                git_config.get_string("spr.githubRepository")
-            }
+    let jj = jj_spr::jj::Jujutsu::discover(&current_dir)
+        .context("could not initialize Jujutsu backend".to_owned())?;
+    let git_config = jj.git_repo.config()?;
+
+    // Read all settings at once, so all problems with them are reported
+    // together.
+    let mut settings = Settings::load(&jj, &git_config)?;
+    for (key, value) in [
+        ("githubAuthToken", cli.github_auth_token),
+        ("githubRepository", cli.github_repository),
+        ("branchPrefix", cli.branch_prefix),
+    ] {
+        if let Some(value) = value {
+            settings.set(key, value, ValueSource::CliFlag);
         }
-    }?;
-
-    let (github_owner, github_repo) = {
-        let captures = lazy_regex::regex!(r#"^([\w\-\.]+)/([\w\-\.]+)$"#)
-            .captures(&github_repository)
-            .ok_or_else(|| OptionsError::InvalidRepository(github_repository.clone()))?;
-        (
-            captures.get(1).unwrap().as_str().to_string(),
-            captures.get(2).unwrap().as_str().to_string(),
-        )
-    };
+    }
 
-    let github_remote_name = get_config_value("spr.githubRemoteName", &git_config)
-        .unwrap_or_else(|| "origin".to_string());
-    let github_master_branch = get_config_value("spr.githubMasterBranch", &git_config)
-        .unwrap_or_else(|| "main".to_string());
-    let branch_prefix = get_config_value("spr.branchPrefix", &git_config)
-        .ok_or_else(|| Error::new("spr.branchPrefix must be configured".to_string()))?;
-    let require_approval = get_config_bool("spr.requireApproval", &git_config).unwrap_or(false);
-
-    let config = jj_spr::config::Config::new(
-        github_owner,
-        github_repo,
-        github_remote_name,
-        github_master_branch,
-        branch_prefix,
-        require_approval,
-    );
+    if let Commands::Config = cli.command {
+        return commands::config::config(&settings, &jj.git_repo);
+    }
 
-    let jj = jj_spr::jj::Jujutsu::new(repo)
-        .context("could not initialize Jujutsu backend".to_owned())?;
+    let mut config = settings.to_config(&jj.git_repo)?;
+    config.git_dir = Some(jj.git_repo.path().to_path_buf());
 
     if let Commands::Format(opts) = cli.command {
         return commands::format::format(opts, &jj, &config).await;
     }
 
-    let github_auth_token = match cli.github_auth_token {
-        Some(v) => v,
-        None => get_auth_token(&git_config)
-            .ok_or_else(|| Error::new("GitHub auth token must be configured".to_string()))?,
-    };
-
-    octocrab::initialise(
-        octocrab::OctocrabBuilder::default()
-            .personal_token(github_auth_token.clone())
-            .build()?,
-    );
+    let github_auth_token = get_auth_token_with_source(
+        &settings,
+        config.git_dir.as_deref(),
+        &config.host,
+        &config.api_url(),
+        Some((&config.owner, &config.repo)),
+    )
+    .await?
+    .ok_or_else(|| Error::new(format!("Auth token for {} must be configured", config.host)))?;
 
     let mut headers = header::HeaderMap::new();
     headers.insert(header::ACCEPT, "application/json".parse()?);
@@ -180,25 +149,48 @@ pub async fn spr() -> Result<()> {
     );
     headers.insert(
         header::AUTHORIZATION,
-        format!("Bearer {}", github_auth_token).parse()?,
+        format!("Bearer {}", github_auth_token.token()).parse()?,
     );
 
     let graphql_client = reqwest::Client::builder()
         .default_headers(headers)
         .build()?;
 
-    let mut gh = jj_spr::github::GitHub::new(config.clone(), graphql_client.clone());
+    // GitLab and Gitea accept personal access tokens as bearer tokens, too,
+    // so the same client serves all forges.
+    let mut backend: Box<dyn Forge> = match config.forge {
+        ForgeKind::GitHub => Box::new(jj_spr::github::GitHub::new(
+            config.clone(),
+            ApiClient::new(graphql_client.clone(), cli.verbose),
+        )),
+        ForgeKind::GitLab => Box::new(jj_spr::gitlab::GitLab::new(
+            config.clone(),
+            graphql_client.clone(),
+        )),
+        ForgeKind::Gitea => Box::new(jj_spr::gitea::Gitea::new(
+            config.clone(),
+            graphql_client.clone(),
+        )),
+    };
+    let gh = backend.as_mut();
 
     match cli.command {
-        Commands::Diff(opts) => commands::diff::diff(opts, &jj, &mut gh, &config).await?,
-        Commands::Land(opts) => commands::land::land(opts, &jj, &mut gh, &config).await?,
-        Commands::Amend(opts) => commands::amend::amend(opts, &jj, &mut gh, &config).await?,
-        Commands::List => commands::list::list(graphql_client, &config).await?,
-        Commands::Patch(opts) => commands::patch::patch(opts, &jj, &mut gh, &config).await?,
-        Commands::Close(opts) => commands::close::close(opts, &jj, &mut gh, &config).await?,
+        Commands::Diff(opts) => commands::diff::diff(opts, &jj, gh, &config).await?,
+        Commands::Land(opts) => commands::land::land(opts, &jj, gh, &config).await?,
+        Commands::Amend(opts) => commands::amend::amend(opts, &jj, gh, &config).await?,
+        Commands::List => {
+            if config.forge != ForgeKind::GitHub {
+                return Err(Error::new("The list command only supports GitHub"));
+            }
+            commands::list::list(ApiClient::new(graphql_client, cli.verbose), &config).await?
+        }
+        Commands::Patch(opts) => commands::patch::patch(opts, &jj, gh, &config).await?,
+        Commands::Close(opts) => commands::close::close(opts, &jj, gh, &config).await?,
+        Commands::Status(opts) => commands::status::status(opts, &jj, gh, &config).await?,
+        Commands::Sync(opts) => commands::sync::sync(opts, &jj, gh, &config).await?,
         // The following commands are executed above and return from this
         // function before it reaches this match.
-        Commands::Init | Commands::Format(_) => (),
+        Commands::Init | Commands::Config | Commands::Format(_) => (),
     };
 
     Ok::<_, Error>(())
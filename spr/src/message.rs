@@ -6,6 +6,7 @@
  */
 
 use crate::{
+    config::Config,
     error::{Error, Result},
     output::output,
 };
@@ -173,7 +174,52 @@ pub fn build_github_body_for_merging(section_texts: &MessageSectionsMap) -> Stri
     )
 }
 
-pub fn validate_commit_message(message: &MessageSectionsMap) -> Result<()> {
+/// Markers around the stack navigation block in Pull Request descriptions.
+/// The block is maintained by `spr diff` and is not part of the commit
+/// message.
+const STACK_BLOCK_START: &str = "<!-- spr-stack-start -->";
+const STACK_BLOCK_END: &str = "<!-- spr-stack-end -->";
+
+/// Split a Pull Request description into the text written by the user and the
+/// stack navigation block, if there is one.
+pub fn split_stack_block(body: &str) -> (String, Option<&str>) {
+    if let Some(start) = body.find(STACK_BLOCK_START)
+        && let Some(end) = body[start..].find(STACK_BLOCK_END)
+    {
+        let end = start + end + STACK_BLOCK_END.len();
+        let text = format!("{}{}", &body[..start], &body[end..]);
+        return (text.trim().to_string(), Some(&body[start..end]));
+    }
+
+    (body.to_string(), None)
+}
+
+/// Append the stack navigation block (if given) to a Pull Request description.
+pub fn with_stack_block(body: &str, stack_block: Option<&str>) -> String {
+    match stack_block {
+        Some(block) if body.trim().is_empty() => format!("{}\n", block),
+        Some(block) => format!("{}\n\n{}\n", body.trim_end(), block),
+        None => body.to_string(),
+    }
+}
+
+/// Build the stack navigation block for the Pull Request `current`, listing
+/// all Pull Requests of the stack. `stack` is ordered bottom to top, and the
+/// block shows the top of the stack first.
+pub fn build_stack_block(stack: &[u64], current: u64) -> String {
+    let mut block = format!("{}\n---\n\n**Stack**\n\n", STACK_BLOCK_START);
+    for number in stack.iter().rev() {
+        if *number == current {
+            block.push_str(&format!("- **#{}** 👈 this Pull Request\n", number));
+        } else {
+            block.push_str(&format!("- #{}\n", number));
+        }
+    }
+    block.push_str(STACK_BLOCK_END);
+    block
+}
+
+pub fn validate_commit_message(message: &MessageSectionsMap, config: &Config) -> Result<()> {
     let title_missing_or_empty = match message.get(&MessageSection::Title) {
         None => true,
         Some(title) => title.is_empty(),
@@ -183,6 +229,29 @@ pub fn validate_commit_message(message: &MessageSectionsMap) -> Result<()> {
         return Err(Error::empty());
     }
 
+    let missing_sections: Vec<_> = config
+        .required_sections
+        .iter()
+        .filter(|section| {
+            message
+                .get(section)
+                .is_none_or(|text| text.trim().is_empty())
+        })
+        .collect();
+    for section in &missing_sections {
+        output(
+            "💔",
+            &format!(
+                "Commit message does not have a '{}' section, which this \
+                 repository requires!",
+                message_section_label(section)
+            ),
+        )?;
+    }
+    if !missing_sections.is_empty() {
+        return Err(Error::empty());
+    }
+
     Ok(())
 }
 
@@ -273,4 +342,28 @@ Reviewer:    a, b, c"#,
             .into()
         );
     }
+
+    #[test]
+    fn test_stack_block() {
+        let block = build_stack_block(&[12, 13, 14], 13);
+        assert_eq!(
+            block,
+            "<!-- spr-stack-start -->\n---\n\n**Stack**\n\n\
+             - #14\n- **#13** 👈 this Pull Request\n- #12\n\
+             <!-- spr-stack-end -->"
+        );
+
+        let body = with_stack_block("Some summary\n", Some(&block));
+        assert_eq!(body, format!("Some summary\n\n{}\n", block));
+        assert_eq!(
+            split_stack_block(&body),
+            ("Some summary".to_string(), Some(&block[..]))
+        );
+
+        assert_eq!(with_stack_block("", Some(&block)), format!("{}\n", block));
+        assert_eq!(
+            split_stack_block("No stack"),
+            ("No stack".to_string(), None)
+        );
+    }
 }
@@ -0,0 +1,62 @@
+/*
+ * A server with canned responses for tests of the forge API clients
+ */
+
+//! Answers each request with the response configured for the end of its
+//! path, and with 404 Not Found for any other path. This is enough to test
+//! how the forges handle the status codes of their APIs.
+
+use std::convert::Infallible;
+
+use http_body_util::Full;
+use hyper::{
+    Request, Response, StatusCode,
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+
+/// A response: the end of the path it answers, its status code and body
+pub type CannedResponse = (&'static str, u16, &'static str);
+
+/// Start the server and return its base URL
+pub async fn start(responses: Vec<CannedResponse>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind canned response server");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let responses = responses.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| respond(responses.clone(), request));
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    url
+}
+
+async fn respond(
+    responses: Vec<CannedResponse>,
+    request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let (status, body) = responses
+        .iter()
+        .find(|(path, _, _)| request.uri().path().ends_with(path))
+        .map_or(
+            (404, r#"{"message":"404 Not Found"}"#),
+            |(_, status, body)| (*status, *body),
+        );
+
+    Ok(Response::builder()
+        .status(StatusCode::from_u16(status).unwrap())
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap())
+}
@@ -0,0 +1,631 @@
+/*
+ * A fake GitHub server for end-to-end tests
+ */
+
+//! An in-process fake of the parts of the GitHub API that spr uses: the
+//! GraphQL queries in `src/gql` and the REST endpoints called via octocrab.
+//! Branches live in a bare git repository, which the repository under test
+//! uses as its remote, so merging a Pull Request really updates the base
+//! branch.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FakeState {
+    Open,
+    Closed,
+    Merged,
+}
+
+#[derive(Debug, Clone)]
+pub struct FakePullRequest {
+    pub number: u64,
+    pub title: String,
+    pub body: String,
+    pub base: String,
+    pub head: String,
+    pub state: FakeState,
+    pub draft: bool,
+    /// Last known head commit, kept for when the head branch is deleted
+    pub head_oid: String,
+    pub merge_commit: Option<String>,
+    pub requested_reviewers: Vec<String>,
+    pub approved_by: Vec<String>,
+}
+
+struct State {
+    remote: PathBuf,
+    pull_requests: BTreeMap<u64, FakePullRequest>,
+    graphql_requests: usize,
+    /// Responses to send instead of handling the next requests
+    failures: VecDeque<(StatusCode, Vec<(String, String)>)>,
+    /// The tokens GitHub App requests were authorized with
+    app_tokens: Vec<String>,
+}
+
+/// A running fake GitHub. The server stops with the test's runtime.
+pub struct FakeGitHub {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeGitHub {
+    /// Start serving on a free local port, with branches stored in the bare
+    /// repository at `remote`.
+    pub async fn start(remote: &Path) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind fake GitHub server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State {
+            remote: remote.to_path_buf(),
+            pull_requests: BTreeMap::new(),
+            graphql_requests: 0,
+            failures: VecDeque::new(),
+            app_tokens: Vec::new(),
+        }));
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| handle(state.clone(), request));
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        Self { url, state }
+    }
+
+    /// The tokens the GitHub App requests so far were authorized with
+    pub fn app_tokens(&self) -> Vec<String> {
+        self.state.lock().unwrap().app_tokens.clone()
+    }
+
+    /// Base URL to use as `spr.apiUrl`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn pull_request(&self, number: u64) -> Option<FakePullRequest> {
+        let mut state = self.state.lock().unwrap();
+        refresh_head(&mut state, number);
+        state.pull_requests.get(&number).cloned()
+    }
+
+    pub fn pull_requests(&self) -> Vec<FakePullRequest> {
+        let numbers: Vec<u64> = self
+            .state
+            .lock()
+            .unwrap()
+            .pull_requests
+            .keys()
+            .copied()
+            .collect();
+        numbers
+            .into_iter()
+            .filter_map(|number| self.pull_request(number))
+            .collect()
+    }
+
+    /// Number of GraphQL requests served so far
+    pub fn graphql_requests(&self) -> usize {
+        self.state.lock().unwrap().graphql_requests
+    }
+
+    /// Fail the next request that is not failed already with `status` and
+    /// `headers`, without handling it.
+    pub fn fail_next(&self, status: u16, headers: &[(&str, &str)]) {
+        self.state.lock().unwrap().failures.push_back((
+            StatusCode::from_u16(status).unwrap(),
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        ));
+    }
+
+    pub fn approve(&self, number: u64, login: &str) {
+        let mut state = self.state.lock().unwrap();
+        let pull_request = state.pull_requests.get_mut(&number).unwrap();
+        pull_request.approved_by.push(login.to_string());
+    }
+}
+
+type HttpResponse = Response<Full<Bytes>>;
+
+fn respond(status: StatusCode, body: Value) -> HttpResponse {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+fn not_found() -> HttpResponse {
+    respond(StatusCode::NOT_FOUND, json!({ "message": "Not Found" }))
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    request: Request<Incoming>,
+) -> Result<HttpResponse, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let token = request
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(String::from);
+    let body = request
+        .into_body()
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .unwrap_or_default();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let mut state = state.lock().unwrap();
+    if let Some((status, headers)) = state.failures.pop_front() {
+        let mut response = respond(status, json!({ "message": "Injected failure" }));
+        for (name, value) in headers {
+            response.headers_mut().insert(
+                hyper::header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        return Ok(response);
+    }
+
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    Ok(match (&method, &segments[..]) {
+        (&Method::POST, ["graphql"]) => respond(StatusCode::OK, graphql(&mut state, &body)),
+        (&Method::POST, ["repos", _, _, "pulls"]) => create_pull_request(&mut state, &body),
+        (&Method::PATCH, ["repos", _, _, "pulls", number]) => {
+            update_pull_request(&mut state, number.parse().unwrap_or(0), &body)
+        }
+        (&Method::POST, ["repos", _, _, "pulls", number, "requested_reviewers"]) => {
+            match state.pull_requests.get_mut(&number.parse().unwrap_or(0)) {
+                Some(pull_request) => {
+                    for reviewer in body["reviewers"].as_array().into_iter().flatten() {
+                        if let Some(login) = reviewer.as_str() {
+                            pull_request.requested_reviewers.push(login.to_string());
+                        }
+                    }
+                    respond(StatusCode::CREATED, json!({}))
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::PUT, ["repos", _, _, "pulls", number, "merge"]) => {
+            merge_pull_request(&mut state, number.parse().unwrap_or(0), &body)
+        }
+        // The installation of the GitHub App on every repository is 42.
+        (&Method::GET, ["repos", _, _, "installation"]) => {
+            state.app_tokens.extend(token);
+            respond(StatusCode::OK, json!({ "id": 42 }))
+        }
+        (&Method::POST, ["app", "installations", "42", "access_tokens"]) => {
+            state.app_tokens.extend(token);
+            respond(
+                StatusCode::CREATED,
+                json!({ "token": "ghs_installation", "expires_at": "2099-01-01T00:00:00Z" }),
+            )
+        }
+        (&Method::GET, ["users", login]) => {
+            respond(StatusCode::OK, json!({ "login": login, "name": null }))
+        }
+        _ => not_found(),
+    })
+}
+
+fn branch_name(name: &str) -> &str {
+    name.strip_prefix("refs/heads/").unwrap_or(name)
+}
+
+fn resolve_branch(remote: &Path, branch: &str) -> Option<git2::Oid> {
+    let repo = git2::Repository::open_bare(remote).ok()?;
+    let reference = repo.find_reference(&format!("refs/heads/{branch}")).ok()?;
+    reference.target()
+}
+
+/// Pick up pushes to the head branch of an open Pull Request.
+fn refresh_head(state: &mut State, number: u64) {
+    let remote = state.remote.clone();
+    if let Some(pull_request) = state.pull_requests.get_mut(&number)
+        && pull_request.state == FakeState::Open
+        && let Some(oid) = resolve_branch(&remote, &pull_request.head)
+    {
+        pull_request.head_oid = oid.to_string();
+    }
+}
+
+/// Whether the head of the Pull Request merges cleanly into its base
+fn is_mergeable(state: &State, pull_request: &FakePullRequest) -> bool {
+    let Ok(repo) = git2::Repository::open_bare(&state.remote) else {
+        return false;
+    };
+    let (Some(base), Ok(head)) = (
+        resolve_branch(&state.remote, &pull_request.base),
+        git2::Oid::from_str(&pull_request.head_oid),
+    ) else {
+        return false;
+    };
+    let (Ok(base), Ok(head)) = (repo.find_commit(base), repo.find_commit(head)) else {
+        return false;
+    };
+
+    repo.merge_commits(&base, &head, None)
+        .is_ok_and(|index| !index.has_conflicts())
+}
+
+fn rest_pull_request(state: &State, pull_request: &FakePullRequest) -> Value {
+    let base_sha = resolve_branch(&state.remote, &pull_request.base)
+        .map(|oid| oid.to_string())
+        .unwrap_or_default();
+
+    json!({
+        "url": format!("/pulls/{}", pull_request.number),
+        "id": pull_request.number,
+        "number": pull_request.number,
+        "state": if pull_request.state == FakeState::Open { "open" } else { "closed" },
+        "locked": false,
+        "maintainer_can_modify": false,
+        "title": pull_request.title,
+        "body": pull_request.body,
+        "draft": pull_request.draft,
+        "head": { "ref": pull_request.head, "sha": pull_request.head_oid },
+        "base": { "ref": pull_request.base, "sha": base_sha },
+    })
+}
+
+fn create_pull_request(state: &mut State, body: &Value) -> HttpResponse {
+    let head = branch_name(body["head"].as_str().unwrap_or_default()).to_string();
+    let base = branch_name(body["base"].as_str().unwrap_or_default()).to_string();
+
+    let (Some(head_oid), Some(_)) = (
+        resolve_branch(&state.remote, &head),
+        resolve_branch(&state.remote, &base),
+    ) else {
+        return respond(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            json!({ "message": "Validation Failed" }),
+        );
+    };
+
+    let number = state.pull_requests.keys().next_back().copied().unwrap_or(0) + 1;
+    let pull_request = FakePullRequest {
+        number,
+        title: body["title"].as_str().unwrap_or_default().to_string(),
+        body: body["body"].as_str().unwrap_or_default().to_string(),
+        base,
+        head,
+        state: FakeState::Open,
+        draft: body["draft"].as_bool().unwrap_or(false),
+        head_oid: head_oid.to_string(),
+        merge_commit: None,
+        requested_reviewers: Vec::new(),
+        approved_by: Vec::new(),
+    };
+    let response = rest_pull_request(state, &pull_request);
+    state.pull_requests.insert(number, pull_request);
+
+    respond(StatusCode::CREATED, response)
+}
+
+fn update_pull_request(state: &mut State, number: u64, body: &Value) -> HttpResponse {
+    refresh_head(state, number);
+    let Some(pull_request) = state.pull_requests.get_mut(&number) else {
+        return not_found();
+    };
+
+    if let Some(title) = body["title"].as_str() {
+        pull_request.title = title.to_string();
+    }
+    if let Some(text) = body["body"].as_str() {
+        pull_request.body = text.to_string();
+    }
+    if let Some(base) = body["base"].as_str() {
+        pull_request.base = branch_name(base).to_string();
+    }
+    match body["state"].as_str() {
+        Some("closed") if pull_request.state == FakeState::Open => {
+            pull_request.state = FakeState::Closed
+        }
+        Some("open") if pull_request.state == FakeState::Closed => {
+            pull_request.state = FakeState::Open
+        }
+        _ => (),
+    }
+
+    let pull_request = pull_request.clone();
+    respond(StatusCode::OK, rest_pull_request(state, &pull_request))
+}
+
+fn merge_pull_request(state: &mut State, number: u64, body: &Value) -> HttpResponse {
+    refresh_head(state, number);
+    let Some(pull_request) = state.pull_requests.get(&number).cloned() else {
+        return not_found();
+    };
+
+    if pull_request.state != FakeState::Open {
+        return respond(
+            StatusCode::METHOD_NOT_ALLOWED,
+            json!({ "message": "Pull Request is not open" }),
+        );
+    }
+    if body["sha"]
+        .as_str()
+        .is_some_and(|sha| sha != pull_request.head_oid)
+    {
+        return respond(
+            StatusCode::CONFLICT,
+            json!({ "message": "Head branch was modified" }),
+        );
+    }
+
+    let repo = git2::Repository::open_bare(&state.remote).unwrap();
+    let base_ref = format!("refs/heads/{}", pull_request.base);
+    let base = repo
+        .find_reference(&base_ref)
+        .and_then(|r| r.peel_to_commit())
+        .unwrap();
+    let head = repo
+        .find_commit(git2::Oid::from_str(&pull_request.head_oid).unwrap())
+        .unwrap();
+
+    let mut index = repo.merge_commits(&base, &head, None).unwrap();
+    if index.has_conflicts() {
+        return respond(
+            StatusCode::METHOD_NOT_ALLOWED,
+            json!({ "message": "Pull Request is not mergeable" }),
+        );
+    }
+    let tree = repo.find_tree(index.write_tree_to(&repo).unwrap()).unwrap();
+
+    let message = format!(
+        "{}\n\n{}",
+        body["commit_title"].as_str().unwrap_or(&pull_request.title),
+        body["commit_message"].as_str().unwrap_or_default()
+    );
+    let signature = git2::Signature::now("GitHub", "noreply@github.com").unwrap();
+    let parents = match body["merge_method"].as_str() {
+        Some("merge") => vec![&base, &head],
+        _ => vec![&base],
+    };
+    let merge_oid = repo
+        .commit(
+            Some(&base_ref),
+            &signature,
+            &signature,
+            message.trim_end(),
+            &tree,
+            &parents,
+        )
+        .unwrap();
+
+    let pull_request = state.pull_requests.get_mut(&number).unwrap();
+    pull_request.state = FakeState::Merged;
+    pull_request.merge_commit = Some(merge_oid.to_string());
+
+    respond(
+        StatusCode::OK,
+        json!({
+            "sha": merge_oid.to_string(),
+            "merged": true,
+            "message": "Pull Request successfully merged",
+        }),
+    )
+}
+
+fn graphql(state: &mut State, body: &Value) -> Value {
+    state.graphql_requests += 1;
+    let query = body["query"].as_str().unwrap_or_default();
+    let variables = &body["variables"];
+    let operation = lazy_regex::regex_captures!(r#"(?:query|mutation)\s+(\w+)"#, query)
+        .map(|(_, name)| name)
+        .unwrap_or_default();
+
+    match operation {
+        "PullRequestsQuery" => {
+            let mut repository = serde_json::Map::new();
+            let mut errors = Vec::new();
+            for (alias, number) in aliased_pull_requests(query) {
+                refresh_head(state, number);
+                let pull_request = state.pull_requests.get(&number).map(graphql_pull_request);
+                if pull_request.is_none() {
+                    errors.push(pull_request_not_found(&alias, number));
+                }
+                repository.insert(alias, pull_request.unwrap_or(Value::Null));
+            }
+            repository_response(repository, errors)
+        }
+        "PullRequestMergeabilityQuery" => {
+            let number = variables["number"].as_u64().unwrap_or(0);
+            refresh_head(state, number);
+            let pull_request = state.pull_requests.get(&number).map(|pull_request| {
+                json!({
+                    "id": format!("PR_{}", pull_request.number),
+                    "baseRefName": pull_request.base,
+                    "headRefOid": pull_request.head_oid,
+                    "mergeable": if is_mergeable(state, pull_request) {
+                        "MERGEABLE"
+                    } else {
+                        "CONFLICTING"
+                    },
+                    "mergeCommit": merge_commit(pull_request),
+                    "mergeQueue": null,
+                    "baseRef": { "branchProtectionRule": null },
+                    "statusCheckRollup": null,
+                })
+            });
+            json!({ "data": { "repository": { "pullRequest": pull_request } } })
+        }
+        "PullRequestStatusQuery" => {
+            let mut repository = serde_json::Map::new();
+            let mut errors = Vec::new();
+            for (alias, number) in aliased_pull_requests(query) {
+                refresh_head(state, number);
+                let status = state.pull_requests.get(&number).map(|pull_request| {
+                    let tree = git2::Repository::open_bare(&state.remote)
+                        .ok()
+                        .and_then(|repo| {
+                            let oid = git2::Oid::from_str(&pull_request.head_oid).ok()?;
+                            Some(repo.find_commit(oid).ok()?.tree_id().to_string())
+                        });
+                    json!({
+                        "number": pull_request.number,
+                        "title": pull_request.title,
+                        "body": pull_request.body,
+                        "state": graphql_state(pull_request),
+                        "isDraft": pull_request.draft,
+                        "reviewDecision": review_decision(pull_request),
+                        "mergeable": if is_mergeable(state, pull_request) {
+                            "MERGEABLE"
+                        } else {
+                            "CONFLICTING"
+                        },
+                        "headRefOid": pull_request.head_oid,
+                        "statusCheckRollup": null,
+                        "commits": {
+                            "nodes": tree.map(|oid| vec![json!({ "commit": { "tree": { "oid": oid } } })]),
+                        },
+                    })
+                });
+                if status.is_none() {
+                    errors.push(pull_request_not_found(&alias, number));
+                }
+                repository.insert(alias, status.unwrap_or(Value::Null));
+            }
+            repository_response(repository, errors)
+        }
+        "RepositoryMergeMethodsQuery" => json!({
+            "data": {
+                "repository": {
+                    "mergeCommitAllowed": true,
+                    "rebaseMergeAllowed": true,
+                    "squashMergeAllowed": true,
+                }
+            }
+        }),
+        "SearchQuery" => {
+            let nodes: Vec<Value> = state
+                .pull_requests
+                .values()
+                .filter(|pull_request| pull_request.state == FakeState::Open)
+                .map(|pull_request| {
+                    json!({
+                        "__typename": "PullRequest",
+                        "number": pull_request.number,
+                        "title": pull_request.title,
+                        "url": format!("/pull/{}", pull_request.number),
+                        "reviewDecision": review_decision(pull_request),
+                    })
+                })
+                .collect();
+            json!({ "data": { "search": { "nodes": nodes } } })
+        }
+        _ => json!({
+            "data": null,
+            "errors": [{ "message": format!("{operation} is not supported by the fake") }],
+        }),
+    }
+}
+
+/// The error GitHub reports for an aliased `pullRequest` field whose Pull
+/// Request does not exist
+fn pull_request_not_found(alias: &str, number: u64) -> Value {
+    json!({
+        "type": "NOT_FOUND",
+        "path": ["repository", alias],
+        "message": format!("Could not resolve to a PullRequest with the number of {number}."),
+    })
+}
+
+/// The response to a query of aliased `pullRequest` fields, which has data
+/// next to the errors about missing Pull Requests
+fn repository_response(repository: serde_json::Map<String, Value>, errors: Vec<Value>) -> Value {
+    if errors.is_empty() {
+        json!({ "data": { "repository": repository } })
+    } else {
+        json!({ "data": { "repository": repository }, "errors": errors })
+    }
+}
+
+/// The `alias: pullRequest(number: N)` fields of a query
+fn aliased_pull_requests(query: &str) -> Vec<(String, u64)> {
+    lazy_regex::regex!(r#"(pr\d+): pullRequest\(number: (\d+)\)"#)
+        .captures_iter(query)
+        .map(|captures| (captures[1].to_string(), captures[2].parse().unwrap()))
+        .collect()
+}
+
+fn graphql_state(pull_request: &FakePullRequest) -> &'static str {
+    match pull_request.state {
+        FakeState::Open => "OPEN",
+        FakeState::Closed => "CLOSED",
+        FakeState::Merged => "MERGED",
+    }
+}
+
+fn review_decision(pull_request: &FakePullRequest) -> Value {
+    if !pull_request.approved_by.is_empty() {
+        json!("APPROVED")
+    } else if !pull_request.requested_reviewers.is_empty() {
+        json!("REVIEW_REQUIRED")
+    } else {
+        Value::Null
+    }
+}
+
+fn merge_commit(pull_request: &FakePullRequest) -> Value {
+    match &pull_request.merge_commit {
+        Some(oid) => json!({ "oid": oid }),
+        None => Value::Null,
+    }
+}
+
+fn graphql_pull_request(pull_request: &FakePullRequest) -> Value {
+    json!({
+        "number": pull_request.number,
+        "state": graphql_state(pull_request),
+        "reviewDecision": review_decision(pull_request),
+        "title": pull_request.title,
+        "body": pull_request.body,
+        "baseRefName": pull_request.base,
+        "headRefName": pull_request.head,
+        "mergeCommit": merge_commit(pull_request),
+        "latestOpinionatedReviews": {
+            "nodes": pull_request.approved_by.iter().map(|login| json!({
+                "author": { "__typename": "User", "login": login },
+                "state": "APPROVED",
+            })).collect::<Vec<_>>(),
+        },
+        "reviewRequests": {
+            "nodes": pull_request.requested_reviewers.iter().map(|login| json!({
+                "requestedReviewer": { "__typename": "User", "login": login },
+            })).collect::<Vec<_>>(),
+        },
+    })
+}
@@ -0,0 +1,99 @@
+/*
+ * Helpers shared by the integration tests
+ */
+
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+pub mod canned_server;
+pub mod fake_github;
+
+use std::{path::Path, process::Command};
+
+/// Run a command in `dir`, panicking if it fails, and return its stdout.
+pub fn run(dir: &Path, program: &str, args: &[&str]) -> String {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap_or_else(|e| panic!("Failed to run {program}: {e}"));
+
+    if !output.status.success() {
+        panic!(
+            "{program} {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    run(dir, "git", args)
+}
+
+pub fn jj(dir: &Path, args: &[&str]) -> String {
+    run(dir, "jj", args)
+}
+
+pub fn jj_available() -> bool {
+    Command::new("jj")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Create a bare repository at `remote` and a clone of it at `repo` whose
+/// `main` branch has one commit, pushed to the remote as `origin/main`.
+pub fn create_repo_with_remote(remote: &Path, repo: &Path) {
+    std::fs::create_dir_all(remote).unwrap();
+    std::fs::create_dir_all(repo).unwrap();
+
+    git(remote, &["init", "--bare", "--initial-branch=main"]);
+    git(repo, &["init", "--initial-branch=main"]);
+    git(repo, &["config", "user.name", "Test User"]);
+    git(repo, &["config", "user.email", "test@example.com"]);
+    git(repo, &["remote", "add", "origin", remote.to_str().unwrap()]);
+
+    std::fs::write(repo.join("README.md"), "# Test\n").unwrap();
+    git(repo, &["add", "README.md"]);
+    git(repo, &["commit", "-m", "Initial commit"]);
+    git(repo, &["push", "origin", "main"]);
+}
+
+/// Turn the Git repository into a colocated Jujutsu repository, like
+/// `jj git init --colocate`
+#[cfg(feature = "jj-lib")]
+pub fn init_jujutsu(repo: &Path) {
+    let config = jj_lib::config::StackedConfig::with_defaults();
+    let settings = jj_lib::settings::UserSettings::from_config(config).unwrap();
+    jj_lib::workspace::Workspace::init_external_git(&settings, repo, &repo.join(".git")).unwrap();
+}
+
+/// Configuration for a repository `acme/codez` whose API is served at
+/// `api_url`
+pub fn config(api_url: &str) -> jj_spr::config::Config {
+    jj_spr::config::Config::new(
+        "acme".into(),
+        "codez".into(),
+        "origin".into(),
+        "main".into(),
+        "spr/test/".into(),
+        false,
+        jj_spr::forge::MergeMethod::Squash,
+        false,
+        Vec::new(),
+        jj_spr::config::DEFAULT_GITHUB_HOST.into(),
+        jj_spr::forge::ForgeKind::GitHub,
+        Some(api_url.into()),
+    )
+}
+
+/// The GitHub forge, talking to the API configured in `config`
+pub fn github(config: &jj_spr::config::Config) -> jj_spr::github::GitHub {
+    jj_spr::github::GitHub::new(
+        config.clone(),
+        jj_spr::api::ApiClient::new(reqwest::Client::new(), false),
+    )
+}
@@ -0,0 +1,171 @@
+/*
+ * End-to-end test of diff, land and close against the fake GitHub server
+ */
+
+mod common;
+
+use clap::Parser;
+use common::{create_repo_with_remote, fake_github::FakeGitHub, fake_github::FakeState, git, jj};
+use jj_spr::commands::{
+    close::{CloseOptions, close},
+    diff::{DiffOptions, diff},
+    land::{LandOptions, land},
+};
+use tempfile::TempDir;
+
+fn change_id(repo: &std::path::Path, description: &str) -> String {
+    jj(
+        repo,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            &format!("description(substring:\"{description}\")"),
+            "-T",
+            "change_id",
+        ],
+    )
+}
+
+fn description(repo: &std::path::Path, change_id: &str) -> String {
+    jj(
+        repo,
+        &["log", "--no-graph", "-r", change_id, "-T", "description"],
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_diff_update_land_close() {
+    if !common::jj_available() {
+        // jj is needed to create and rewrite the changes.
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    jj(&repo, &["git", "init", "--colocate"]);
+    jj(
+        &repo,
+        &["config", "set", "--repo", "user.name", "Test User"],
+    );
+    jj(
+        &repo,
+        &["config", "set", "--repo", "user.email", "test@example.com"],
+    );
+
+    // A stack of two changes on top of main
+    std::fs::write(repo.join("a.txt"), "a\n").unwrap();
+    jj(&repo, &["commit", "-m", "Add a"]);
+    std::fs::write(repo.join("b.txt"), "b\n").unwrap();
+    jj(&repo, &["commit", "-m", "Add b"]);
+    let change_a = change_id(&repo, "Add a");
+    let change_b = change_id(&repo, "Add b");
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+    let jj_repo = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    // diff: one Pull Request per change. The upper one is based on the branch
+    // of the lower one, because its parent is not on main.
+    diff(
+        DiffOptions::parse_from(["diff", "--all"]),
+        &jj_repo,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let pull_requests = fake.pull_requests();
+    assert_eq!(pull_requests.len(), 2);
+    assert_eq!(pull_requests[0].title, "Add a");
+    assert_eq!(pull_requests[0].base, "main");
+    assert_eq!(pull_requests[1].title, "Add b");
+    assert_eq!(pull_requests[1].base, pull_requests[0].head);
+    assert!(description(&repo, &change_a).contains(&config.pull_request_url(1)));
+    assert!(description(&repo, &change_b).contains(&config.pull_request_url(2)));
+
+    // diff after amending the lower change updates both Pull Requests.
+    jj(&repo, &["edit", &change_a]);
+    std::fs::write(repo.join("a.txt"), "a, amended\n").unwrap();
+    jj(&repo, &["new", &change_b]);
+
+    // Like every spr invocation, load the repository after jj changed it.
+    let jj_repo = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+
+    diff(
+        DiffOptions::parse_from(["diff", "--all", "-m", "Amend a"]),
+        &jj_repo,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let updated = fake.pull_requests();
+    assert_ne!(updated[0].head_oid, pull_requests[0].head_oid);
+    assert_ne!(updated[1].head_oid, pull_requests[1].head_oid);
+
+    // land the lower change: main gets the squashed commit, and the landed
+    // change is abandoned locally.
+    land(
+        LandOptions::parse_from(["land", "-r", &change_a]),
+        &jj_repo,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let landed = fake.pull_request(1).unwrap();
+    assert_eq!(landed.state, FakeState::Merged);
+    assert_eq!(
+        landed.merge_commit,
+        Some(git(&remote, &["rev-parse", "main"]))
+    );
+    assert_eq!(
+        jj(
+            &repo,
+            &[
+                "log",
+                "--no-graph",
+                "-r",
+                "description(substring:\"Add a\") & mutable()",
+                "-T",
+                "change_id"
+            ],
+        ),
+        ""
+    );
+
+    // diff on the remaining change, now rebased onto the new main
+    diff(
+        DiffOptions::parse_from(["diff", "-r", &change_b, "-m", "Rebase"]),
+        &jj_repo,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+    assert_ne!(fake.pull_request(2).unwrap().head_oid, updated[1].head_oid);
+
+    // close the remaining Pull Request, which removes it from the change.
+    close(
+        CloseOptions::parse_from(["close", "-r", &change_b]),
+        &jj_repo,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(fake.pull_request(2).unwrap().state, FakeState::Closed);
+    assert!(!description(&repo, &change_b).contains("Pull Request:"));
+}
@@ -0,0 +1,208 @@
+/*
+ * Tests for the GitHub forge against the fake GitHub server
+ */
+
+mod common;
+
+use common::{create_repo_with_remote, fake_github::FakeGitHub, fake_github::FakeState, git};
+use jj_spr::{
+    forge::{Forge, MergeMethod, PullRequestRequestReviewers, PullRequestState, ReviewStatus},
+    message::{MessageSection, MessageSectionsMap},
+};
+use tempfile::TempDir;
+
+fn message(title: &str) -> MessageSectionsMap {
+    let mut message = MessageSectionsMap::new();
+    message.insert(MessageSection::Title, title.to_string());
+    message.insert(MessageSection::Summary, format!("Summary of {title}"));
+    message
+}
+
+/// Commit a new file on a new branch off main and push it
+fn push_branch(repo: &std::path::Path, branch: &str, file: &str) -> git2::Oid {
+    git(repo, &["checkout", "-q", "-b", branch, "main"]);
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", file]);
+    git(repo, &["push", "-q", "origin", branch]);
+    git2::Oid::from_str(&git(repo, &["rev-parse", "HEAD"])).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pull_request_lifecycle_with_github_forge() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    let feature_oid = push_branch(&repo, "spr/test/feature", "feature.txt");
+    let other_oid = push_branch(&repo, "spr/test/other", "other.txt");
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let gh = common::github(&config);
+
+    // Fetching the branches of a Pull Request happens in the current
+    // directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    // Create
+    let number = gh
+        .create_pull_request(
+            &message("Add feature"),
+            "main".into(),
+            "spr/test/feature".into(),
+            false,
+        )
+        .await
+        .unwrap();
+    assert_eq!(number, 1);
+
+    let pull_request = gh.get_pull_request(number).await.unwrap();
+    assert_eq!(pull_request.state, PullRequestState::Open);
+    assert_eq!(pull_request.title, "Add feature");
+    assert!(pull_request.base.is_master_branch());
+    assert_eq!(pull_request.head_oid, feature_oid);
+    assert_eq!(
+        pull_request.sections.get(&MessageSection::Summary),
+        Some(&"Summary of Add feature".to_string())
+    );
+    assert_eq!(
+        pull_request.sections.get(&MessageSection::PullRequest),
+        Some(&config.pull_request_url(number))
+    );
+    assert_eq!(pull_request.review_status, None);
+
+    // Review
+    gh.request_reviewers(
+        number,
+        PullRequestRequestReviewers {
+            reviewers: vec!["alice".into()],
+            team_reviewers: vec![],
+        },
+    )
+    .await
+    .unwrap();
+    let pull_request = gh.get_pull_request(number).await.unwrap();
+    assert_eq!(pull_request.review_status, Some(ReviewStatus::Requested));
+
+    fake.approve(number, "alice");
+    let pull_request = gh.get_pull_request(number).await.unwrap();
+    assert_eq!(pull_request.review_status, Some(ReviewStatus::Approved));
+    assert_eq!(
+        pull_request.sections.get(&MessageSection::ReviewedBy),
+        Some(&"alice".to_string())
+    );
+
+    // A Pull Request that does not exist
+    let error = gh.get_pull_requests(&[number, 42]).await.unwrap_err();
+    assert_eq!(error.messages(), &["Pull Request #42 not found"]);
+
+    // Status
+    let statuses = gh.get_pull_request_statuses(&[number, 42]).await.unwrap();
+    assert_eq!(statuses.len(), 1);
+    let status = &statuses[&number];
+    assert_eq!(status.mergeable, Some(true));
+    assert_eq!(status.head_oid, feature_oid);
+    let repository = git2::Repository::open(&repo).unwrap();
+    assert_eq!(
+        status.head_tree_oid,
+        Some(repository.find_commit(feature_oid).unwrap().tree_id())
+    );
+
+    // Merge
+    assert!(
+        gh.get_allowed_merge_methods()
+            .await
+            .unwrap()
+            .contains(&MergeMethod::Squash)
+    );
+    let mergeability = gh.get_pull_request_mergeability(number).await.unwrap();
+    assert_eq!(mergeability.mergeable, Some(true));
+    assert_eq!(mergeability.head_oid, feature_oid);
+
+    let merge_oid = gh
+        .merge_pull_request(
+            number,
+            MergeMethod::Squash,
+            "Add feature",
+            "Summary of Add feature",
+            feature_oid,
+        )
+        .await
+        .unwrap()
+        .expect("merge commit");
+    assert_eq!(git(&remote, &["rev-parse", "main"]), merge_oid.to_string());
+    assert_eq!(fake.pull_request(number).unwrap().state, FakeState::Merged);
+    assert_eq!(
+        gh.get_pull_request(number).await.unwrap().merge_commit,
+        Some(merge_oid)
+    );
+
+    // Transient failures are retried for queries, but not for requests that
+    // may have done something already. Requests rejected because of a rate
+    // limit are always retried.
+    fake.fail_next(502, &[]);
+    assert_eq!(gh.get_pull_request(number).await.unwrap().number, number);
+
+    fake.fail_next(502, &[]);
+    assert!(
+        gh.create_pull_request(
+            &message("Add other"),
+            "main".into(),
+            "spr/test/other".into(),
+            true,
+        )
+        .await
+        .is_err()
+    );
+    assert_eq!(fake.pull_requests().len(), 1);
+
+    fake.fail_next(403, &[("retry-after", "0")]);
+    fake.fail_next(
+        429,
+        &[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "0")],
+    );
+
+    // Update and close
+    let number = gh
+        .create_pull_request(
+            &message("Add other"),
+            "main".into(),
+            "spr/test/other".into(),
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        fake.pull_request(number).unwrap().head_oid,
+        other_oid.to_string()
+    );
+
+    // Both Pull Requests are loaded with a single query.
+    let graphql_requests = fake.graphql_requests();
+    let pull_requests = gh.get_pull_requests(&[1, number]).await.unwrap();
+    assert_eq!(fake.graphql_requests(), graphql_requests + 1);
+    assert_eq!(pull_requests.len(), 2);
+    assert_eq!(pull_requests[&1].merge_commit, Some(merge_oid));
+    assert_eq!(pull_requests[&number].title, "Add other");
+    assert_eq!(pull_requests[&number].head_oid, other_oid);
+    assert!(gh.get_pull_requests(&[number, 42]).await.is_err());
+
+    let mut updates = jj_spr::forge::PullRequestUpdate::default();
+    updates.update_message(
+        &gh.get_pull_request(number).await.unwrap(),
+        &message("Add another thing"),
+    );
+    gh.update_pull_request(number, updates).await.unwrap();
+    assert_eq!(
+        fake.pull_request(number).unwrap().title,
+        "Add another thing"
+    );
+
+    gh.close_pull_request(number).await.unwrap();
+    assert_eq!(
+        gh.get_pull_request(number).await.unwrap().state,
+        PullRequestState::Closed
+    );
+}
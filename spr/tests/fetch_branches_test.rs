@@ -0,0 +1,85 @@
+/*
+ * Tests for fetching the branches of Pull Requests from the fake GitHub
+ * server's remote
+ */
+
+mod common;
+
+use common::{create_repo_with_remote, fake_github::FakeGitHub, git};
+use jj_spr::{
+    forge::Forge,
+    message::{MessageSection, MessageSectionsMap},
+};
+use tempfile::TempDir;
+
+/// Commit a new file on a new branch off main and push it
+fn push_branch(repo: &std::path::Path, branch: &str, file: &str) {
+    git(repo, &["checkout", "-q", "-b", branch, "main"]);
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", file]);
+    git(repo, &["push", "-q", "origin", branch]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_fetch_with_deleted_branch() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    push_branch(&repo, "spr/test/deleted", "deleted.txt");
+    push_branch(&repo, "spr/test/updated", "updated.txt");
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let gh = common::github(&config);
+
+    // Fetching the branches of a Pull Request happens in the current
+    // directory.
+    std::env::set_current_dir(&repo).unwrap();
+    // Git's messages are translated for users who prefer another language.
+    // SAFETY: This is the only test in this file, and nothing else reads the
+    // environment while it is changed.
+    unsafe {
+        std::env::set_var("LANGUAGE", "de");
+        std::env::set_var("LC_ALL", "C.UTF-8");
+    }
+
+    for branch in ["spr/test/deleted", "spr/test/updated"] {
+        let mut message = MessageSectionsMap::new();
+        message.insert(MessageSection::Title, branch.to_string());
+        gh.create_pull_request(&message, "main".into(), branch.into(), false)
+            .await
+            .unwrap();
+    }
+
+    // One branch is deleted on the remote, the other one moves on without
+    // the local repository knowing.
+    git(&remote, &["branch", "-q", "-D", "spr/test/deleted"]);
+    std::fs::write(repo.join("more.txt"), "more\n").unwrap();
+    git(&repo, &["add", "more.txt"]);
+    git(&repo, &["commit", "-q", "-m", "more"]);
+    git(
+        &repo,
+        &[
+            "push",
+            "-q",
+            remote.to_str().unwrap(),
+            "HEAD:refs/heads/spr/test/updated",
+        ],
+    );
+    let updated_oid = git2::Oid::from_str(&git(&repo, &["rev-parse", "HEAD"])).unwrap();
+
+    // The missing branch does not keep the other one from being fetched.
+    let pull_requests = gh.get_pull_requests(&[1, 2]).await.unwrap();
+    assert!(pull_requests[&1].head_oid.is_zero());
+    assert_eq!(pull_requests[&2].head_oid, updated_oid);
+    assert_eq!(
+        git(
+            &repo,
+            &["rev-parse", "refs/remotes/origin/spr/test/updated"]
+        ),
+        updated_oid.to_string()
+    );
+}
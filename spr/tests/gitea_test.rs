@@ -0,0 +1,57 @@
+/*
+ * Tests for the Gitea forge against a server with canned responses
+ */
+
+mod common;
+
+use jj_spr::{
+    config::Config,
+    forge::{Forge, ForgeKind, PullRequestState},
+    gitea::Gitea,
+};
+
+const PULL_REQUEST: &str = r#"{
+    "number": 1,
+    "title": "WIP: Add feature",
+    "body": "The summary",
+    "state": "open",
+    "mergeable": true,
+    "head": {"ref": "spr/test/add-feature", "sha": "0123456789abcdef0123456789abcdef01234567"},
+    "base": {"ref": "main", "sha": "89abcdef0123456789abcdef0123456789abcdef"}
+}"#;
+
+#[tokio::test]
+async fn test_pull_request_statuses() {
+    let url = common::canned_server::start(vec![
+        ("/pulls/1", 200, PULL_REQUEST),
+        ("/pulls/1/reviews", 200, "[]"),
+        ("/status", 200, r#"{"statuses": []}"#),
+        ("/pulls/3", 500, r#"{"message":"Internal Server Error"}"#),
+    ])
+    .await;
+    let config = Config {
+        forge: ForgeKind::Gitea,
+        ..common::config(&url)
+    };
+    let gitea = Gitea::new(config, reqwest::Client::new());
+
+    // A Pull Request that does not exist is left out.
+    let statuses = gitea.get_pull_request_statuses(&[1, 2]).await.unwrap();
+    assert_eq!(statuses.len(), 1);
+    let status = &statuses[&1];
+    assert_eq!(status.title, "Add feature");
+    assert_eq!(status.body, "The summary");
+    assert_eq!(status.state, PullRequestState::Open);
+    assert!(status.is_draft);
+
+    // Any other failure is reported.
+    let error = gitea.get_pull_request_statuses(&[1, 3]).await.unwrap_err();
+    assert!(
+        error
+            .messages()
+            .iter()
+            .any(|message| message.contains("Internal Server Error")),
+        "{:?}",
+        error.messages()
+    );
+}
@@ -0,0 +1,72 @@
+/*
+ * Tests for authenticating as a GitHub App against the fake GitHub server
+ */
+
+mod common;
+
+use common::fake_github::FakeGitHub;
+use jj_spr::config::{AuthTokenSource, Settings, ValueSource, get_auth_token_with_source};
+use tempfile::TempDir;
+
+/// A key pair made for these tests, as `openssl genrsa` would create it
+const PRIVATE_KEY_FILE: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/github_app.pem");
+const PUBLIC_KEY: &[u8] = include_bytes!("fixtures/github_app.pub.pem");
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_github_app_installation_token() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let fake = FakeGitHub::start(temp_dir.path()).await;
+    let mut settings = Settings::default();
+    settings.set("githubAppId", "1234".to_string(), ValueSource::Git);
+    settings.set(
+        "githubAppPrivateKeyFile",
+        PRIVATE_KEY_FILE.to_string(),
+        ValueSource::Git,
+    );
+
+    // The installation is looked up on the repository, and its token
+    // requested with the App's JSON Web Token.
+    let source = get_auth_token_with_source(
+        &settings,
+        None,
+        "github.com",
+        fake.url(),
+        Some(("acme", "codez")),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        source,
+        Some(AuthTokenSource::GitHubApp {
+            app_id: "1234".to_string(),
+            installation_id: 42,
+            token: "ghs_installation".to_string(),
+        })
+    );
+    assert_eq!(
+        source.unwrap().to_string(),
+        "GitHub App 1234 (installation 42)"
+    );
+
+    let app_tokens = fake.app_tokens();
+    assert_eq!(app_tokens.len(), 2);
+    let key = jsonwebtoken::DecodingKey::from_rsa_pem(PUBLIC_KEY).unwrap();
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+    validation.set_issuer(&["1234"]);
+    for token in &app_tokens {
+        jsonwebtoken::decode::<serde_json::Value>(token, &key, &validation).unwrap();
+    }
+
+    // A configured installation is used without looking it up.
+    settings.set(
+        "githubAppInstallationId",
+        "42".to_string(),
+        ValueSource::Git,
+    );
+    let source = get_auth_token_with_source(&settings, None, "github.com", fake.url(), None)
+        .await
+        .unwrap();
+    assert_eq!(source.unwrap().token(), "ghs_installation");
+    assert_eq!(fake.app_tokens().len(), 3);
+}
@@ -0,0 +1,64 @@
+/*
+ * Tests for the GitLab forge against a server with canned responses
+ */
+
+mod common;
+
+use jj_spr::{
+    config::Config,
+    forge::{Forge, ForgeKind, PullRequestState},
+    gitlab::GitLab,
+};
+
+const MERGE_REQUEST: &str = r#"{
+    "iid": 1,
+    "title": "Draft: Add feature",
+    "description": "The summary",
+    "state": "opened",
+    "source_branch": "spr/test/add-feature",
+    "target_branch": "main",
+    "sha": "0123456789abcdef0123456789abcdef01234567",
+    "draft": true,
+    "detailed_merge_status": "mergeable"
+}"#;
+
+const APPROVALS: &str = r#"{"approved": false, "approvals_left": 1}"#;
+
+#[tokio::test]
+async fn test_merge_request_statuses() {
+    let url = common::canned_server::start(vec![
+        ("/merge_requests/1", 200, MERGE_REQUEST),
+        ("/merge_requests/1/approvals", 200, APPROVALS),
+        (
+            "/merge_requests/3",
+            500,
+            r#"{"message":"500 Internal Server Error"}"#,
+        ),
+    ])
+    .await;
+    let config = Config {
+        forge: ForgeKind::GitLab,
+        ..common::config(&url)
+    };
+    let gitlab = GitLab::new(config, reqwest::Client::new());
+
+    // A Merge Request that does not exist is left out.
+    let statuses = gitlab.get_pull_request_statuses(&[1, 2]).await.unwrap();
+    assert_eq!(statuses.len(), 1);
+    let status = &statuses[&1];
+    assert_eq!(status.title, "Add feature");
+    assert_eq!(status.body, "The summary");
+    assert_eq!(status.state, PullRequestState::Open);
+    assert!(status.is_draft);
+
+    // Any other failure is reported.
+    let error = gitlab.get_pull_request_statuses(&[1, 3]).await.unwrap_err();
+    assert!(
+        error
+            .messages()
+            .iter()
+            .any(|message| message.contains("500 Internal Server Error")),
+        "{:?}",
+        error.messages()
+    );
+}
@@ -0,0 +1,104 @@
+/*
+ * Tests for landing a Pull Request with a base branch against the fake GitHub
+ * server
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use std::path::Path;
+
+use clap::Parser;
+use common::{
+    create_repo_with_remote, fake_github::FakeGitHub, fake_github::FakeState, git, init_jujutsu,
+};
+use jj_spr::{
+    commands::{
+        land::{LandOptions, land},
+        patch::{PatchOptions, patch},
+    },
+    forge::Forge,
+    message::{MessageSection, MessageSectionsMap},
+};
+use tempfile::TempDir;
+
+/// Commit a new file on the current branch and push the branch
+fn commit_and_push(repo: &Path, branch: &str, file: &str) -> String {
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", file]);
+    git(repo, &["push", "-q", "origin", branch]);
+    git(repo, &["rev-parse", "HEAD"])
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_land_pull_request_with_landed_base_branch() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    // A Pull Request on a base branch, whose changes have landed on main
+    // since, followed by another commit.
+    git(&repo, &["checkout", "-q", "-b", "spr/test/main.base"]);
+    commit_and_push(&repo, "spr/test/main.base", "base.txt");
+    git(&repo, &["checkout", "-q", "-b", "spr/test/feature"]);
+    commit_and_push(&repo, "spr/test/feature", "feature.txt");
+    git(&repo, &["checkout", "-q", "main"]);
+    commit_and_push(&repo, "main", "base.txt");
+    let master_oid = commit_and_push(&repo, "main", "other.txt");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+    let mut message = MessageSectionsMap::new();
+    message.insert(MessageSection::Title, "Add feature".to_string());
+    let number = gh
+        .create_pull_request(
+            &message,
+            "spr/test/main.base".into(),
+            "spr/test/feature".into(),
+            false,
+        )
+        .await
+        .unwrap();
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    patch(
+        PatchOptions::parse_from(["patch", "--no-checkout", &number.to_string()]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    land(
+        LandOptions::parse_from(["land", "--cherry-pick", "-r", "PR-1"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    // Before it was merged, main was merged into the Pull Request, so that
+    // it only shows its own changes.
+    let landed = fake.pull_request(number).unwrap();
+    assert_eq!(landed.state, FakeState::Merged);
+    assert_eq!(landed.base, "main");
+    assert_eq!(
+        git(&remote, &["merge-base", &master_oid, &landed.head_oid]),
+        master_oid
+    );
+    assert_eq!(
+        git(&remote, &["ls-tree", "--name-only", "main"]),
+        "README.md\nbase.txt\nfeature.txt\nother.txt"
+    );
+}
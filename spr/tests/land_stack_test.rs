@@ -0,0 +1,101 @@
+/*
+ * Tests for landing a stack of Pull Requests against the fake GitHub server
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use std::path::Path;
+
+use clap::Parser;
+use common::{
+    create_repo_with_remote, fake_github::FakeGitHub, fake_github::FakeState, git, init_jujutsu,
+};
+use jj_spr::commands::{
+    diff::{DiffOptions, diff},
+    land::{LandOptions, land},
+};
+use tempfile::TempDir;
+
+/// Commit a new file on the current branch
+fn commit(repo: &Path, file: &str, message: &str) {
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", message]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_land_stack_range() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    git(&repo, &["checkout", "-q", "-b", "stack"]);
+    commit(&repo, "first.txt", "Add first");
+    commit(&repo, "second.txt", "Add second");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    diff(
+        DiffOptions::parse_from(["diff", "--all", "-r", "stack"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let pull_requests = fake.pull_requests();
+    assert_eq!(pull_requests.len(), 2);
+    let (first, second) = (&pull_requests[0], &pull_requests[1]);
+    assert_eq!(second.base, first.head);
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    land(
+        LandOptions::parse_from(["land", "--all", "-r", "stack"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    // Both are merged, the second one after it was retargeted to main.
+    let landed_first = fake.pull_request(1).unwrap();
+    let landed_second = fake.pull_request(2).unwrap();
+    assert_eq!(landed_first.state, FakeState::Merged);
+    assert_eq!(landed_second.state, FakeState::Merged);
+    assert_eq!(landed_second.base, "main");
+    assert_eq!(
+        landed_second.merge_commit,
+        Some(git(&remote, &["rev-parse", "main"]))
+    );
+    assert_eq!(
+        git(&remote, &["ls-tree", "--name-only", "main"]),
+        "README.md\nfirst.txt\nsecond.txt"
+    );
+
+    // The Pull Request branches are gone.
+    assert_eq!(
+        git(&remote, &["branch", "--list", "--format=%(refname:short)"]),
+        "main"
+    );
+
+    // Both changes have been abandoned locally.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    assert!(
+        jj.get_prepared_commits_for_revset(&config, "trunk()..stack")
+            .unwrap()
+            .is_empty()
+    );
+}
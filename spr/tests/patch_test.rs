@@ -0,0 +1,174 @@
+/*
+ * Tests for patch against the fake GitHub server
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use std::{path::Path, sync::Mutex};
+
+use clap::Parser;
+use common::{create_repo_with_remote, fake_github::FakeGitHub, git, init_jujutsu};
+use jj_spr::{
+    commands::patch::{PatchOptions, patch},
+    forge::Forge,
+    message::{MessageSection, MessageSectionsMap},
+};
+use tempfile::TempDir;
+
+/// The tests change the current directory of the process, so they must not
+/// run at the same time.
+static CURRENT_DIR: Mutex<()> = Mutex::new(());
+
+/// Commit a new file on the current branch and push the branch
+fn commit_and_push(repo: &Path, branch: &str, file: &str) -> git2::Oid {
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", file]);
+    git(repo, &["push", "-q", "origin", branch]);
+    git2::Oid::from_str(&git(repo, &["rev-parse", "HEAD"])).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[allow(clippy::await_holding_lock)]
+async fn test_patch_pull_request_with_base_branch() {
+    let _current_dir = CURRENT_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+    let initial_oid = git2::Oid::from_str(&git(&repo, &["rev-parse", "HEAD"])).unwrap();
+
+    // A Pull Request whose base is a branch on top of main, which has moved
+    // on since.
+    git(&repo, &["checkout", "-q", "-b", "spr/test/main.base"]);
+    let base_oid = commit_and_push(&repo, "spr/test/main.base", "base.txt");
+    git(&repo, &["checkout", "-q", "-b", "spr/test/feature"]);
+    let head_oid = commit_and_push(&repo, "spr/test/feature", "feature.txt");
+    git(&repo, &["checkout", "-q", "main"]);
+    commit_and_push(&repo, "main", "other.txt");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+    let mut message = MessageSectionsMap::new();
+    message.insert(MessageSection::Title, "Add feature".to_string());
+    message.insert(MessageSection::Summary, "The summary".to_string());
+    let number = gh
+        .create_pull_request(
+            &message,
+            "spr/test/main.base".into(),
+            "spr/test/feature".into(),
+            false,
+        )
+        .await
+        .unwrap();
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    patch(
+        PatchOptions::parse_from(["patch", &number.to_string()]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    // The new change has the contents and description of the Pull Request,
+    // and sits on a commit with the changes of the base branch, which is
+    // based on where the Pull Request branched off main.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    let patched = jj
+        .get_prepared_commit_for_revision(&config, "PR-1")
+        .unwrap();
+    assert_eq!(
+        patched.message.get(&MessageSection::Title),
+        Some(&"Add feature".to_string())
+    );
+    assert_eq!(
+        patched.message.get(&MessageSection::Summary),
+        Some(&"The summary".to_string())
+    );
+    assert_eq!(patched.pull_request_number, Some(number));
+
+    let git_repo = &jj.git_repo;
+    let patched_commit = git_repo.find_commit(patched.oid).unwrap();
+    assert_eq!(
+        patched_commit.tree_id(),
+        git_repo.find_commit(head_oid).unwrap().tree_id()
+    );
+    let base_commit = git_repo.find_commit(patched.parent_oid).unwrap();
+    assert_eq!(
+        base_commit.tree_id(),
+        git_repo.find_commit(base_oid).unwrap().tree_id()
+    );
+    assert!(
+        base_commit
+            .message()
+            .unwrap()
+            .starts_with("[spr] Changes on base branch of Pull Request #1")
+    );
+    assert_eq!(base_commit.parent_ids().collect::<Vec<_>>(), [initial_oid]);
+
+    // The working copy is a new change on top of the patched one.
+    let working_copy = jj.get_prepared_commit_for_revision(&config, "@").unwrap();
+    assert_eq!(working_copy.parent_oid, patched.oid);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[allow(clippy::await_holding_lock)]
+async fn test_patch_pull_request_against_moved_master() {
+    let _current_dir = CURRENT_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+    let initial_oid = git2::Oid::from_str(&git(&repo, &["rev-parse", "HEAD"])).unwrap();
+
+    // A Pull Request against main, which has moved on since.
+    git(&repo, &["checkout", "-q", "-b", "spr/test/feature"]);
+    let head_oid = commit_and_push(&repo, "spr/test/feature", "feature.txt");
+    git(&repo, &["checkout", "-q", "main"]);
+    commit_and_push(&repo, "main", "other.txt");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+    let mut message = MessageSectionsMap::new();
+    message.insert(MessageSection::Title, "Add feature".to_string());
+    let number = gh
+        .create_pull_request(&message, "main".into(), "spr/test/feature".into(), false)
+        .await
+        .unwrap();
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    patch(
+        PatchOptions::parse_from(["patch", "--no-checkout", &number.to_string()]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    // The new change sits directly on the commit the Pull Request branched
+    // off, so it does not revert what has landed on main since.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    let patched = jj
+        .get_prepared_commit_for_revision(&config, "PR-1")
+        .unwrap();
+    assert_eq!(patched.parent_oid, initial_oid);
+    let git_repo = &jj.git_repo;
+    assert_eq!(
+        git_repo.find_commit(patched.oid).unwrap().tree_id(),
+        git_repo.find_commit(head_oid).unwrap().tree_id()
+    );
+}
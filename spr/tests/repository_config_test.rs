@@ -0,0 +1,55 @@
+/*
+ * Tests for the team settings in `.spr.toml`
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use common::{create_repo_with_remote, git, init_jujutsu};
+use jj_spr::{
+    config::{Settings, ValueSource},
+    message::MessageSection,
+};
+use tempfile::TempDir;
+
+#[test]
+fn test_settings_from_trunk() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    std::fs::write(
+        repo.join(".spr.toml"),
+        "requireApproval = true\nrequiredSections = ['Summary']\n",
+    )
+    .unwrap();
+    git(&repo, &["add", ".spr.toml"]);
+    git(&repo, &["commit", "-q", "-m", "Add spr settings"]);
+    git(&repo, &["push", "-q", "origin", "main"]);
+
+    // A local change to the file is not in effect before it has landed.
+    std::fs::write(repo.join(".spr.toml"), "requireApproval = false\n").unwrap();
+    git(&repo, &["commit", "-q", "-a", "-m", "Relax spr settings"]);
+    init_jujutsu(&repo);
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    let git_config = jj.git_repo.config().unwrap();
+    let mut settings = Settings::load(&jj, &git_config).unwrap();
+    assert_eq!(
+        settings.value("requireApproval"),
+        Some(("true".to_string(), ValueSource::RepoFile))
+    );
+
+    settings.set(
+        "githubRepository",
+        "acme/codez".into(),
+        ValueSource::CliFlag,
+    );
+    settings.set("branchPrefix", "spr/test/".into(), ValueSource::CliFlag);
+    let config = settings.to_config(&jj.git_repo).unwrap();
+    assert!(config.require_approval);
+    assert_eq!(config.required_sections, [MessageSection::Summary]);
+}
@@ -0,0 +1,94 @@
+/*
+ * Tests for the stack navigation in Pull Request descriptions, against the
+ * fake GitHub server
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use std::path::Path;
+
+use clap::Parser;
+use common::{create_repo_with_remote, fake_github::FakeGitHub, git, init_jujutsu};
+use jj_spr::commands::diff::{DiffOptions, diff};
+use tempfile::TempDir;
+
+/// Commit a new file on the current branch
+fn commit(repo: &Path, file: &str, message: &str) {
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", message]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_diff_single_commit_refreshes_stack() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    git(&repo, &["checkout", "-q", "-b", "stack"]);
+    commit(&repo, "first.txt", "Add first");
+    commit(&repo, "second.txt", "Add second");
+    commit(&repo, "third.txt", "Add third");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    // The bottom two commits first...
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    diff(
+        DiffOptions::parse_from(["diff", "--all", "-r", "stack-"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let pull_requests = fake.pull_requests();
+    assert_eq!(pull_requests.len(), 2);
+    assert!(
+        pull_requests[0]
+            .body
+            .contains("- **#1** 👈 this Pull Request")
+    );
+    assert!(pull_requests[0].body.contains("- #2"));
+
+    // ...then only the top one, which joins the stack of the other two.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    diff(
+        DiffOptions::parse_from(["diff", "-r", "stack"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    let pull_requests = fake.pull_requests();
+    assert_eq!(pull_requests.len(), 3);
+    for pull_request in &pull_requests {
+        let stack: Vec<_> = pull_request
+            .body
+            .lines()
+            .filter(|line| line.starts_with("- "))
+            .collect();
+        assert_eq!(stack.len(), 3, "{}", pull_request.body);
+        assert!(stack[0].contains("#3"));
+        assert!(stack[1].contains("#2"));
+        assert!(stack[2].contains("#1"));
+        assert!(
+            stack
+                .iter()
+                .any(|line| line.contains(&format!("**#{}** 👈", pull_request.number)))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Tests for sync against the fake GitHub server
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use std::path::Path;
+
+use clap::Parser;
+use common::{create_repo_with_remote, fake_github::FakeGitHub, git, init_jujutsu};
+use jj_spr::{
+    commands::{
+        diff::{DiffOptions, diff},
+        sync::{SyncOptions, sync},
+    },
+    forge::{Forge, MergeMethod},
+};
+use tempfile::TempDir;
+
+/// Commit a new file on the current branch
+fn commit(repo: &Path, file: &str, message: &str) {
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", message]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sync_with_open_and_merged_pull_requests() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+
+    git(&repo, &["checkout", "-q", "-b", "stack"]);
+    commit(&repo, "first.txt", "Add first");
+    commit(&repo, "second.txt", "Add second");
+    init_jujutsu(&repo);
+    // mine() needs to know who the user is.
+    std::fs::write(
+        repo.join(".jj/repo/config.toml"),
+        "[user]\nname = 'Test User'\nemail = 'test@example.com'\n",
+    )
+    .unwrap();
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    diff(
+        DiffOptions::parse_from(["diff", "--all", "-r", "stack"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    // The first Pull Request gets merged on GitHub, the second stays open.
+    let first = fake.pull_request(1).unwrap();
+    gh.merge_pull_request(
+        1,
+        MergeMethod::Squash,
+        "Add first",
+        "",
+        git2::Oid::from_str(&first.head_oid).unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // The commits spr pushed to the Pull Request branches are the user's,
+    // too, but they are immutable and must be left alone.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    sync(SyncOptions::parse_from(["sync"]), &jj, &mut gh, &config)
+        .await
+        .unwrap();
+
+    // The merged change is gone, and the open one sits on the new trunk.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    let remaining = jj
+        .get_prepared_commits_for_revset(&config, "mine() & mutable() & ~::trunk() & ~empty()")
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].pull_request_number, Some(2));
+    assert_eq!(
+        remaining[0].parent_oid.to_string(),
+        git(&remote, &["rev-parse", "main"])
+    );
+}
@@ -0,0 +1,173 @@
+/*
+ * Tests for diff on stacks that branch or are moved, against the fake GitHub
+ * server
+ */
+
+// The repository is set up with jj-lib, so this does not need the jj binary.
+#![cfg(feature = "jj-lib")]
+
+mod common;
+
+use std::{path::Path, sync::Mutex};
+
+use clap::Parser;
+use common::{create_repo_with_remote, fake_github::FakeGitHub, git, init_jujutsu};
+use jj_spr::commands::diff::{DiffOptions, diff};
+use tempfile::TempDir;
+
+/// The tests change the current directory of the process, so they must not
+/// run at the same time.
+static CURRENT_DIR: Mutex<()> = Mutex::new(());
+
+/// Commit a new file on the current branch
+fn commit(repo: &Path, file: &str, message: &str) {
+    std::fs::write(repo.join(file), format!("{file}\n")).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", message]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[allow(clippy::await_holding_lock)]
+async fn test_diff_fan_out_on_foundation_change() {
+    let _current_dir = CURRENT_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    // A foundation change with two follow-ups on top of it
+    git(&repo, &["checkout", "-q", "-b", "first"]);
+    commit(&repo, "foundation.txt", "Add foundation");
+    commit(&repo, "first.txt", "Add first");
+    git(&repo, &["checkout", "-q", "-b", "second", "HEAD^"]);
+    commit(&repo, "second.txt", "Add second");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+
+    diff(
+        DiffOptions::parse_from(["diff", "-r", "trunk()..(first | second)"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    // The follow-ups are both based on the foundation's Pull Request branch,
+    // and their branches contain nothing but their own change on top of it.
+    let pull_requests = fake.pull_requests();
+    assert_eq!(pull_requests.len(), 3);
+    let foundation = &pull_requests[0];
+    assert_eq!(foundation.title, "Add foundation");
+    assert_eq!(foundation.base, "main");
+    for follow_up in &pull_requests[1..] {
+        assert_eq!(follow_up.base, foundation.head);
+        assert_eq!(
+            git(&remote, &["rev-parse", &format!("{}^", follow_up.head_oid)]),
+            foundation.head_oid
+        );
+    }
+    let mut titles: Vec<_> = pull_requests[1..].iter().map(|pr| &pr.title).collect();
+    titles.sort();
+    assert_eq!(titles, ["Add first", "Add second"]);
+
+    // The Pull Request links were added to all three changes.
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    let prepared_commits = jj
+        .get_prepared_commits_for_revset(&config, "trunk()..(first | second)")
+        .unwrap();
+    assert_eq!(prepared_commits.len(), 3);
+    assert_eq!(prepared_commits[0].pull_request_number, Some(1));
+    assert!(
+        prepared_commits[1..]
+            .iter()
+            .all(|pc| pc.pull_request_number.is_some())
+    );
+
+    // Nothing changed locally, so nothing gets pushed again.
+    diff(
+        DiffOptions::parse_from(["diff", "-r", "trunk()..(first | second)", "-m", "Update"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+
+    for (before, after) in pull_requests.iter().zip(fake.pull_requests()) {
+        assert_eq!(before.head_oid, after.head_oid);
+        assert_eq!(before.base, after.base);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[allow(clippy::await_holding_lock)]
+async fn test_diff_refuses_to_push_onto_parent_pull_request() {
+    let _current_dir = CURRENT_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    create_repo_with_remote(&remote, &repo);
+    // The commands run git in the current directory.
+    std::env::set_current_dir(&repo).unwrap();
+
+    // A stack of two changes, and another change on main
+    git(&repo, &["checkout", "-q", "-b", "stack"]);
+    commit(&repo, "foundation.txt", "Add foundation");
+    commit(&repo, "first.txt", "Add first");
+    git(&repo, &["checkout", "-q", "-b", "other", "main"]);
+    commit(&repo, "other.txt", "Add other");
+    init_jujutsu(&repo);
+
+    let fake = FakeGitHub::start(&remote).await;
+    let config = common::config(fake.url());
+    let mut gh = common::github(&config);
+
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    diff(
+        DiffOptions::parse_from(["diff", "--all", "-r", "stack"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await
+    .unwrap();
+    let foundation = fake.pull_request(1).unwrap();
+    assert_eq!(fake.pull_request(2).unwrap().base, foundation.head);
+
+    // The top change moves away from the foundation. Its Pull Request is
+    // still based on the foundation's branch, which must not be touched.
+    jj.rebase_roots_onto("stack", "other").unwrap();
+    let jj = jj_spr::jj::Jujutsu::new(git2::Repository::open(&repo).unwrap()).unwrap();
+    let result = diff(
+        DiffOptions::parse_from(["diff", "-r", "stack", "-m", "Move"]),
+        &jj,
+        &mut gh,
+        &config,
+    )
+    .await;
+
+    let error = result.unwrap_err();
+    assert!(
+        error
+            .messages()
+            .iter()
+            .any(|message| message.contains(&format!(
+                "based on '{}', the branch of another",
+                foundation.head
+            ))),
+        "{:?}",
+        error.messages()
+    );
+    assert_eq!(
+        git(&remote, &["rev-parse", &foundation.head]),
+        foundation.head_oid
+    );
+    assert_eq!(fake.pull_request(1).unwrap().head_oid, foundation.head_oid);
+}